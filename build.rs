@@ -1,7 +1,15 @@
 use std::env;
+use std::fs;
+use std::path::PathBuf;
 
 fn main() {
     if Ok("thumbv7m-none-eabi".to_owned()) == env::var("TARGET") {
+        // link.x of cortex-m-rt includes the memory.x found on the search path
+        let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+        fs::copy("memory.x", out.join("memory.x")).unwrap();
+        println!("cargo:rustc-link-search={}", out.display());
+        println!("cargo:rerun-if-changed=memory.x");
+
         println!("cargo:rustc-link-arg-bins=--nmagic");
         println!("cargo:rustc-link-arg-bins=-Tlink.x");
         println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
@@ -0,0 +1,6 @@
+MEMORY
+{
+  /* STM32F103C8, the last 4K of the flash are kept for the config storage */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 60K
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K
+}
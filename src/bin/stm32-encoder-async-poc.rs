@@ -11,17 +11,19 @@ use heapless::Vec;
 
 use reset_ctrl::device::Device;
 use reset_ctrl::handler::{EncoderHandler, MidiAbs};
-use reset_ctrl::output::{MidiMsgCc, OutputData, OutputType, StdOut, UsbOut, CHANNEL};
+use reset_ctrl::output::{
+    MidiMsgCc, OutputData, OutputType, OverflowPolicy, StdOut, UsbOut, CHANNEL, USB_QUEUE_DEPTH_MAX,
+};
 use reset_ctrl::ui::backend::Stm32Backend;
 use reset_ctrl::ui::input::{Encoder, EncoderDirection};
 use reset_ctrl::ui::Backend;
-use reset_ctrl::ui::{Input, InputType};
+use reset_ctrl::ui::{Address, Input, InputType};
 
 use {defmt_rtt as _, panic_probe as _};
 
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) {
-    let mut encoder = Encoder::new();
+    let mut encoder = Encoder::new(Address::new(0, 0, 0));
     let mut handler = EncoderHandler::MidiAbs(MidiAbs {
         channel: 0,
         control: 4,
@@ -36,10 +38,13 @@ async fn main(_spawner: Spawner) {
 
     let mut outputs: Vec<OutputType, 2> = Vec::new();
     outputs.push(OutputType::StdOut(StdOut {}));
-    outputs.push(OutputType::UsbOut(UsbOut {}));
+    outputs.push(OutputType::UsbOut(UsbOut::new(
+        USB_QUEUE_DEPTH_MAX,
+        OverflowPolicy::CoalesceCc,
+    )));
 
     // setup
-    device.add_input(input);
+    device.add_input(input).expect("no input slot left");
     device.init_inputs(&mut b);
 
     // operation
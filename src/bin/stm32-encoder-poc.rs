@@ -14,13 +14,13 @@ use reset_ctrl::output::{MidiMsgCc, OutputData, OutputType, StdOut};
 use reset_ctrl::ui::backend::Stm32Backend;
 use reset_ctrl::ui::input::{Encoder, EncoderDirection};
 use reset_ctrl::ui::Backend;
-use reset_ctrl::ui::{Input, InputType};
+use reset_ctrl::ui::{Address, Input, InputType};
 
 use {defmt_rtt as _, panic_probe as _};
 
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) -> ! {
-    let mut encoder = Encoder::new();
+    let mut encoder = Encoder::new(Address::new(0, 0, 0));
     let mut handler = EncoderHandler::MidiAbs(MidiAbs {
         channel: 0,
         control: 4,
@@ -35,7 +35,7 @@ async fn main(_spawner: Spawner) -> ! {
     outputs.push(OutputType::StdOut(StdOut {}));
 
     // setup
-    device.add_input(input);
+    device.add_input(input).expect("no input slot left");
 
     let mut b = Stm32Backend::new().await;
     device.init_inputs(&mut b);
@@ -3,25 +3,33 @@
 
 use defmt::{panic, *};
 use embassy_executor::Spawner;
-use embassy_time::Timer;
 
 use heapless::Vec;
 
 use reset_ctrl::device::Device;
-use reset_ctrl::handler::{EncoderHandler, MidiAbs, PotMidiAbs, PotentiometerHandler};
-use reset_ctrl::output::{MidiMsgCc, OutputData, OutputType, StdOut, UsbOut, CHANNEL};
-use reset_ctrl::ui::backend::Stm32Backend;
+use reset_ctrl::handler::{EncoderHandler, MidiAbs, PotMidiAbs, PotentiometerHandler, Takeover};
+use reset_ctrl::output::{
+    MidiMsgCc, OutputData, OutputType, OverflowPolicy, StdOut, UsbOut, CHANNEL, USB_QUEUE_DEPTH_MAX,
+};
+use reset_ctrl::storage::Storage;
+use reset_ctrl::sysex::{Command, ConfigServer, Response};
+use reset_ctrl::time::{EmbassyTimer, Timer};
+use reset_ctrl::ui::backend::{Stm32Backend, MIDI_IN, SYSEX_IN, SYSEX_OUT};
 use reset_ctrl::ui::input::{Encoder, EncoderDirection, Potentiometer};
 use reset_ctrl::ui::Backend;
-use reset_ctrl::ui::{Input, InputType};
+use reset_ctrl::ui::{Address, Input, InputType};
 
 use {defmt_rtt as _, panic_probe as _};
 
-#[embassy_executor::main]
-async fn main(spawner: Spawner) {
-    // setup device
+const CONFIG_BUF_LEN: usize = 1024;
+// microseconds without input changes before a changed device is saved
+const SAVE_IDLE: u64 = 2_000_000;
+// microseconds for the reboot acknowledgement to reach the host
+const REBOOT_DELAY: u64 = 100_000;
+
+fn default_device() -> Device {
     // encoder
-    let mut encoder = Encoder::new();
+    let mut encoder = Encoder::new(Address::new(0, 0, 0));
     let mut handler = EncoderHandler::MidiAbs(MidiAbs {
         channel: 0,
         control: 4,
@@ -31,42 +39,115 @@ async fn main(spawner: Spawner) {
     let mut input = InputType::Encoder(encoder);
 
     // potentiometer
-    let mut pot = Potentiometer::new();
-    let mut pot_handler = PotentiometerHandler::MidiAbs(PotMidiAbs {
-        channel: 5,
-        control: 42,
-        value: 23,
-    });
+    let mut pot = Potentiometer::new(Address::new(0, 0, 2));
+    let mut pot_handler =
+        PotentiometerHandler::MidiAbs(PotMidiAbs::new(5, 42, 23, Takeover::Pickup));
     pot.attach_handler(pot_handler);
     let mut pot_input = InputType::Potentiometer(pot);
 
     let mut device = Device::new();
-    let mut outputs: Vec<OutputType, 2> = Vec::new();
-    outputs.push(OutputType::StdOut(StdOut {}));
-    outputs.push(OutputType::UsbOut(UsbOut {}));
-
-    // setup
-    device.add_input(input);
-    device.add_input(pot_input);
+    device.add_input(input).expect("no input slot left");
+    device.add_input(pot_input).expect("no input slot left");
+    device
+}
 
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
     info!("Setting up Stm32Backend...");
     // reset_ctrl setup
     let mut b = Stm32Backend::new().await;
     info!("Stm32Backend setup completed!");
 
-    device.init_inputs(&mut b);
+    let mut storage = Storage::new(b.take_flash().unwrap()).unwrap();
+    let mut buf = [0u8; CONFIG_BUF_LEN];
+    let mut device = match storage.load(&mut buf) {
+        Ok(Some(data)) => match Device::from_binary(data) {
+            Ok(device) => {
+                info!("Loaded config from flash");
+                device
+            }
+            Err(_) => {
+                info!("Stored config is invalid, using the default");
+                default_device()
+            }
+        },
+        _ => default_device(),
+    };
+    b.set_chain(device.boards().clone());
+
+    let mut outputs: Vec<OutputType, 2> = Vec::new();
+    outputs.push(OutputType::StdOut(StdOut {}));
+    outputs.push(OutputType::UsbOut(UsbOut::new(
+        USB_QUEUE_DEPTH_MAX,
+        OverflowPolicy::CoalesceCc,
+    )));
+
+    device.init_inputs(&mut b).await;
 
     b.spawn_usb(spawner);
     b.spawn_midi(spawner);
 
     // operation
     info!("Starting update loop");
+    let mut timer = EmbassyTimer;
+    let mut server = ConfigServer::new();
     let reset_ctrl_fut = async {
+        // the handler values or the config differ from the saved ones
+        let mut dirty = false;
+        let mut changed_at = timer.now();
         loop {
-            Timer::after_micros(500).await;
+            // wake up early for the next clock
+            let now = timer.now();
+            let deadline = device
+                .deadline(now)
+                .map_or(now + 500, |d| d.min(now + 500));
+            timer.sleep_until(deadline).await;
+            device.tick(&outputs, timer.now()).await;
 
-            device.update(&mut b).await;
+            while let Ok(data) = MIDI_IN.try_receive() {
+                device.receive(&data);
+            }
+            while let Ok(byte) = SYSEX_IN.try_receive() {
+                // LCD feedback of the DAW for the MCU preset
+                device.receive_byte(byte);
+                let Some(response) = server
+                    .receive(byte, &mut device, &mut storage, &outputs)
+                    .await
+                else {
+                    continue;
+                };
+                if let Response::Ack(Command::CommitConfig | Command::SetInput) = response {
+                    info!("Config changed by the host");
+                    dirty = true;
+                    b.set_chain(device.boards().clone());
+                    device.init_inputs(&mut b).await;
+                }
+                SYSEX_OUT.send(response.to_sysex()).await;
+            }
+            if server.reboot_requested() {
+                info!("Rebooting on request of the host");
+                timer.sleep_until(timer.now() + REBOOT_DELAY).await;
+                cortex_m::peripheral::SCB::sys_reset();
+            }
+            if device.update(&mut b).await {
+                dirty = true;
+                changed_at = timer.now();
+            }
             device.run_handler(&outputs).await;
+
+            // keep the handler values, erasing and writing the flash blocks
+            // the loop so wait until the controls are left alone
+            if dirty && timer.now() - changed_at > SAVE_IDLE {
+                dirty = false;
+                match device.to_binary(&mut buf) {
+                    Ok(data) => {
+                        if storage.save(data).is_err() {
+                            info!("Saving the config failed");
+                        }
+                    }
+                    Err(_) => info!("Config too large to save"),
+                }
+            }
         }
     };
 
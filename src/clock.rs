@@ -0,0 +1,486 @@
+use crate::output::OutputData;
+
+use serde::{Deserialize, Serialize};
+
+pub(crate) const CLOCK_PPQN: u64 = 24;
+// a MIDI beat of the song position is a 16th note
+pub(crate) const CLOCK_CLOCKS_PER_BEAT: u32 = 6;
+pub(crate) const CLOCK_BPM_MIN: u16 = 20;
+pub(crate) const CLOCK_BPM_MAX: u16 = 300;
+const CLOCK_BPM_DEFAULT: u16 = 120;
+const SONG_POSITION_MAX: u16 = 0x3fff;
+pub(crate) const MICROS_PER_MINUTE: u64 = 60_000_000;
+// gates of notes played to the clock are percent of the note length
+pub(crate) const GATE_MAX: u8 = 100;
+// a clock at the slowest tempo comes every 125ms
+const CLOCK_FOLLOW_TIMEOUT_DEFAULT: u32 = 500_000;
+// weight of the latest interval in the tempo average, as 1/N
+const CLOCK_FOLLOW_SMOOTHING: u64 = 8;
+
+/*
+ * Input index used in events of output data generated by the clock.
+ */
+pub const CLOCK_INPUT: usize = usize::MAX;
+
+/*
+ * Commands handlers send to the clock generator of the device.
+ */
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ClockControl {
+    Start,
+    Stop,
+    Continue,
+    // stops if running, otherwise continues from the song position
+    Toggle,
+    // changes the tempo by the given BPM
+    Tempo(i16),
+    // moves the song position while stopped, in MIDI beats
+    Locate(u16),
+}
+
+/*
+ * Tempo, run state and song position of the clock the device follows.
+ */
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct ClockState {
+    pub bpm: u16,
+    pub running: bool,
+    pub song_position: u16,
+    // true while following an external clock
+    pub external: bool,
+}
+
+/*
+ * Generates MIDI clock at 24 ppqn. Clock times are calculated from the
+ * start or the last tempo change, so rounding doesn't add up to drift.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ClockGenerator {
+    #[cfg_attr(
+        feature = "schema",
+        schemars(range(min = "CLOCK_BPM_MIN", max = "CLOCK_BPM_MAX"))
+    )]
+    bpm: u16,
+    #[serde(skip)]
+    running: bool,
+    // time of the first clock since start or the last tempo change
+    #[serde(skip)]
+    anchor: Option<u64>,
+    // clocks sent since the anchor
+    #[serde(skip)]
+    ticks: u64,
+    // clocks since the song start
+    #[serde(skip)]
+    clocks: u32,
+}
+
+impl Default for ClockGenerator {
+    fn default() -> Self {
+        Self::new(CLOCK_BPM_DEFAULT)
+    }
+}
+
+impl ClockGenerator {
+    pub fn new(bpm: u16) -> Self {
+        Self {
+            bpm: bpm.clamp(CLOCK_BPM_MIN, CLOCK_BPM_MAX),
+            running: false,
+            anchor: None,
+            ticks: 0,
+            clocks: 0,
+        }
+    }
+
+    pub fn bpm(&self) -> u16 {
+        self.bpm
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn song_position(&self) -> u16 {
+        (self.clocks / CLOCK_CLOCKS_PER_BEAT).min(SONG_POSITION_MAX as u32) as u16
+    }
+
+    /*
+     * Changes the tempo, a pending clock keeps its time.
+     */
+    pub fn set_bpm(&mut self, bpm: u16) {
+        if let Some(deadline) = self.deadline().filter(|_| self.anchor.is_some()) {
+            self.anchor = Some(deadline);
+            self.ticks = 0;
+        }
+        self.bpm = bpm.clamp(CLOCK_BPM_MIN, CLOCK_BPM_MAX);
+    }
+
+    /*
+     * Time of the next clock, `None` while stopped. Right after starting
+     * the next clock is due right away.
+     */
+    pub fn deadline(&self) -> Option<u64> {
+        if !self.running {
+            return None;
+        }
+        let bpm = self.bpm.clamp(CLOCK_BPM_MIN, CLOCK_BPM_MAX) as u64;
+        let offset = self.ticks * MICROS_PER_MINUTE / (bpm * CLOCK_PPQN);
+        Some(self.anchor.map_or(0, |anchor| anchor + offset))
+    }
+
+    /*
+     * Applies a command and returns the transport message to send, if any.
+     */
+    pub fn control(&mut self, control: ClockControl) -> Option<OutputData> {
+        match control {
+            ClockControl::Start => {
+                self.clocks = 0;
+                self.restart();
+                Some(OutputData::Start)
+            }
+            ClockControl::Stop => {
+                self.running = false;
+                Some(OutputData::Stop)
+            }
+            ClockControl::Continue if !self.running => {
+                self.restart();
+                Some(OutputData::Continue)
+            }
+            ClockControl::Continue => None,
+            ClockControl::Toggle if self.running => self.control(ClockControl::Stop),
+            ClockControl::Toggle if self.clocks == 0 => self.control(ClockControl::Start),
+            ClockControl::Toggle => self.control(ClockControl::Continue),
+            ClockControl::Tempo(delta) => {
+                let bpm = (self.bpm as i32 + delta as i32).clamp(0, u16::MAX as i32);
+                self.set_bpm(bpm as u16);
+                None
+            }
+            // the song position must not change while running
+            ClockControl::Locate(_) if self.running => None,
+            ClockControl::Locate(position) => {
+                let position = position.min(SONG_POSITION_MAX);
+                self.clocks = position as u32 * CLOCK_CLOCKS_PER_BEAT;
+                Some(OutputData::SongPosition(position))
+            }
+        }
+    }
+
+    /*
+     * Returns a clock message if one is due at the given time. Call again
+     * until it returns `None` to catch up on missed clocks.
+     */
+    pub fn tick(&mut self, now: u64) -> Option<OutputData> {
+        if !self.running {
+            return None;
+        }
+        self.anchor.get_or_insert(now);
+        match self.deadline() {
+            Some(deadline) if now >= deadline => (),
+            _ => return None,
+        }
+
+        self.ticks += 1;
+        self.clocks = self.clocks.saturating_add(1);
+        Some(OutputData::Clock)
+    }
+
+    /*
+     * Takes over tempo, run state and song position of an external clock
+     * which went away. A running clock goes on with the next tick.
+     */
+    pub fn sync(&mut self, follower: &ClockFollower) {
+        if let Some(bpm) = follower.bpm() {
+            self.bpm = bpm.clamp(CLOCK_BPM_MIN, CLOCK_BPM_MAX);
+        }
+        self.restart();
+        self.running = follower.is_running();
+        self.clocks = follower.clocks;
+    }
+
+    pub fn state(&self) -> ClockState {
+        ClockState {
+            bpm: self.bpm,
+            running: self.running,
+            song_position: self.song_position(),
+            external: false,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.running = true;
+        self.anchor = None;
+        self.ticks = 0;
+    }
+}
+
+/*
+ * Follows an external MIDI clock. The tempo is averaged over the clock
+ * intervals, the clock is considered gone once no clock came in for
+ * `timeout` microseconds.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ClockFollower {
+    #[serde(default = "ClockFollower::default_timeout")]
+    timeout: u32,
+    #[serde(skip)]
+    last: Option<u64>,
+    // averaged clock interval in microseconds
+    #[serde(skip)]
+    interval: Option<u64>,
+    #[serde(skip)]
+    running: bool,
+    #[serde(skip)]
+    clocks: u32,
+}
+
+impl Default for ClockFollower {
+    fn default() -> Self {
+        Self::new(CLOCK_FOLLOW_TIMEOUT_DEFAULT)
+    }
+}
+
+impl ClockFollower {
+    pub fn new(timeout: u32) -> Self {
+        Self {
+            timeout,
+            last: None,
+            interval: None,
+            running: false,
+            clocks: 0,
+        }
+    }
+
+    fn default_timeout() -> u32 {
+        CLOCK_FOLLOW_TIMEOUT_DEFAULT
+    }
+
+    /*
+     * Updates the state from received data, other data than clock and
+     * transport messages is ignored.
+     */
+    pub fn receive(&mut self, data: &OutputData, now: u64) {
+        match *data {
+            OutputData::Clock => {
+                let last = self.last.filter(|_| self.is_active(now));
+                // time going backwards, e.g. after a timer reset, gives no interval
+                let interval = last.map(|last| now.saturating_sub(last)).filter(|i| *i > 0);
+                if let Some(interval) = interval {
+                    self.interval = Some(match self.interval {
+                        Some(avg) => {
+                            (avg * (CLOCK_FOLLOW_SMOOTHING - 1) + interval) / CLOCK_FOLLOW_SMOOTHING
+                        }
+                        None => interval,
+                    });
+                }
+                self.last = Some(now);
+                if self.running {
+                    self.clocks = self.clocks.saturating_add(1);
+                }
+            }
+            OutputData::Start => {
+                self.running = true;
+                self.clocks = 0;
+            }
+            OutputData::Continue => self.running = true,
+            OutputData::Stop => self.running = false,
+            OutputData::SongPosition(position) if !self.running => {
+                self.clocks = position as u32 * CLOCK_CLOCKS_PER_BEAT;
+            }
+            _ => (),
+        }
+    }
+
+    /*
+     * Whether a clock came in within the timeout.
+     */
+    pub fn is_active(&self, now: u64) -> bool {
+        self.last
+            .is_some_and(|last| now.saturating_sub(last) < self.timeout as u64)
+    }
+
+    /*
+     * Time in microseconds the followed clock times out at, unless
+     * another clock comes in.
+     */
+    pub fn timeout_at(&self) -> Option<u64> {
+        self.last.map(|last| last + self.timeout as u64)
+    }
+
+    /*
+     * Whether a clock came in before, but not within the timeout.
+     */
+    pub fn timed_out(&self, now: u64) -> bool {
+        self.last.is_some() && !self.is_active(now)
+    }
+
+    pub fn bpm(&self) -> Option<u16> {
+        let interval = self.interval.filter(|i| *i > 0)?;
+        let bpm = (MICROS_PER_MINUTE + interval * CLOCK_PPQN / 2) / (interval * CLOCK_PPQN);
+        Some(bpm.min(u16::MAX as u64) as u16)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn song_position(&self) -> u16 {
+        (self.clocks / CLOCK_CLOCKS_PER_BEAT).min(SONG_POSITION_MAX as u32) as u16
+    }
+
+    /*
+     * Forgets the external clock, the tempo is measured from scratch the
+     * next time a clock comes in.
+     */
+    pub fn reset(&mut self) {
+        *self = Self::new(self.timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clocks_until(clock: &mut ClockGenerator, now: u64) -> usize {
+        core::iter::from_fn(|| clock.tick(now)).count()
+    }
+
+    #[test]
+    fn clock_times() {
+        let mut clock = ClockGenerator::new(120);
+        assert_eq!(clock.tick(0), None);
+
+        assert_eq!(clock.control(ClockControl::Start), Some(OutputData::Start));
+        assert_eq!(clock.deadline(), Some(0));
+        assert_eq!(clock.tick(1000), Some(OutputData::Clock));
+        // 120 BPM at 24 ppqn is one clock every 20833.3us
+        assert_eq!(clock.deadline(), Some(21833));
+        assert_eq!(clocks_until(&mut clock, 21832), 0);
+        assert_eq!(clocks_until(&mut clock, 21833), 1);
+
+        // a whole beat later without drift
+        assert_eq!(clocks_until(&mut clock, 1000 + 500_000), 23);
+        assert_eq!(clock.deadline(), Some(1000 + 500_000 + 20833));
+    }
+
+    #[test]
+    fn tempo_change() {
+        let mut clock = ClockGenerator::new(120);
+        clock.control(ClockControl::Start);
+        clock.tick(0);
+
+        clock.control(ClockControl::Tempo(-60));
+        assert_eq!(clock.bpm(), 60);
+        // the pending clock keeps its time
+        assert_eq!(clock.deadline(), Some(20833));
+        assert_eq!(clocks_until(&mut clock, 20833), 1);
+        assert_eq!(clock.deadline(), Some(20833 + 41666));
+
+        clock.control(ClockControl::Tempo(-100));
+        assert_eq!(clock.bpm(), CLOCK_BPM_MIN);
+        clock.set_bpm(1000);
+        assert_eq!(clock.bpm(), CLOCK_BPM_MAX);
+    }
+
+    #[test]
+    fn follow_tempo() {
+        let mut follower = ClockFollower::new(100_000);
+        assert_eq!(follower.bpm(), None);
+
+        // 100 BPM with some jitter
+        let mut now = 0;
+        for i in 0..48 {
+            let jitter = if i % 2 == 0 { 300 } else { 0 };
+            follower.receive(&OutputData::Clock, now + jitter);
+            now += 25_000;
+        }
+        assert!(follower.is_active(now));
+        assert_eq!(follower.bpm(), Some(100));
+
+        assert!(!follower.is_active(now + 100_000));
+        // a clock after the timeout doesn't count as interval
+        follower.receive(&OutputData::Clock, now + 200_000);
+        assert_eq!(follower.bpm(), Some(100));
+    }
+
+    #[test]
+    fn follow_time_going_backwards() {
+        let mut follower = ClockFollower::new(100_000);
+        follower.receive(&OutputData::Clock, 50_000);
+        follower.receive(&OutputData::Clock, 75_000);
+        assert_eq!(follower.bpm(), Some(100));
+
+        follower.receive(&OutputData::Clock, 10_000);
+        follower.receive(&OutputData::Clock, 10_000);
+        assert_eq!(follower.bpm(), Some(100));
+        assert!(follower.is_active(10_000));
+    }
+
+    #[test]
+    fn follow_transport() {
+        let mut follower = ClockFollower::default();
+
+        follower.receive(&OutputData::SongPosition(2), 0);
+        follower.receive(&OutputData::Continue, 0);
+        for _ in 0..12 {
+            follower.receive(&OutputData::Clock, 0);
+        }
+        assert!(follower.is_running());
+        assert_eq!(follower.song_position(), 4);
+
+        follower.receive(&OutputData::Stop, 0);
+        follower.receive(&OutputData::Clock, 0);
+        assert!(!follower.is_running());
+        assert_eq!(follower.song_position(), 4);
+
+        follower.receive(&OutputData::Start, 0);
+        assert_eq!(follower.song_position(), 0);
+    }
+
+    #[test]
+    fn sync_from_follower() {
+        let mut follower = ClockFollower::default();
+        follower.receive(&OutputData::Start, 0);
+        for i in 0..7 {
+            follower.receive(&OutputData::Clock, i * 12_500);
+        }
+
+        let mut clock = ClockGenerator::new(120);
+        clock.sync(&follower);
+        assert_eq!(clock.bpm(), 200);
+        assert!(clock.is_running());
+        assert_eq!(clock.song_position(), 1);
+        // no burst of missed clocks
+        assert_eq!(clocks_until(&mut clock, 1_000_000), 1);
+    }
+
+    #[test]
+    fn transport() {
+        let mut clock = ClockGenerator::new(120);
+
+        assert_eq!(clock.control(ClockControl::Toggle), Some(OutputData::Start));
+        assert_eq!(clocks_until(&mut clock, 0), 1);
+        assert_eq!(clocks_until(&mut clock, 999_999), 47);
+        assert_eq!(clock.song_position(), 8);
+        assert_eq!(clock.control(ClockControl::Locate(0)), None);
+
+        assert_eq!(clock.control(ClockControl::Toggle), Some(OutputData::Stop));
+        assert_eq!(clocks_until(&mut clock, 2_000_000), 0);
+        assert_eq!(
+            clock.control(ClockControl::Locate(4)),
+            Some(OutputData::SongPosition(4))
+        );
+
+        assert_eq!(
+            clock.control(ClockControl::Toggle),
+            Some(OutputData::Continue)
+        );
+        assert_eq!(clock.control(ClockControl::Continue), None);
+        assert_eq!(clocks_until(&mut clock, 3_000_000), 1);
+        assert_eq!(clock.song_position(), 4);
+
+        assert_eq!(clock.control(ClockControl::Start), Some(OutputData::Start));
+        assert_eq!(clock.song_position(), 0);
+    }
+}
@@ -0,0 +1,469 @@
+mod binary;
+#[cfg(target_os = "linux")]
+pub use self::binary::to_binary_vec;
+pub use self::binary::{from_binary, to_binary, BINARY_HEADER_LEN, BINARY_MAGIC, BINARY_VERSION};
+
+#[cfg(target_os = "linux")]
+mod migrate;
+#[cfg(target_os = "linux")]
+pub use self::migrate::{migrate, migrate_json, migrate_yaml};
+
+use crate::handler::{ButtonHandler, EncoderHandler, GeneratorHandler, PotentiometerHandler};
+use crate::handler::{MCU_STRIPS, MCU_VPOT_CONTROL};
+use crate::ui::{Address, Chain, InputType, SLOT_PINS};
+
+use core::fmt::{self, Write};
+
+/*
+ * Version of the layout of YAML and JSON configs, given by their top-level
+ * `version` field. Bumped with a migration whenever old configs stop
+ * loading.
+ */
+pub const CONFIG_VERSION: u32 = 2;
+
+const CONFIG_PATH_MAX: usize = 64;
+const CONFIG_MESSAGE_MAX: usize = 128;
+pub(crate) const MIDI_CHANNEL_MAX: u8 = 15;
+pub(crate) const MIDI_DATA_MAX: u8 = 0x7f;
+pub(crate) const MCU_STRIP_MAX: u8 = MCU_STRIPS - 1;
+// the master fader follows the strips
+pub(crate) const MCU_FADER_MAX: u8 = MCU_STRIPS;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ConfigErrorKind {
+    // not valid YAML or not matching the structure of the config
+    Syntax,
+    OutOfRange,
+    Duplicate,
+    Capacity,
+    // refers to an input or output which doesn't exist
+    Reference,
+    // written by an incompatible version
+    Version,
+}
+
+/*
+ * Text of a config error. On linux it lives on the heap to keep results
+ * carrying an error small, on the bare-metal target it's truncated to N
+ * bytes.
+ */
+#[cfg(target_os = "linux")]
+pub type ErrorText<const N: usize> = std::string::String;
+#[cfg(target_os = "none")]
+pub type ErrorText<const N: usize> = heapless::String<N>;
+
+/*
+ * Error found while loading or validating a config. Line and column are
+ * only known for syntax errors, the path names the offending field like
+ * `inputs[1].handler.channel`.
+ */
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConfigError {
+    pub kind: ConfigErrorKind,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub input: Option<usize>,
+    pub path: ErrorText<CONFIG_PATH_MAX>,
+    pub message: ErrorText<CONFIG_MESSAGE_MAX>,
+}
+
+/*
+ * JSON Schema of the config in its JSON form, YAML configs match it once
+ * their tags are written as maps with a single key.
+ */
+#[cfg(feature = "schema")]
+pub fn schema() -> schemars::schema::RootSchema {
+    use schemars::schema::{InstanceType, SchemaObject};
+
+    let mut schema = schemars::schema_for!(crate::device::Device);
+    schema.schema.metadata().title = Some("reset_ctrl config".into());
+    // older configs have to be migrated before they match
+    let version = SchemaObject {
+        instance_type: Some(InstanceType::Integer.into()),
+        const_value: Some(CONFIG_VERSION.into()),
+        ..Default::default()
+    };
+    schema
+        .schema
+        .object()
+        .properties
+        .insert("version".into(), version.into());
+    schema
+}
+
+/*
+ * Config written together with the version of its layout.
+ */
+#[cfg(target_os = "linux")]
+#[derive(serde::Serialize)]
+pub(crate) struct Versioned<'a, T> {
+    pub version: u32,
+    #[serde(flatten)]
+    pub config: &'a T,
+}
+
+/*
+ * MIDI message an input is mapped to, no two inputs may share one.
+ */
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Mapping {
+    // channel and control
+    Cc(u8, u8),
+    // channel and key
+    Note(u8, u8),
+    // channel
+    PitchBend(u8),
+}
+
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mapping::Cc(channel, control) => write!(f, "CC {} on channel {}", control, channel),
+            Mapping::Note(channel, key) => write!(f, "Note {} on channel {}", key, channel),
+            Mapping::PitchBend(channel) => write!(f, "Pitch bend on channel {}", channel),
+        }
+    }
+}
+
+/*
+ * Writes as much as fits into a string and drops the rest.
+ */
+#[cfg(target_os = "none")]
+struct Truncate<'a, const N: usize>(&'a mut heapless::String<N>);
+
+#[cfg(target_os = "none")]
+impl<const N: usize> Write for Truncate<'_, N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.0.push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn text<const N: usize>(args: fmt::Arguments) -> ErrorText<N> {
+    let mut s = ErrorText::new();
+    #[cfg(target_os = "linux")]
+    s.write_fmt(args).ok();
+    #[cfg(target_os = "none")]
+    Truncate(&mut s).write_fmt(args).ok();
+    s
+}
+
+impl ConfigError {
+    pub fn new(kind: ConfigErrorKind, path: fmt::Arguments, message: fmt::Arguments) -> Self {
+        Self {
+            kind,
+            line: None,
+            column: None,
+            input: None,
+            path: text::<CONFIG_PATH_MAX>(path),
+            message: text::<CONFIG_MESSAGE_MAX>(message),
+        }
+    }
+
+    pub fn with_input(self, input: usize) -> Self {
+        Self {
+            input: Some(input),
+            ..self
+        }
+    }
+
+    /*
+     * Drops the line and column, for errors in a document generated from
+     * the one of the user.
+     */
+    pub fn without_location(self) -> Self {
+        Self {
+            line: None,
+            column: None,
+            ..self
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn from_yaml(err: serde_path_to_error::Error<serde_yaml::Error>) -> Self {
+        let (path, input) = path(err.path());
+        let location = err.inner().location();
+        // serde_yaml puts the path in front and the location behind
+        let message = err.inner().to_string();
+        let message = message
+            .rsplit_once(" at line ")
+            .map_or(&*message, |(m, _)| m);
+        let message = match message.split_once(": ") {
+            Some((prefix, m)) if !prefix.contains(' ') => m,
+            _ => message,
+        };
+
+        Self {
+            kind: kind(message),
+            line: location.as_ref().map(|l| l.line()),
+            column: location.as_ref().map(|l| l.column()),
+            input,
+            path,
+            message: text::<CONFIG_MESSAGE_MAX>(format_args!("{}", message)),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn from_json(err: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        let (path, input) = path(err.path());
+        let inner = err.inner();
+        // serde_json puts the location behind
+        let message = inner.to_string();
+        let message = message
+            .rsplit_once(" at line ")
+            .map_or(&*message, |(m, _)| m);
+
+        Self {
+            kind: kind(message),
+            line: Some(inner.line()),
+            column: Some(inner.column()),
+            input,
+            path,
+            message: text::<CONFIG_MESSAGE_MAX>(format_args!("{}", message)),
+        }
+    }
+}
+
+/*
+ * Formats the path of a deserialization error like `inputs[1].handler`
+ * and returns it with the index of the input it lies in.
+ */
+#[cfg(target_os = "linux")]
+fn path(err_path: &serde_path_to_error::Path) -> (ErrorText<CONFIG_PATH_MAX>, Option<usize>) {
+    use serde_path_to_error::Segment;
+
+    let mut path = ErrorText::new();
+    let mut input = None;
+    let mut parent = None;
+    for segment in err_path.iter() {
+        match segment {
+            Segment::Seq { index } => {
+                if parent == Some("inputs") {
+                    input = Some(*index);
+                }
+                write!(path, "[{}]", index).ok()
+            }
+            Segment::Map { key } if path.is_empty() => write!(path, "{}", key).ok(),
+            Segment::Map { key } => write!(path, ".{}", key).ok(),
+            // variant names are YAML tags or JSON keys, not fields
+            Segment::Enum { .. } => continue,
+            Segment::Unknown => write!(path, ".?").ok(),
+        };
+        parent = match segment {
+            Segment::Map { key } => Some(key.as_str()),
+            _ => None,
+        };
+    }
+    (path, input)
+}
+
+// the fixed capacity lists report overflows as invalid length
+#[cfg(target_os = "linux")]
+fn kind(message: &str) -> ConfigErrorKind {
+    if message.starts_with("invalid length") {
+        ConfigErrorKind::Capacity
+    } else {
+        ConfigErrorKind::Syntax
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, "line {} column {}: ", line, column)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl std::error::Error for ConfigError {}
+
+/*
+ * Error of a serializer or a parser without location.
+ */
+#[cfg(target_os = "linux")]
+pub(crate) fn syntax(err: impl fmt::Display) -> ConfigError {
+    ConfigError::new(
+        ConfigErrorKind::Syntax,
+        format_args!(""),
+        format_args!("{}", err),
+    )
+}
+
+pub(crate) fn channel(v: u8, path: fmt::Arguments) -> Result<(), ConfigError> {
+    if v > MIDI_CHANNEL_MAX {
+        return Err(ConfigError::new(
+            ConfigErrorKind::OutOfRange,
+            path,
+            format_args!("channel {} is not within 0 to {}", v, MIDI_CHANNEL_MAX),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn data(v: u8, path: fmt::Arguments) -> Result<(), ConfigError> {
+    if v > MIDI_DATA_MAX {
+        return Err(ConfigError::new(
+            ConfigErrorKind::OutOfRange,
+            path,
+            format_args!("value {} is not within 0 to {}", v, MIDI_DATA_MAX),
+        ));
+    }
+    Ok(())
+}
+
+fn strip(v: u8, max: u8, path: fmt::Arguments) -> Result<(), ConfigError> {
+    if v > max {
+        return Err(ConfigError::new(
+            ConfigErrorKind::OutOfRange,
+            path,
+            format_args!("strip {} is not within 0 to {}", v, max),
+        ));
+    }
+    Ok(())
+}
+
+/*
+ * Checks the address of the input lies within the chain and returns it
+ * together with the number of mux lines used, if any.
+ */
+pub(crate) fn address(
+    idx: usize,
+    inputs: &[InputType],
+    boards: &Chain,
+) -> Result<Option<(Address, u8)>, ConfigError> {
+    let Some((address, lines)) = inputs[idx].lines() else {
+        return Ok(None);
+    };
+    let err = match boards.boards().get(address.board as usize) {
+        None => Some(ConfigError::new(
+            ConfigErrorKind::Reference,
+            format_args!("inputs[{}].address.board", idx),
+            format_args!("board {} doesn't exist", address.board),
+        )),
+        Some(board) if address.slot >= board.slots => Some(ConfigError::new(
+            ConfigErrorKind::OutOfRange,
+            format_args!("inputs[{}].address.slot", idx),
+            format_args!(
+                "slot {} is not within 0 to {}",
+                address.slot,
+                board.slots - 1
+            ),
+        )),
+        Some(_) if address.pin > SLOT_PINS - lines => Some(ConfigError::new(
+            ConfigErrorKind::OutOfRange,
+            format_args!("inputs[{}].address.pin", idx),
+            format_args!(
+                "pins {} to {} are not within 0 to {}",
+                address.pin,
+                address.pin + lines - 1,
+                SLOT_PINS - 1
+            ),
+        )),
+        Some(_) => None,
+    };
+    match err {
+        Some(e) => Err(e.with_input(idx)),
+        None => Ok(Some((address, lines))),
+    }
+}
+
+/*
+ * Checks the handler of the input with the given index and returns the
+ * MIDI message it is mapped to, if any.
+ */
+pub(crate) fn input(idx: usize, inputs: &[InputType]) -> Result<Option<Mapping>, ConfigError> {
+    mapping(idx, inputs).map_err(|e| e.with_input(idx))
+}
+
+fn mapping(idx: usize, inputs: &[InputType]) -> Result<Option<Mapping>, ConfigError> {
+    let mapping = match &inputs[idx] {
+        InputType::Encoder(i) => match &i.handler {
+            EncoderHandler::MidiRel(h) => {
+                channel(h.channel, format_args!("inputs[{}].handler.channel", idx))?;
+                data(h.control, format_args!("inputs[{}].handler.control", idx))?;
+                Some(Mapping::Cc(h.channel, h.control))
+            }
+            EncoderHandler::MidiAbs(h) => {
+                channel(h.channel, format_args!("inputs[{}].handler.channel", idx))?;
+                data(h.control, format_args!("inputs[{}].handler.control", idx))?;
+                data(h.value, format_args!("inputs[{}].handler.value", idx))?;
+                Some(Mapping::Cc(h.channel, h.control))
+            }
+            EncoderHandler::Modulation(h) => {
+                if !matches!(inputs.get(h.input), Some(InputType::Generator(_))) {
+                    return Err(ConfigError::new(
+                        ConfigErrorKind::Reference,
+                        format_args!("inputs[{}].handler.input", idx),
+                        format_args!("input {} is not a generator", h.input),
+                    ));
+                }
+                None
+            }
+            EncoderHandler::VPot(h) => {
+                strip(
+                    h.strip,
+                    MCU_STRIP_MAX,
+                    format_args!("inputs[{}].handler.strip", idx),
+                )?;
+                Some(Mapping::Cc(0, MCU_VPOT_CONTROL + h.strip))
+            }
+            EncoderHandler::Tempo(_) | EncoderHandler::Dummy => None,
+        },
+        InputType::Potentiometer(i) => match &i.handler {
+            PotentiometerHandler::MidiAbs(h) => {
+                channel(h.channel, format_args!("inputs[{}].handler.channel", idx))?;
+                data(h.control, format_args!("inputs[{}].handler.control", idx))?;
+                data(h.value, format_args!("inputs[{}].handler.value", idx))?;
+                Some(Mapping::Cc(h.channel, h.control))
+            }
+            PotentiometerHandler::Fader(h) => {
+                strip(
+                    h.strip,
+                    MCU_FADER_MAX,
+                    format_args!("inputs[{}].handler.strip", idx),
+                )?;
+                Some(Mapping::PitchBend(h.strip))
+            }
+            PotentiometerHandler::Dummy => None,
+        },
+        InputType::Button(i) => match &i.handler {
+            ButtonHandler::MidiNote(h) => {
+                channel(h.channel, format_args!("inputs[{}].handler.channel", idx))?;
+                data(h.key, format_args!("inputs[{}].handler.key", idx))?;
+                data(h.velocity, format_args!("inputs[{}].handler.velocity", idx))?;
+                Some(Mapping::Note(h.channel, h.key))
+            }
+            ButtonHandler::Mcu(h) => {
+                strip(
+                    h.strip,
+                    MCU_STRIP_MAX,
+                    format_args!("inputs[{}].handler.strip", idx),
+                )?;
+                Some(Mapping::Note(0, h.function.note(h.strip)))
+            }
+            ButtonHandler::Transport(_) | ButtonHandler::Dummy => None,
+        },
+        InputType::Generator(i) => {
+            data(i.depth, format_args!("inputs[{}].depth", idx))?;
+            data(i.center, format_args!("inputs[{}].center", idx))?;
+            match &i.handler {
+                GeneratorHandler::MidiCc(h) => {
+                    channel(h.channel, format_args!("inputs[{}].handler.channel", idx))?;
+                    data(h.control, format_args!("inputs[{}].handler.control", idx))?;
+                    Some(Mapping::Cc(h.channel, h.control))
+                }
+                GeneratorHandler::Dummy => None,
+            }
+        }
+    };
+    Ok(mapping)
+}
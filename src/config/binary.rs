@@ -0,0 +1,138 @@
+use super::{ConfigError, ConfigErrorKind};
+
+use serde::{Deserialize, Serialize};
+
+/*
+ * Header in front of the postcard encoded config: magic, format version
+ * and length of the encoded config as little endian u32.
+ */
+pub const BINARY_MAGIC: [u8; 4] = *b"RCTL";
+pub const BINARY_VERSION: u8 = 1;
+pub const BINARY_HEADER_LEN: usize = 9;
+
+fn header(len: usize) -> [u8; BINARY_HEADER_LEN] {
+    let mut header = [0; BINARY_HEADER_LEN];
+    header[..4].copy_from_slice(&BINARY_MAGIC);
+    header[4] = BINARY_VERSION;
+    header[5..].copy_from_slice(&(len as u32).to_le_bytes());
+    header
+}
+
+fn postcard_error(err: postcard::Error) -> ConfigError {
+    let kind = match err {
+        postcard::Error::SerializeBufferFull => ConfigErrorKind::Capacity,
+        _ => ConfigErrorKind::Syntax,
+    };
+    ConfigError::new(kind, format_args!(""), format_args!("{}", err))
+}
+
+/*
+ * Encodes the value into the buffer and returns the part used.
+ */
+pub fn to_binary<'a, T: Serialize>(
+    value: &T,
+    buf: &'a mut [u8],
+) -> Result<&'a mut [u8], ConfigError> {
+    if buf.len() < BINARY_HEADER_LEN {
+        return Err(postcard_error(postcard::Error::SerializeBufferFull));
+    }
+    let (head, body) = buf.split_at_mut(BINARY_HEADER_LEN);
+    let len = postcard::to_slice(value, body)
+        .map_err(postcard_error)?
+        .len();
+    head.copy_from_slice(&header(len));
+    Ok(&mut buf[..BINARY_HEADER_LEN + len])
+}
+
+#[cfg(target_os = "linux")]
+pub fn to_binary_vec<T: Serialize>(value: &T) -> Result<std::vec::Vec<u8>, ConfigError> {
+    let body = postcard::to_stdvec(value).map_err(postcard_error)?;
+    let mut data = header(body.len()).to_vec();
+    data.extend(body);
+    Ok(data)
+}
+
+/*
+ * Decodes a value written by `to_binary`, trailing data is ignored.
+ */
+pub fn from_binary<'a, T: Deserialize<'a>>(data: &'a [u8]) -> Result<T, ConfigError> {
+    if data.len() < BINARY_HEADER_LEN || data[..4] != BINARY_MAGIC {
+        return Err(ConfigError::new(
+            ConfigErrorKind::Syntax,
+            format_args!(""),
+            format_args!("not a binary config"),
+        ));
+    }
+    if data[4] != BINARY_VERSION {
+        return Err(ConfigError::new(
+            ConfigErrorKind::Version,
+            format_args!(""),
+            format_args!(
+                "binary config version {} is not supported, expected {}",
+                data[4], BINARY_VERSION
+            ),
+        ));
+    }
+
+    let mut len = [0; 4];
+    len.copy_from_slice(&data[5..BINARY_HEADER_LEN]);
+    let body = data[BINARY_HEADER_LEN..]
+        .get(..u32::from_le_bytes(len) as usize)
+        .ok_or_else(|| postcard_error(postcard::Error::DeserializeUnexpectedEnd))?;
+    postcard::from_bytes(body).map_err(postcard_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        channel: u8,
+        name: heapless::String<8>,
+    }
+
+    fn config() -> Config {
+        Config {
+            channel: 3,
+            name: "fader".try_into().unwrap(),
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut buf = [0; 32];
+        let data = to_binary(&config(), &mut buf).unwrap();
+
+        assert_eq!(&data[..5], b"RCTL\x01");
+        assert_eq!(data.len(), BINARY_HEADER_LEN + 7);
+        assert_eq!(from_binary::<Config>(data).unwrap(), config());
+        assert_eq!(to_binary_vec(&config()).unwrap(), data);
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        let mut buf = [0; 12];
+        let err = to_binary(&config(), &mut buf).unwrap_err();
+        assert_eq!(err.kind, ConfigErrorKind::Capacity);
+    }
+
+    #[test]
+    fn reject_foreign_data() {
+        let mut data = to_binary_vec(&config()).unwrap();
+
+        assert_eq!(
+            from_binary::<Config>(b"channel: 3").unwrap_err().message,
+            "not a binary config"
+        );
+
+        data[4] = 2;
+        let err = from_binary::<Config>(&data).unwrap_err();
+        assert_eq!(err.kind, ConfigErrorKind::Version);
+
+        data[4] = BINARY_VERSION;
+        data.truncate(data.len() - 1);
+        let err = from_binary::<Config>(&data).unwrap_err();
+        assert_eq!(err.kind, ConfigErrorKind::Syntax);
+    }
+}
@@ -0,0 +1,136 @@
+use super::{ConfigError, ConfigErrorKind, CONFIG_VERSION};
+use crate::ui::SLOT_PINS;
+
+use serde_yaml::{Mapping, Value};
+
+/*
+ * Upgrades the document of the given version to the next one.
+ */
+type Migration = fn(&mut Mapping);
+
+/*
+ * Migration from version n to n + 1 at index n - 1.
+ */
+const MIGRATIONS: [Migration; CONFIG_VERSION as usize - 1] = [v1_addresses];
+
+/*
+ * Upgrades a config document to CONFIG_VERSION and writes the version into
+ * it. Documents without version are taken as version 1. Returns whether
+ * the migrations changed anything besides the version.
+ */
+pub fn migrate(doc: &mut Value) -> Result<bool, ConfigError> {
+    let Value::Mapping(doc) = doc else {
+        // left for the deserializer to complain about
+        return Ok(false);
+    };
+    let version = match doc.get("version") {
+        None => 1,
+        Some(v) => v.as_u64().filter(|v| *v >= 1).ok_or_else(|| {
+            ConfigError::new(
+                ConfigErrorKind::Syntax,
+                format_args!("version"),
+                format_args!("version has to be a number from 1"),
+            )
+        })?,
+    };
+    if version > CONFIG_VERSION as u64 {
+        return Err(ConfigError::new(
+            ConfigErrorKind::Version,
+            format_args!("version"),
+            format_args!(
+                "config version {} is newer than the supported {}",
+                version, CONFIG_VERSION
+            ),
+        ));
+    }
+
+    doc.remove("version");
+    let original = doc.clone();
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(doc);
+    }
+    let changed = *doc != original;
+
+    // the version goes first
+    let mut versioned = Mapping::new();
+    versioned.insert("version".into(), CONFIG_VERSION.into());
+    versioned.extend(core::mem::take(doc));
+    *doc = versioned;
+    Ok(changed)
+}
+
+/*
+ * Upgrades a YAML config, None if the migrations left it unchanged or it
+ * isn't valid YAML.
+ */
+pub fn migrate_yaml(config: &str) -> Result<Option<String>, ConfigError> {
+    let Ok(mut doc) = serde_yaml::from_str::<Value>(config) else {
+        return Ok(None);
+    };
+    if !migrate(&mut doc)? {
+        return Ok(None);
+    }
+    serde_yaml::to_string(&doc).map(Some).map_err(super::syntax)
+}
+
+/*
+ * Like `migrate_yaml` for a JSON config.
+ */
+pub fn migrate_json(config: &str) -> Result<Option<String>, ConfigError> {
+    let Ok(mut doc) = serde_json::from_str::<Value>(config) else {
+        return Ok(None);
+    };
+    if !migrate(&mut doc)? {
+        return Ok(None);
+    }
+    serde_json::to_string(&doc).map(Some).map_err(super::syntax)
+}
+
+/*
+ * Variant name and fields of an enum value, given as YAML tag or as map
+ * with a single key like in JSON.
+ */
+fn variant(value: &mut Value) -> Option<(String, &mut Mapping)> {
+    match value {
+        Value::Tagged(tagged) => {
+            let name = tagged.tag.to_string();
+            let name = name.trim_start_matches('!').into();
+            tagged.value.as_mapping_mut().map(|m| (name, m))
+        }
+        Value::Mapping(m) if m.len() == 1 => {
+            let (name, fields) = m.iter_mut().next()?;
+            let name = name.as_str()?.into();
+            fields.as_mapping_mut().map(|m| (name, m))
+        }
+        _ => None,
+    }
+}
+
+/*
+ * Version 1 had no input addresses, the inputs read the mux lines one
+ * after the other in the order they were listed. Each gets the address of
+ * the lines it used to read.
+ */
+fn v1_addresses(doc: &mut Mapping) {
+    let Some(Value::Sequence(inputs)) = doc.get_mut("inputs") else {
+        return;
+    };
+    let mut line = 0u64;
+    for input in inputs {
+        let Some((name, fields)) = variant(input) else {
+            continue;
+        };
+        let lines = match name.as_str() {
+            "Encoder" => 2,
+            "Potentiometer" | "Button" => 1,
+            _ => continue,
+        };
+        if !fields.contains_key("address") {
+            let mut address = Mapping::new();
+            address.insert("slot".into(), (line / SLOT_PINS as u64).into());
+            address.insert("pin".into(), (line % SLOT_PINS as u64).into());
+            fields.insert("address".into(), address.into());
+        }
+        line += lines;
+    }
+}
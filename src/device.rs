@@ -1,61 +1,660 @@
-use crate::output::{OutputData, OutputType};
+use crate::clock::{
+    ClockFollower, ClockGenerator, ClockState, CLOCK_BPM_MAX, CLOCK_BPM_MIN, CLOCK_INPUT,
+};
+use crate::config::{self, ConfigError, ConfigErrorKind, Mapping};
+use crate::output::{
+    route, Coalescer, Event, Filter, MidiPort, NoteStage, OutputData, OutputError, OutputStats,
+    OutputType, Route, Thru, ARPEGGIATOR_INPUT,
+};
+use crate::preset::Preset;
+use crate::sequencer::{Sequencer, SEQUENCER_INPUT};
+use crate::time::{self, Timer};
 use crate::ui::backend::InMemoryBackend;
 use crate::ui::Backend;
-use crate::ui::{Input, InputType};
+use crate::ui::{Chain, Input, InputType, BOARD_SLOTS};
 
 use heapless::Vec;
 use serde::{Deserialize, Serialize};
 
-const DEVICE_INPUTS_MAX: usize = 2;
+mod slots;
+
+pub use self::slots::Slots;
+
+/*
+ * Inputs a device holds unless given another capacity, the slots of a
+ * board.
+ */
+pub const DEVICE_INPUTS_MAX: usize = 8;
+const DEVICE_ROUTES_MAX: usize = 8;
+const DEVICE_OUTPUTS_MAX: usize = 8;
+const DEVICE_COALESCE_MAX: usize = 16;
+const DEVICE_THRU_MAX: usize = 4;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
-pub struct Device {
-    inputs: Vec<InputType, DEVICE_INPUTS_MAX>,
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Device<const N: usize = DEVICE_INPUTS_MAX> {
+    // boards of the chain, a single one if not given
+    #[serde(default)]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(length(min = 1, max = "crate::ui::CHAIN_BOARDS_MAX"))
+    )]
+    boards: Chain,
+    inputs: Slots<InputType, N>,
+    #[serde(default)]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "std::vec::Vec<Route>", length(max = "DEVICE_ROUTES_MAX"))
+    )]
+    routes: Vec<Route, DEVICE_ROUTES_MAX>,
+    // flush window of the CC coalescing in microseconds, disabled if unset
+    #[serde(default)]
+    coalesce_window: Option<u32>,
+    #[serde(default)]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "std::vec::Vec<Thru>", length(max = "DEVICE_THRU_MAX"))
+    )]
+    thru: Vec<Thru, DEVICE_THRU_MAX>,
+    #[serde(default)]
+    clock: ClockGenerator,
+    #[serde(default)]
+    clock_follower: ClockFollower,
+    #[serde(default)]
+    sequencer: Option<Sequencer>,
+    // chords or arpeggiator for notes on their way to the outputs
+    #[serde(default)]
+    notes: Option<NoteStage>,
+    #[serde(default)]
+    preset: Option<Preset>,
+    #[serde(skip)]
+    updated: Slots<usize, N>,
     #[serde(skip)]
-    updated: Vec<usize, 4>,
+    output_stats: Vec<OutputStats, DEVICE_OUTPUTS_MAX>,
+    #[serde(skip)]
+    coalescer: Coalescer<DEVICE_COALESCE_MAX>,
 }
 
 impl Device {
     pub fn new() -> Self {
+        Self::sized()
+    }
+
+    /*
+     * Loads a device from its YAML config, which has to pass `validate`.
+     */
+    #[cfg(target_os = "linux")]
+    pub fn from_config(config: &str) -> Result<Self, ConfigError> {
+        Self::from_config_sized(config)
+    }
+
+    /*
+     * Loads a device from the JSON form of the config.
+     */
+    #[cfg(target_os = "linux")]
+    pub fn from_json(config: &str) -> Result<Self, ConfigError> {
+        Self::from_json_sized(config)
+    }
+
+    /*
+     * Loads a device from a config written by `to_binary`, which has to
+     * pass `validate` as well.
+     */
+    pub fn from_binary(data: &[u8]) -> Result<Self, ConfigError> {
+        Self::from_binary_sized(data)
+    }
+}
+
+impl Default for Device {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Device<N> {
+    /*
+     * Device holding up to N inputs.
+     */
+    pub fn sized() -> Self {
         Self {
-            inputs: Vec::new(),
-            updated: Vec::new(),
+            boards: Chain::default(),
+            inputs: Slots::new(),
+            routes: Vec::new(),
+            coalesce_window: None,
+            thru: Vec::new(),
+            clock: ClockGenerator::default(),
+            clock_follower: ClockFollower::default(),
+            sequencer: None,
+            notes: None,
+            preset: None,
+            updated: Slots::new(),
+            output_stats: Vec::new(),
+            coalescer: Coalescer::new(),
+        }
+    }
+
+    /*
+     * Like `from_config` for a device holding up to N inputs.
+     */
+    #[cfg(target_os = "linux")]
+    pub fn from_config_sized(config: &str) -> Result<Self, ConfigError> {
+        // configs left alone by the migrations are read as they are to
+        // keep the error locations, the ones of a migrated config point
+        // into the rewritten document and are dropped
+        let migrated = config::migrate_yaml(config)?;
+        let de = serde_yaml::Deserializer::from_str(migrated.as_deref().unwrap_or(config));
+        let device: Self = serde_path_to_error::deserialize(de).map_err(|e| {
+            let err = ConfigError::from_yaml(e);
+            match migrated {
+                Some(_) => err.without_location(),
+                None => err,
+            }
+        })?;
+        device.loaded()
+    }
+
+    /*
+     * Like `from_json` for a device holding up to N inputs.
+     */
+    #[cfg(target_os = "linux")]
+    pub fn from_json_sized(config: &str) -> Result<Self, ConfigError> {
+        let migrated = config::migrate_json(config)?;
+        let mut de = serde_json::Deserializer::from_str(migrated.as_deref().unwrap_or(config));
+        let device: Self = serde_path_to_error::deserialize(&mut de).map_err(|e| {
+            let err = ConfigError::from_json(e);
+            match migrated {
+                Some(_) => err.without_location(),
+                None => err,
+            }
+        })?;
+        device.loaded()
+    }
+
+    /*
+     * Like `from_binary` for a device holding up to N inputs.
+     */
+    pub fn from_binary_sized(data: &[u8]) -> Result<Self, ConfigError> {
+        let device: Self = config::from_binary(data)?;
+        device.loaded()
+    }
+
+    /*
+     * Checks the values of a loaded config before the handlers of the
+     * preset are attached, those are checked against the handlers left
+     * over afterwards.
+     */
+    fn loaded(mut self) -> Result<Self, ConfigError> {
+        self.validate()?;
+        if let Some(preset) = &self.preset {
+            preset.apply(&mut self.inputs);
+            self.validate()?;
+        }
+        Ok(self)
+    }
+
+    /*
+     * Writes the config in the compact binary format into the buffer and
+     * returns the part used.
+     */
+    pub fn to_binary<'a>(&self, buf: &'a mut [u8]) -> Result<&'a mut [u8], ConfigError> {
+        config::to_binary(self, buf)
+    }
+
+    /*
+     * Writes the YAML config, starting with the version of its layout.
+     */
+    #[cfg(target_os = "linux")]
+    pub fn to_config(&self) -> Result<std::string::String, ConfigError> {
+        serde_yaml::to_string(&self.versioned()).map_err(config::syntax)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn to_json(&self) -> Result<std::string::String, ConfigError> {
+        serde_json::to_string_pretty(&self.versioned()).map_err(config::syntax)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn versioned(&self) -> config::Versioned<'_, Self> {
+        config::Versioned {
+            version: config::CONFIG_VERSION,
+            config: self,
         }
     }
 
     #[cfg(target_os = "linux")]
-    pub fn from_config(config: &str) -> Self {
-        let mut device: Self = serde_yaml::from_str(config).unwrap();
-        device
+    pub fn to_binary_vec(&self) -> Result<std::vec::Vec<u8>, ConfigError> {
+        config::to_binary_vec(self)
+    }
+
+    /*
+     * Rejects values MIDI can't carry, inputs mapped to the same message
+     * and references to inputs or outputs beyond the capacities.
+     */
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for idx in 0..self.inputs.len() {
+            let Some(mapping) = config::input(idx, &self.inputs)? else {
+                continue;
+            };
+            let other = (0..idx).find(|i| config::input(*i, &self.inputs) == Ok(Some(mapping)));
+            if let Some(other) = other {
+                return Err(ConfigError::new(
+                    ConfigErrorKind::Duplicate,
+                    format_args!("inputs[{}].handler", idx),
+                    format_args!("sends the same message as input {}", other),
+                )
+                .with_input(idx));
+            }
+        }
+
+        if self.boards.is_empty() {
+            return Err(ConfigError::new(
+                ConfigErrorKind::Capacity,
+                format_args!("boards"),
+                format_args!("at least one board is needed"),
+            ));
+        }
+        for (idx, board) in self.boards.boards().iter().enumerate() {
+            if board.slots == 0 || board.slots > BOARD_SLOTS {
+                return Err(ConfigError::new(
+                    ConfigErrorKind::OutOfRange,
+                    format_args!("boards[{}].slots", idx),
+                    format_args!("{} slots are not within 1 to {}", board.slots, BOARD_SLOTS),
+                ));
+            }
+        }
+
+        let bpm = self.clock.bpm();
+        if !(CLOCK_BPM_MIN..=CLOCK_BPM_MAX).contains(&bpm) {
+            return Err(ConfigError::new(
+                ConfigErrorKind::OutOfRange,
+                format_args!("clock.bpm"),
+                format_args!(
+                    "{} BPM are not within {} to {}",
+                    bpm, CLOCK_BPM_MIN, CLOCK_BPM_MAX
+                ),
+            ));
+        }
+
+        for idx in 0..self.inputs.len() {
+            let Some((address, lines)) = config::address(idx, &self.inputs, &self.boards)? else {
+                continue;
+            };
+            let overlaps = |other: &InputType| match other.lines() {
+                Some((a, n)) => {
+                    a.board == address.board
+                        && a.slot == address.slot
+                        && a.pin < address.pin + lines
+                        && address.pin < a.pin + n
+                }
+                None => false,
+            };
+            if let Some(other) = self.inputs[..idx].iter().position(overlaps) {
+                return Err(ConfigError::new(
+                    ConfigErrorKind::Duplicate,
+                    format_args!("inputs[{}].address", idx),
+                    format_args!("uses the same mux lines as input {}", other),
+                )
+                .with_input(idx));
+            }
+        }
+
+        for (idx, r) in self.routes.iter().enumerate() {
+            if r.output >= DEVICE_OUTPUTS_MAX {
+                return Err(ConfigError::new(
+                    ConfigErrorKind::Capacity,
+                    format_args!("routes[{}].output", idx),
+                    format_args!(
+                        "output {} exceeds the {} outputs",
+                        r.output, DEVICE_OUTPUTS_MAX
+                    ),
+                ));
+            }
+            if let Some(c) = r.channel {
+                config::channel(c, format_args!("routes[{}].channel", idx))?;
+            }
+            self.validate_filter(&r.filter, format_args!("routes[{}].filter", idx))?;
+        }
+
+        if let Some(notes) = &self.notes {
+            self.validate_filter(&notes.filter, format_args!("notes.filter"))?;
+        }
+
+        for (idx, track) in self
+            .sequencer
+            .iter()
+            .flat_map(|s| s.tracks.iter())
+            .enumerate()
+        {
+            config::channel(
+                track.channel,
+                format_args!("sequencer.tracks[{}].channel", idx),
+            )?;
+            if let Some(control) = track.control {
+                config::data(control, format_args!("sequencer.tracks[{}].control", idx))?;
+            }
+            for (s, step) in track.steps.iter().enumerate() {
+                let path = "sequencer.tracks";
+                config::data(
+                    step.note,
+                    format_args!("{}[{}].steps[{}].note", path, idx, s),
+                )?;
+                config::data(
+                    step.velocity,
+                    format_args!("{}[{}].steps[{}].velocity", path, idx, s),
+                )?;
+                config::data(
+                    step.cc.unwrap_or_default(),
+                    format_args!("{}[{}].steps[{}].cc", path, idx, s),
+                )?;
+            }
+            if track.length.is_some_and(|l| l as usize > track.steps.len()) {
+                return Err(ConfigError::new(
+                    ConfigErrorKind::Capacity,
+                    format_args!("sequencer.tracks[{}].length", idx),
+                    format_args!("exceeds the {} steps of the track", track.steps.len()),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_filter(
+        &self,
+        filter: &Filter,
+        path: core::fmt::Arguments,
+    ) -> Result<(), ConfigError> {
+        for c in filter.channels.iter().flatten() {
+            config::channel(*c, format_args!("{}.channels", path))?;
+        }
+        if let Some(i) = filter
+            .inputs
+            .iter()
+            .flatten()
+            .find(|i| **i >= self.inputs.len())
+        {
+            return Err(ConfigError::new(
+                ConfigErrorKind::Reference,
+                format_args!("{}.inputs", path),
+                format_args!("input {} doesn't exist", i),
+            ));
+        }
+        Ok(())
     }
 
     pub fn add_input(&mut self, input: InputType) -> Result<(), InputType> {
         self.inputs.push(input)
     }
 
+    pub fn inputs(&self) -> &[InputType] {
+        &self.inputs
+    }
+
+    /*
+     * MIDI message the input at the index is mapped to, if any.
+     */
+    pub fn mapping(&self, idx: usize) -> Option<Mapping> {
+        config::input(idx, &self.inputs).ok().flatten()
+    }
+
+    /*
+     * Replaces the input at the index, the device is left unchanged if it
+     * doesn't pass `validate` afterwards.
+     */
+    pub fn replace_input(&mut self, idx: usize, input: InputType) -> Result<(), ConfigError> {
+        let Some(slot) = self.inputs.get_mut(idx) else {
+            return Err(ConfigError::new(
+                ConfigErrorKind::Reference,
+                format_args!("inputs[{}]", idx),
+                format_args!("input {} doesn't exist", idx),
+            ));
+        };
+        let previous = core::mem::replace(slot, input);
+        if let Err(e) = self.validate() {
+            self.inputs[idx] = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn add_route(&mut self, route: Route) -> Result<(), ConfigError> {
+        self.routes.push(route).map_err(|_| {
+            ConfigError::new(
+                ConfigErrorKind::Capacity,
+                format_args!("routes"),
+                format_args!("exceeds the {} routes", DEVICE_ROUTES_MAX),
+            )
+        })
+    }
+
+    pub fn set_coalesce_window(&mut self, window: Option<u32>) {
+        self.coalesce_window = window;
+    }
+
+    pub fn add_thru(&mut self, thru: Thru) -> Result<(), Thru> {
+        self.thru.push(thru)
+    }
+
+    /*
+     * Forwards a byte received on a MIDI port to the merge outputs of all
+     * ports configured as its thru destination.
+     */
+    pub fn thru(
+        &self,
+        outputs: &[OutputType],
+        from: MidiPort,
+        byte: u8,
+    ) -> Result<(), OutputError> {
+        let mut result = Ok(());
+        for ot in outputs {
+            let OutputType::MergeOut(o) = ot else {
+                continue;
+            };
+            if self.thru.iter().any(|t| t.from == from && t.to == o.port()) {
+                result = result.and(o.input(byte));
+            }
+        }
+        result
+    }
+
+    pub fn clock(&self) -> &ClockGenerator {
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut ClockGenerator {
+        &mut self.clock
+    }
+
+    pub fn set_sequencer(&mut self, sequencer: Option<Sequencer>) {
+        self.sequencer = sequencer;
+    }
+
+    pub fn sequencer(&self) -> Option<&Sequencer> {
+        self.sequencer.as_ref()
+    }
+
+    /*
+     * Attaches the handlers of the preset to the inputs added so far.
+     */
+    pub fn set_preset(&mut self, preset: Option<Preset>) {
+        if let Some(preset) = &preset {
+            preset.apply(&mut self.inputs);
+        }
+        self.preset = preset;
+    }
+
+    pub fn preset(&self) -> Option<&Preset> {
+        self.preset.as_ref()
+    }
+
+    pub fn boards(&self) -> &Chain {
+        &self.boards
+    }
+
+    pub fn set_boards(&mut self, boards: Chain) {
+        self.boards = boards;
+    }
+
+    /*
+     * Checks the configured boards are present, if the backend is able to
+     * detect the length of the chain.
+     */
+    pub fn detect_boards(&self, backend: &mut impl Backend) -> Result<(), ConfigError> {
+        match backend.detect_boards() {
+            Some(found) if found < self.boards.len() => Err(ConfigError::new(
+                ConfigErrorKind::Reference,
+                format_args!("boards"),
+                format_args!(
+                    "{} boards configured but only {} found",
+                    self.boards.len(),
+                    found
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /*
+     * Replaces the note stage, the notes the old stage started are ended
+     * first so that none keeps hanging.
+     */
+    pub async fn set_note_stage(&mut self, notes: Option<NoteStage>, outputs: &[OutputType]) {
+        if let Some(mut old) = self.notes.take() {
+            let event = Event {
+                input: ARPEGGIATOR_INPUT,
+                input_type: "NoteStage",
+                handler: "NoteStage",
+                timestamp: time::now(),
+            };
+            while let Some(output_data) = old.release() {
+                self.send(outputs, &output_data, &event).await;
+            }
+        }
+        self.notes = notes;
+    }
+
+    /*
+     * Ends the notes the sequencer and the note stage started, e.g. before
+     * the device is replaced by a new config.
+     */
+    pub async fn release_notes(&mut self, outputs: &[OutputType]) {
+        let now = time::now();
+        let bpm = self.clock.bpm();
+        if let Some(sequencer) = self.sequencer.as_mut() {
+            // a stop moves the pending note offs to now and drops the rest
+            sequencer.receive(&OutputData::Stop, now, bpm);
+        }
+        let event = Event {
+            input: SEQUENCER_INPUT,
+            input_type: "Sequencer",
+            handler: "Track",
+            timestamp: now,
+        };
+        while let Some(output_data) = self.sequencer.as_mut().and_then(|s| s.poll(now)) {
+            self.send(outputs, &output_data, &event).await;
+        }
+
+        let event = Event {
+            input: ARPEGGIATOR_INPUT,
+            input_type: "NoteStage",
+            handler: "NoteStage",
+            timestamp: now,
+        };
+        while let Some(output_data) = self.notes.as_mut().and_then(|n| n.release()) {
+            self.send(outputs, &output_data, &event).await;
+        }
+    }
+
+    /*
+     * State of the external clock while one comes in, otherwise of the
+     * internal clock generator.
+     */
+    pub fn clock_state(&self, now: u64) -> ClockState {
+        let f = &self.clock_follower;
+        if !f.is_active(now) {
+            return self.clock.state();
+        }
+        ClockState {
+            bpm: f.bpm().unwrap_or(self.clock.bpm()),
+            running: f.is_running(),
+            song_position: f.song_position(),
+            external: true,
+        }
+    }
+
+    /*
+     * Health counters of the outputs passed to `run_handler`, in the same
+     * order.
+     */
+    pub fn output_stats(&self) -> &[OutputStats] {
+        &self.output_stats
+    }
+
+    /*
+     * Feeds MIDI data received from the host back into the handlers, so
+     * that their state follows parameter changes made on the other side.
+     * Clock and transport messages drive the clock follower and reach the
+     * handlers as well.
+     */
+    pub fn receive(&mut self, data: &OutputData) {
+        self.receive_at(data, time::now());
+    }
+
+    /*
+     * Like `receive` with the time of reception in microseconds.
+     */
+    pub fn receive_at(&mut self, data: &OutputData, now: u64) {
+        self.clock_follower.receive(data, now);
+        self.sequence(data, now);
+        if let Some(preset) = self.preset.as_mut() {
+            preset.receive(data);
+        }
+        for input in self.inputs.iter_mut() {
+            input.receive(data);
+        }
+    }
+
+    /*
+     * Feeds raw bytes received from the host to the preset, which parses
+     * SysEx feedback from them.
+     */
+    pub fn receive_byte(&mut self, byte: u8) {
+        if let Some(preset) = self.preset.as_mut() {
+            preset.input(byte);
+        }
+    }
+
     pub async fn init_inputs(&mut self, backend: &mut impl Backend) {
         for (idx, input) in self.inputs.iter_mut().enumerate() {
             match input {
                 InputType::Encoder(i) => i.init(backend).await,
                 InputType::Potentiometer(i) => i.init(backend).await,
-                _ => (),
+                InputType::Button(i) => i.init(backend).await,
+                InputType::Generator(_) => (),
             };
         }
-        backend.rewind();
     }
 
-    pub async fn update(&mut self, backend: &mut impl Backend) {
+    /*
+     * Reads the inputs from the backend, returns whether any of them
+     * changed.
+     */
+    pub async fn update(&mut self, backend: &mut impl Backend) -> bool {
+        let mut changed = false;
         for (idx, input) in self.inputs.iter_mut().enumerate() {
             let was_updated = match input {
                 InputType::Encoder(i) => i.update(backend).await,
                 InputType::Potentiometer(i) => i.update(backend).await,
+                InputType::Button(i) => i.update(backend).await,
+                InputType::Generator(i) => i.update(backend).await,
             };
 
             if was_updated {
-                self.updated.push(idx);
+                // one slot per input, this can't run out
+                self.updated.push(idx).ok();
+                changed = true;
             }
         }
-        backend.rewind();
+        changed
     }
 
     pub async fn run_handler(&mut self, outputs: &[OutputType]) {
@@ -67,17 +666,194 @@ impl Device {
             let output_data = match input {
                 InputType::Encoder(i) => i.run_handler(),
                 InputType::Potentiometer(i) => i.run_handler(),
+                InputType::Button(i) => i.run_handler(),
+                InputType::Generator(i) => i.run_handler(),
+            };
+            if output_data == OutputData::Dummy {
+                continue;
+            }
+            let event = Event {
+                input: i,
+                input_type: input.name(),
+                handler: input.handler_name(),
+                timestamp: time::now(),
             };
 
-            for ot in outputs {
-                match ot {
-                    OutputType::StdOut(o) => o.run(&output_data).await,
-                    #[cfg(target_os = "none")]
-                    OutputType::UsbOut(o) => o.run(&output_data).await,
-                    _ => (),
+            if let OutputData::ClockControl(control) = output_data {
+                if let Some(output_data) = self.clock.control(control) {
+                    self.sequence(&output_data, event.timestamp);
+                    self.send(outputs, &output_data, &event).await;
+                }
+                continue;
+            }
+
+            if let OutputData::GeneratorControl(control) = output_data {
+                if let Some(InputType::Generator(g)) = self.inputs.get_mut(control.input) {
+                    g.control(control.param, control.delta);
                 }
+                continue;
+            }
+
+            let Some(notes) = self.notes.as_mut() else {
+                self.dispatch(outputs, output_data, event).await;
+                continue;
+            };
+            for output_data in notes.process(output_data, &event) {
+                self.dispatch(outputs, output_data, event).await;
             }
         }
         self.updated.clear();
+
+        if let Some(window) = self.coalesce_window {
+            if self.coalescer.is_due(time::now(), window as u64) {
+                self.flush(outputs).await;
+            }
+        }
+    }
+
+    /*
+     * Sends the clock messages due at the given time in microseconds. The
+     * internal clock stays silent while following an external clock and
+     * takes over once it timed out. Sequencer and arpeggiator notes due are
+     * sent as well.
+     * Generator inputs are moved forward to the given time, new values are
+     * sent by the next `run_handler`.
+     */
+    pub async fn tick(&mut self, outputs: &[OutputType], now: u64) {
+        if !self.clock_follower.is_active(now) {
+            if self.clock_follower.timed_out(now) {
+                self.clock.sync(&self.clock_follower);
+                self.clock_follower.reset();
+            }
+
+            let event = Event {
+                input: CLOCK_INPUT,
+                input_type: "Clock",
+                handler: "ClockGenerator",
+                timestamp: now,
+            };
+            while let Some(output_data) = self.clock.tick(now) {
+                self.sequence(&output_data, now);
+                self.send(outputs, &output_data, &event).await;
+            }
+        }
+
+        let event = Event {
+            input: SEQUENCER_INPUT,
+            input_type: "Sequencer",
+            handler: "Track",
+            timestamp: now,
+        };
+        while let Some(output_data) = self.sequencer.as_mut().and_then(|s| s.poll(now)) {
+            self.send(outputs, &output_data, &event).await;
+        }
+
+        let clock = self.clock_state(now);
+        if let Some(notes) = self.notes.as_mut() {
+            let event = Event {
+                input: ARPEGGIATOR_INPUT,
+                input_type: "Arpeggiator",
+                handler: "NoteStage",
+                timestamp: now,
+            };
+            for output_data in notes.tick(now, clock.bpm) {
+                self.dispatch(outputs, output_data, event).await;
+            }
+        }
+
+        for (idx, input) in self.inputs.iter_mut().enumerate() {
+            if let InputType::Generator(g) = input {
+                if g.advance(now, &clock) && !self.updated.contains(&idx) {
+                    self.updated.push(idx).ok();
+                }
+            }
+        }
+    }
+
+    /*
+     * Time in microseconds `tick` has to run at next for the clock, None
+     * while it is stopped. While following an external clock the internal
+     * one is idle, the device only has to wake up when the external clock
+     * times out.
+     */
+    pub fn deadline(&self, now: u64) -> Option<u64> {
+        if self.clock_follower.is_active(now) {
+            return self.clock_follower.timeout_at();
+        }
+        self.clock.deadline()
+    }
+
+    /*
+     * Waits for the next clock and sends it, returns right away while the
+     * clock is stopped.
+     */
+    pub async fn run_clock(&mut self, outputs: &[OutputType], timer: &mut impl Timer) {
+        if let Some(deadline) = self.deadline(timer.now()) {
+            timer.sleep_until(deadline).await;
+        }
+        self.tick(outputs, timer.now()).await;
+    }
+
+    /*
+     * Passes clock and transport messages on to the sequencer and the note
+     * stage.
+     */
+    fn sequence(&mut self, data: &OutputData, now: u64) {
+        let bpm = self.clock_state(now).bpm;
+        if let Some(sequencer) = self.sequencer.as_mut() {
+            sequencer.receive(data, now, bpm);
+        }
+        if let Some(notes) = self.notes.as_mut() {
+            notes.receive(data, now);
+        }
+    }
+
+    /*
+     * Sends output data right away or hands it to the CC coalescing.
+     */
+    async fn dispatch(&mut self, outputs: &[OutputType], output_data: OutputData, event: Event) {
+        if self.coalesce_window.is_none() {
+            self.send(outputs, &output_data, &event).await;
+        } else if let Err((output_data, event)) = self.coalescer.push(output_data, event) {
+            self.flush(outputs).await;
+            self.coalescer.push(output_data, event).ok();
+        }
+    }
+
+    /*
+     * Sends all output data held back by the CC coalescing right away.
+     */
+    pub async fn flush(&mut self, outputs: &[OutputType]) {
+        for (output_data, event) in self.coalescer.flush() {
+            self.send(outputs, &output_data, &event).await;
+        }
+    }
+
+    async fn send(&mut self, outputs: &[OutputType], output_data: &OutputData, event: &Event) {
+        self.output_stats
+            .resize_default(outputs.len().min(DEVICE_OUTPUTS_MAX))
+            .ok();
+
+        for (idx, ot) in outputs.iter().enumerate() {
+            let Some(output_data) = route(&self.routes, idx, output_data, event) else {
+                continue;
+            };
+            let result = match ot {
+                OutputType::StdOut(o) => o.run(&output_data).await,
+                OutputType::MergeOut(o) => o.run(&output_data).await,
+                #[cfg(target_os = "linux")]
+                OutputType::RtpMidiOut(o) => o.run(&output_data).await,
+                #[cfg(target_os = "linux")]
+                OutputType::SmfOut(o) => o.run(&output_data, event).await,
+                #[cfg(target_os = "linux")]
+                OutputType::JsonOut(o) => o.run(&output_data, event).await,
+                #[cfg(target_os = "none")]
+                OutputType::UsbOut(o) => o.run(&output_data).await,
+                OutputType::MidiOut(_) => Err(OutputError::Unsupported),
+            };
+            if let Some(stats) = self.output_stats.get_mut(idx) {
+                stats.record(result);
+            }
+        }
     }
 }
@@ -0,0 +1,129 @@
+use core::ops::{Deref, DerefMut};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/*
+ * List holding at most N items, kept on the heap on Linux so large
+ * capacities don't weigh on the stack.
+ */
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Slots<T, const N: usize> {
+    #[cfg(target_os = "linux")]
+    items: std::vec::Vec<T>,
+    #[cfg(target_os = "none")]
+    items: heapless::Vec<T, N>,
+}
+
+impl<T, const N: usize> Default for Slots<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Slots<T, N> {
+    pub fn new() -> Self {
+        Self {
+            items: Default::default(),
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /*
+     * Hands the item back if all slots are taken.
+     */
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        if self.items.len() >= N {
+            return Err(item);
+        }
+        #[cfg(target_os = "linux")]
+        self.items.push(item);
+        #[cfg(target_os = "none")]
+        self.items.push(item)?;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.items.pop()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+impl<T, const N: usize> Deref for Slots<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.items
+    }
+}
+
+impl<T, const N: usize> DerefMut for Slots<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.items
+    }
+}
+
+impl<'de, T: Deserialize<'de>, const N: usize> Deserialize<'de> for Slots<T, N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[cfg(target_os = "linux")]
+        let items = std::vec::Vec::<T>::deserialize(deserializer)?;
+        #[cfg(target_os = "none")]
+        let items = heapless::Vec::<T, N>::deserialize(deserializer)?;
+
+        if items.len() > N {
+            return Err(D::Error::invalid_length(
+                items.len(),
+                &"no more items than the device capacity",
+            ));
+        }
+        Ok(Self { items })
+    }
+}
+
+#[cfg(feature = "schema")]
+impl<T: schemars::JsonSchema, const N: usize> schemars::JsonSchema for Slots<T, N> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> std::string::String {
+        format!("Slots_{}_{}", T::schema_name(), N)
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        let mut schema = gen.subschema_for::<std::vec::Vec<T>>().into_object();
+        schema.array().max_items = Some(N as u32);
+        schema.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity() {
+        let mut s: Slots<u8, 2> = Slots::new();
+
+        assert_eq!(s.push(1), Ok(()));
+        assert_eq!(s.push(2), Ok(()));
+        assert_eq!(s.push(3), Err(3));
+        assert_eq!(&s[..], [1, 2]);
+        assert_eq!(s.pop(), Some(2));
+    }
+
+    #[test]
+    fn deserialize_over_capacity() {
+        let s: Result<Slots<u8, 2>, _> = serde_json::from_str("[1, 2, 3]");
+        assert!(s.unwrap_err().to_string().starts_with("invalid length 3"));
+
+        let s: Slots<u8, 2> = serde_json::from_str("[1, 2]").unwrap();
+        assert_eq!(&s[..], [1, 2]);
+    }
+}
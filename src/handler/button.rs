@@ -0,0 +1,69 @@
+use crate::clock::ClockControl;
+#[cfg(feature = "schema")]
+use crate::config::{MIDI_CHANNEL_MAX, MIDI_DATA_MAX};
+use crate::handler::McuButton;
+use crate::output::{MidiMsgNote, OutputData};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ButtonHandler {
+    Dummy,
+    Transport(Transport),
+    MidiNote(MidiNote),
+    Mcu(McuButton),
+}
+
+/*
+ * Sends a command to the clock generator when the button is pressed.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Transport {
+    pub control: ClockControl,
+}
+
+/*
+ * Plays a note while the button is held.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MidiNote {
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_CHANNEL_MAX")))]
+    pub channel: u8,
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
+    pub key: u8,
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
+    pub velocity: u8,
+}
+
+impl ButtonHandler {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ButtonHandler::Dummy => "Dummy",
+            ButtonHandler::Transport(_) => "Transport",
+            ButtonHandler::MidiNote(_) => "MidiNote",
+            ButtonHandler::Mcu(_) => "Mcu",
+        }
+    }
+}
+
+impl Transport {
+    pub fn run(&mut self, pressed: bool) -> OutputData {
+        if !pressed {
+            return OutputData::Dummy;
+        }
+        OutputData::ClockControl(self.control)
+    }
+}
+
+impl MidiNote {
+    pub fn run(&mut self, pressed: bool) -> OutputData {
+        OutputData::MidiMsgNote(MidiMsgNote {
+            channel: self.channel,
+            key: self.key,
+            on: pressed,
+            velocity: if pressed { self.velocity } else { 0 },
+        })
+    }
+}
@@ -1,34 +1,92 @@
+use crate::clock::ClockControl;
+#[cfg(feature = "schema")]
+use crate::config::{MIDI_CHANNEL_MAX, MIDI_DATA_MAX};
+use crate::handler::VPot;
 use crate::output::{MidiMsgCc, OutputData};
-use crate::ui::input::{Encoder, EncoderDirection};
+use crate::ui::input::{Encoder, EncoderDirection, GeneratorControl, GeneratorParam};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum EncoderHandler {
     Dummy,
     MidiRel(MidiRel),
     MidiAbs(MidiAbs),
+    Tempo(Tempo),
+    Modulation(Modulation),
+    VPot(VPot),
     //MidiNote(MidiNote),
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MidiRel {
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_CHANNEL_MAX")))]
     pub channel: u8,
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
     pub control: u8,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MidiAbs {
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_CHANNEL_MAX")))]
     pub channel: u8,
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
     pub control: u8,
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
     pub value: u8,
 }
 
+/*
+ * Changes the tempo of the clock generator by `step` BPM per detent.
+ */
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Tempo {
+    pub step: u8,
+}
+
+/*
+ * Changes a parameter of a generator input by `step` per detent.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Modulation {
+    pub input: usize,
+    pub param: GeneratorParam,
+    pub step: u8,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MidiNote {
     channel: u8,
     key: u8,
 }
 
+impl EncoderHandler {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EncoderHandler::Dummy => "Dummy",
+            EncoderHandler::MidiRel(_) => "MidiRel",
+            EncoderHandler::MidiAbs(_) => "MidiAbs",
+            EncoderHandler::Tempo(_) => "Tempo",
+            EncoderHandler::Modulation(_) => "Modulation",
+            EncoderHandler::VPot(_) => "VPot",
+        }
+    }
+
+    /*
+     * Updates the handler state from MIDI data received from the host.
+     */
+    pub fn receive(&mut self, data: &OutputData) {
+        if let EncoderHandler::MidiAbs(h) = self {
+            h.receive(data);
+        }
+    }
+}
+
 impl MidiRel {
     pub fn run(&mut self, ev: EncoderDirection) -> OutputData {
         let v = match ev {
@@ -58,6 +116,14 @@ impl MidiAbs {
         })
     }
 
+    pub fn receive(&mut self, data: &OutputData) {
+        if let OutputData::MidiMsgCc(m) = data {
+            if m.channel == self.channel && m.control == self.control {
+                self.value = m.value;
+            }
+        }
+    }
+
     fn inc(&mut self) {
         if self.value < 0x7f {
             self.value += 1;
@@ -71,6 +137,34 @@ impl MidiAbs {
     }
 }
 
+impl Tempo {
+    pub fn run(&mut self, ev: EncoderDirection) -> OutputData {
+        let step = self.step as i16;
+        let delta = match ev {
+            EncoderDirection::CW => step,
+            EncoderDirection::CCW => -step,
+        };
+
+        OutputData::ClockControl(ClockControl::Tempo(delta))
+    }
+}
+
+impl Modulation {
+    pub fn run(&mut self, ev: EncoderDirection) -> OutputData {
+        let step = self.step as i16;
+        let delta = match ev {
+            EncoderDirection::CW => step,
+            EncoderDirection::CCW => -step,
+        };
+
+        OutputData::GeneratorControl(GeneratorControl {
+            input: self.input,
+            param: self.param,
+            delta,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -121,6 +215,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn hander_abs_receive() {
+        let mut handler = MidiAbs {
+            channel: 5,
+            control: 23,
+            value: 0,
+        };
+
+        handler.receive(&OutputData::MidiMsgCc(MidiMsgCc {
+            channel: 5,
+            control: 24,
+            value: 100,
+        }));
+        assert_eq!(handler.value, 0);
+
+        handler.receive(&OutputData::MidiMsgCc(MidiMsgCc {
+            channel: 5,
+            control: 23,
+            value: 100,
+        }));
+        if let OutputData::MidiMsgCc(m) = handler.run(EncoderDirection::CW) {
+            assert_eq!(m.value, 101);
+        } else {
+            panic!("Wrong output data returned");
+        }
+    }
+
     #[test]
     fn hander_abs_ccw() {
         let mut handler = MidiAbs {
@@ -0,0 +1,50 @@
+#[cfg(feature = "schema")]
+use crate::config::{MIDI_CHANNEL_MAX, MIDI_DATA_MAX};
+use crate::output::{MidiMsgCc, OutputData};
+use serde::{Deserialize, Serialize};
+
+const MIDI_CONTROL_MAX: i32 = 0x7f;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum GeneratorHandler {
+    Dummy,
+    MidiCc(MidiCc),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MidiCc {
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_CHANNEL_MAX")))]
+    pub channel: u8,
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
+    pub control: u8,
+}
+
+impl GeneratorHandler {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeneratorHandler::Dummy => "Dummy",
+            GeneratorHandler::MidiCc(_) => "MidiCc",
+        }
+    }
+
+    /*
+     * Moves the handler to another controller.
+     */
+    pub fn retarget(&mut self, delta: i16) {
+        if let GeneratorHandler::MidiCc(h) = self {
+            h.control = (h.control as i32 + delta as i32).clamp(0, MIDI_CONTROL_MAX) as u8;
+        }
+    }
+}
+
+impl MidiCc {
+    pub fn run(&mut self, v: u8) -> OutputData {
+        OutputData::MidiMsgCc(MidiMsgCc {
+            channel: self.channel,
+            control: self.control,
+            value: v,
+        })
+    }
+}
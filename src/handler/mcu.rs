@@ -0,0 +1,172 @@
+#[cfg(feature = "schema")]
+use crate::config::{MCU_FADER_MAX, MCU_STRIP_MAX};
+use crate::output::{MidiMsgCc, MidiMsgNote, MidiMsgPitchBend, OutputData};
+use crate::ui::input::EncoderDirection;
+use serde::{Deserialize, Serialize};
+
+pub(crate) const MCU_CHANNEL: u8 = 0;
+pub(crate) const MCU_STRIPS: u8 = 8;
+pub(crate) const MCU_VPOT_CONTROL: u8 = 0x10;
+const MCU_VPOT_CW: u8 = 0x01;
+const MCU_VPOT_CCW: u8 = 0x41;
+const MCU_BUTTON_ON: u8 = 0x7f;
+
+/*
+ * Button functions of a Mackie Control Universal. The strip functions
+ * apply to the strip given with the button.
+ */
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum McuFunction {
+    RecArm,
+    Solo,
+    Mute,
+    Select,
+    VPotPush,
+    BankLeft,
+    BankRight,
+    ChannelLeft,
+    ChannelRight,
+    Rewind,
+    FastForward,
+    Stop,
+    Play,
+    Record,
+}
+
+/*
+ * V-Pot of a channel strip, sends relative CC 16-23 with the sign in bit
+ * 6 as the MCU expects.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct VPot {
+    #[cfg_attr(feature = "schema", schemars(range(max = "MCU_STRIP_MAX")))]
+    pub strip: u8,
+}
+
+/*
+ * Fader of a channel strip, sends pitch bend on the channel of the strip.
+ * Strip 8 is the master fader.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Fader {
+    #[cfg_attr(feature = "schema", schemars(range(max = "MCU_FADER_MAX")))]
+    pub strip: u8,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct McuButton {
+    pub function: McuFunction,
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(range(max = "MCU_STRIP_MAX")))]
+    pub strip: u8,
+}
+
+impl McuFunction {
+    pub fn note(&self, strip: u8) -> u8 {
+        let strip = strip % MCU_STRIPS;
+        match self {
+            McuFunction::RecArm => strip,
+            McuFunction::Solo => 0x08 + strip,
+            McuFunction::Mute => 0x10 + strip,
+            McuFunction::Select => 0x18 + strip,
+            McuFunction::VPotPush => 0x20 + strip,
+            McuFunction::BankLeft => 0x2e,
+            McuFunction::BankRight => 0x2f,
+            McuFunction::ChannelLeft => 0x30,
+            McuFunction::ChannelRight => 0x31,
+            McuFunction::Rewind => 0x5b,
+            McuFunction::FastForward => 0x5c,
+            McuFunction::Stop => 0x5d,
+            McuFunction::Play => 0x5e,
+            McuFunction::Record => 0x5f,
+        }
+    }
+}
+
+impl VPot {
+    pub fn run(&mut self, ev: EncoderDirection) -> OutputData {
+        let v = match ev {
+            EncoderDirection::CW => MCU_VPOT_CW,
+            EncoderDirection::CCW => MCU_VPOT_CCW,
+        };
+
+        OutputData::MidiMsgCc(MidiMsgCc {
+            channel: MCU_CHANNEL,
+            control: MCU_VPOT_CONTROL + self.strip % MCU_STRIPS,
+            value: v,
+        })
+    }
+}
+
+impl Fader {
+    pub fn run(&mut self, v: u8) -> OutputData {
+        // spread the 7 bit position over the full 14 bit range
+        let v = v as u16 & 0x7f;
+        OutputData::MidiMsgPitchBend(MidiMsgPitchBend {
+            channel: self.strip,
+            value: v << 7 | v,
+        })
+    }
+}
+
+impl McuButton {
+    /*
+     * The MCU releases buttons with a note on of velocity 0.
+     */
+    pub fn run(&mut self, pressed: bool) -> OutputData {
+        OutputData::MidiMsgNote(MidiMsgNote {
+            channel: MCU_CHANNEL,
+            key: self.function.note(self.strip),
+            on: true,
+            velocity: if pressed { MCU_BUTTON_ON } else { 0 },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vpot() {
+        let mut h = VPot { strip: 3 };
+
+        assert_eq!(
+            h.run(EncoderDirection::CW).to_bytes().unwrap(),
+            [0xb0, 0x13, 0x01]
+        );
+        assert_eq!(
+            h.run(EncoderDirection::CCW).to_bytes().unwrap(),
+            [0xb0, 0x13, 0x41]
+        );
+    }
+
+    #[test]
+    fn fader() {
+        let mut h = Fader { strip: 8 };
+
+        assert_eq!(h.run(0).to_bytes().unwrap(), [0xe8, 0, 0]);
+        assert_eq!(h.run(127).to_bytes().unwrap(), [0xe8, 0x7f, 0x7f]);
+    }
+
+    #[test]
+    fn button() {
+        let mut h = McuButton {
+            function: McuFunction::Mute,
+            strip: 2,
+        };
+
+        assert_eq!(h.run(true).to_bytes().unwrap(), [0x90, 0x12, 0x7f]);
+        assert_eq!(h.run(false).to_bytes().unwrap(), [0x90, 0x12, 0]);
+
+        let mut h = McuButton {
+            function: McuFunction::Play,
+            strip: 0,
+        };
+        assert_eq!(h.run(true).to_bytes().unwrap(), [0x90, 0x5e, 0x7f]);
+    }
+}
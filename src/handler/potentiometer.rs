@@ -1,24 +1,138 @@
+#[cfg(feature = "schema")]
+use crate::config::{MIDI_CHANNEL_MAX, MIDI_DATA_MAX};
+use crate::handler::Fader;
 use crate::output::{MidiMsgCc, OutputData};
 use serde::{Deserialize, Serialize};
 
+const MIDI_VALUE_MAX: u8 = 0x7f;
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum PotentiometerHandler {
     Dummy,
     // MidiRel(MidiRel),
     MidiAbs(MidiAbs),
+    Fader(Fader),
     //MidiNote(MidiNote),
 }
 
+/*
+ * Decides how the handler takes over once the pot position and the value
+ * diverged, e.g. after the host changed the value.
+ */
+#[derive(Debug, Default, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Takeover {
+    // the value jumps to the pot position right away
+    #[default]
+    Jump,
+    // nothing is sent until the pot crosses the value
+    Pickup,
+    // the value moves proportionally so both meet at the end stop
+    Scale,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "schema", schemars(rename = "PotMidiAbs"))]
 pub struct MidiAbs {
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_CHANNEL_MAX")))]
     pub channel: u8,
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
     pub control: u8,
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
     pub value: u8,
+    #[serde(default)]
+    pub takeover: Takeover,
+    #[serde(skip)]
+    position: Option<u8>,
+    #[serde(skip)]
+    engaged: bool,
+}
+
+impl PotentiometerHandler {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PotentiometerHandler::Dummy => "Dummy",
+            PotentiometerHandler::MidiAbs(_) => "MidiAbs",
+            PotentiometerHandler::Fader(_) => "Fader",
+        }
+    }
+
+    /*
+     * Tells the handler the pot position found on startup.
+     */
+    pub fn init(&mut self, v: u8) {
+        if let PotentiometerHandler::MidiAbs(h) = self {
+            h.init(v);
+        }
+    }
+
+    /*
+     * Updates the handler state from MIDI data received from the host.
+     */
+    pub fn receive(&mut self, data: &OutputData) {
+        if let PotentiometerHandler::MidiAbs(h) = self {
+            h.receive(data);
+        }
+    }
 }
 
 impl MidiAbs {
+    pub fn new(channel: u8, control: u8, value: u8, takeover: Takeover) -> Self {
+        Self {
+            channel,
+            control,
+            value,
+            takeover,
+            position: None,
+            engaged: false,
+        }
+    }
+
+    pub fn init(&mut self, v: u8) {
+        self.position = Some(v);
+        self.engaged = v == self.value;
+    }
+
+    pub fn receive(&mut self, data: &OutputData) {
+        if let OutputData::MidiMsgCc(m) = data {
+            if m.channel == self.channel && m.control == self.control {
+                self.value = m.value;
+                self.engaged = self.position == Some(m.value);
+            }
+        }
+    }
+
+    /*
+     * Returns `OutputData::Dummy` as long as the takeover mode holds the
+     * value back.
+     */
     pub fn run(&mut self, v: u8) -> OutputData {
-        self.value = v;
+        let last = self.position.replace(v).unwrap_or(v);
+
+        if !self.engaged {
+            let value = match self.takeover {
+                Takeover::Jump => v,
+                Takeover::Pickup => {
+                    let crossed = (last <= self.value && v >= self.value)
+                        || (last >= self.value && v <= self.value);
+                    if !crossed {
+                        return OutputData::Dummy;
+                    }
+                    v
+                }
+                Takeover::Scale => Self::scale(self.value, last, v),
+            };
+            self.engaged = value == v;
+
+            if value == self.value && !self.engaged {
+                return OutputData::Dummy;
+            }
+            self.value = value;
+        } else {
+            self.value = v;
+        }
 
         OutputData::MidiMsgCc(MidiMsgCc {
             channel: self.channel,
@@ -26,4 +140,97 @@ impl MidiAbs {
             value: self.value,
         })
     }
+
+    fn scale(value: u8, last: u8, v: u8) -> u8 {
+        let (value, last, v, max) = (value as u16, last as u16, v as u16, MIDI_VALUE_MAX as u16);
+
+        let scaled = if v > last {
+            value + (v - last) * (max - value) / (max - last)
+        } else if v < last {
+            value - (last - v) * value / last
+        } else {
+            value
+        };
+        scaled as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host_sets(handler: &mut MidiAbs, value: u8) {
+        handler.receive(&OutputData::MidiMsgCc(MidiMsgCc {
+            channel: 0,
+            control: 7,
+            value,
+        }));
+    }
+
+    fn sent(data: OutputData) -> Option<u8> {
+        match data {
+            OutputData::MidiMsgCc(m) => Some(m.value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn jump() {
+        let mut handler = MidiAbs::new(0, 7, 0, Takeover::Jump);
+        handler.init(20);
+
+        host_sets(&mut handler, 100);
+        assert_eq!(sent(handler.run(21)), Some(21));
+    }
+
+    #[test]
+    fn pickup() {
+        let mut handler = MidiAbs::new(0, 7, 20, Takeover::Pickup);
+        handler.init(20);
+        assert_eq!(sent(handler.run(21)), Some(21));
+
+        host_sets(&mut handler, 50);
+        assert_eq!(sent(handler.run(30)), None);
+        assert_eq!(sent(handler.run(49)), None);
+        // crossing the value without hitting it exactly
+        assert_eq!(sent(handler.run(52)), Some(52));
+        assert_eq!(sent(handler.run(40)), Some(40));
+    }
+
+    #[test]
+    fn pickup_from_above() {
+        let mut handler = MidiAbs::new(0, 7, 0, Takeover::Pickup);
+        handler.init(100);
+
+        host_sets(&mut handler, 50);
+        assert_eq!(sent(handler.run(90)), None);
+        assert_eq!(sent(handler.run(50)), Some(50));
+    }
+
+    #[test]
+    fn scale() {
+        let mut handler = MidiAbs::new(0, 7, 0, Takeover::Scale);
+        handler.init(27);
+
+        host_sets(&mut handler, 77);
+        // 100 steps of the pot map onto the 50 steps left of the value
+        assert_eq!(sent(handler.run(47)), Some(87));
+        assert_eq!(sent(handler.run(127)), Some(127));
+        // both met at the end stop
+        assert_eq!(sent(handler.run(100)), Some(100));
+
+        host_sets(&mut handler, 50);
+        assert_eq!(sent(handler.run(50)), Some(25));
+        assert_eq!(sent(handler.run(0)), Some(0));
+        assert_eq!(sent(handler.run(1)), Some(1));
+    }
+
+    #[test]
+    fn scale_without_change() {
+        let mut handler = MidiAbs::new(0, 7, 0, Takeover::Scale);
+        handler.init(0);
+
+        host_sets(&mut handler, 127);
+        assert_eq!(sent(handler.run(1)), None);
+    }
 }
@@ -1,15 +1,33 @@
 #![cfg_attr(target_os = "none", no_std)]
 
+pub mod clock;
+pub mod config;
 pub mod device;
 pub mod output;
+pub mod preset;
+pub mod sequencer;
+pub mod storage;
+pub mod sysex;
+pub mod time;
 pub mod ui;
 
 pub mod handler {
 
+    mod button;
     mod encoder;
+    mod generator;
+    mod mcu;
     mod potentiometer;
+    pub use self::{button::ButtonHandler, button::MidiNote, button::Transport};
     pub use self::{encoder::EncoderHandler, encoder::MidiAbs, encoder::MidiRel};
-    pub use self::{potentiometer::MidiAbs as PotMidiAbs, potentiometer::PotentiometerHandler};
+    pub use self::{encoder::Modulation, encoder::Tempo};
+    pub use self::{generator::GeneratorHandler, generator::MidiCc};
+    pub use self::{mcu::Fader, mcu::McuButton, mcu::McuFunction, mcu::VPot};
+    pub(crate) use self::{mcu::MCU_CHANNEL, mcu::MCU_STRIPS, mcu::MCU_VPOT_CONTROL};
+    pub use self::{
+        potentiometer::MidiAbs as PotMidiAbs, potentiometer::PotentiometerHandler,
+        potentiometer::Takeover,
+    };
 }
 
 use device::Device;
@@ -17,19 +35,17 @@ use handler::{EncoderHandler, MidiRel, PotentiometerHandler};
 use output::{MidiMsgCc, OutputData, OutputType, StdOut};
 use ui::backend::InMemoryBackend;
 use ui::input::{Encoder, EncoderDirection};
-use ui::{Input, InputType};
+use ui::{Address, Input, InputType};
 
 use heapless::Vec;
 
 // TODO: simple poc code to demonstrate that the code runs on
 //       both targets
 pub fn run() {
-    let data_cw = [false, false, true, false, true, true];
-
     let mut b = InMemoryBackend::new();
-    b.set_input_buffer(&data_cw);
+    b.set_input(Address::new(0, 0, 0), true);
 
-    let mut encoder = Encoder::new();
+    let mut encoder = Encoder::new(Address::new(0, 0, 0));
     let mut handler = EncoderHandler::MidiRel(MidiRel {
         channel: 0,
         control: 4,
@@ -43,7 +59,7 @@ pub fn run() {
     outputs.push(OutputType::StdOut(StdOut {}));
 
     // setup
-    device.add_input(input);
+    device.add_input(input).expect("no input slot left");
     device.init_inputs(&mut b);
 
     // operation
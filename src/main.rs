@@ -6,6 +6,311 @@ use {defmt_rtt as _, panic_probe as _};
 #[cfg(target_os = "none")]
 use defmt::info;
 
+#[cfg(target_os = "none")]
 fn main() {
     reset_ctrl::run();
 }
+
+#[cfg(target_os = "linux")]
+fn main() -> std::process::ExitCode {
+    cli::main()
+}
+
+/*
+ * Host side tool to check, convert and try out configs and to transfer
+ * them to a board.
+ */
+#[cfg(target_os = "linux")]
+mod cli {
+    use reset_ctrl::config::BINARY_VERSION;
+    use reset_ctrl::device::Device;
+    use reset_ctrl::output::{JsonOut, OutputType};
+    use reset_ctrl::sysex::{Client, RawMidi};
+    use reset_ctrl::ui::backend::SimulatedBackend;
+    use reset_ctrl::ui::Address;
+
+    use clap::{Parser, Subcommand, ValueEnum};
+    use heapless::Vec;
+    use std::error::Error;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::ExitCode;
+    use std::time::Duration;
+
+    type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+    #[derive(Parser)]
+    #[command(
+        name = "reset_ctrl",
+        version,
+        about = "Configure reset_ctrl MIDI controllers"
+    )]
+    struct Cli {
+        #[command(subcommand)]
+        command: Command,
+    }
+
+    #[derive(Subcommand)]
+    enum Command {
+        #[command(about = "Check a config and report the first error")]
+        Validate {
+            config: PathBuf,
+            #[arg(long)]
+            format: Option<Format>,
+        },
+        #[command(about = "Convert a config between YAML, JSON and the binary format")]
+        Convert {
+            input: PathBuf,
+            output: PathBuf,
+            #[arg(long)]
+            from: Option<Format>,
+            #[arg(long)]
+            to: Option<Format>,
+        },
+        #[command(
+            about = "Run a config against scripted input and print the output as JSON lines"
+        )]
+        Simulate {
+            config: PathBuf,
+            #[arg(
+                help = "Steps like `set 0.1.0 1`, `adc 0.2.0 512`, `update` or `tick 1000`, one per line"
+            )]
+            script: PathBuf,
+            #[arg(long)]
+            format: Option<Format>,
+        },
+        #[command(about = "Print the MIDI message every input is mapped to")]
+        Dump {
+            config: PathBuf,
+            #[arg(long)]
+            format: Option<Format>,
+        },
+        #[cfg(feature = "schema")]
+        #[command(about = "Print the JSON Schema of the config")]
+        Schema,
+        #[command(about = "Upload a config to a board")]
+        Push {
+            config: PathBuf,
+            #[arg(long, help = "Raw MIDI device of the board, e.g. /dev/snd/midiC1D0")]
+            port: PathBuf,
+            #[arg(long, help = "Keep the config in flash across reboots")]
+            save: bool,
+            #[arg(long)]
+            format: Option<Format>,
+        },
+        #[command(about = "Download the config of a board")]
+        Pull {
+            output: PathBuf,
+            #[arg(long)]
+            port: PathBuf,
+            #[arg(long)]
+            format: Option<Format>,
+        },
+    }
+
+    #[derive(Debug, PartialEq, Copy, Clone, ValueEnum)]
+    enum Format {
+        Yaml,
+        Json,
+        Binary,
+    }
+
+    // milliseconds to wait for an answer of the board
+    const PORT_TIMEOUT: u64 = 1000;
+
+    impl Format {
+        /*
+         * Guesses the format from the file extension, YAML if unknown.
+         */
+        fn of(path: &Path) -> Self {
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("json") => Format::Json,
+                Some("bin") => Format::Binary,
+                _ => Format::Yaml,
+            }
+        }
+    }
+
+    fn load(path: &Path, format: Option<Format>) -> Result<Device> {
+        let device = match format.unwrap_or_else(|| Format::of(path)) {
+            Format::Yaml => Device::from_config(&fs::read_to_string(path)?)?,
+            Format::Json => Device::from_json(&fs::read_to_string(path)?)?,
+            Format::Binary => Device::from_binary(&fs::read(path)?)?,
+        };
+        Ok(device)
+    }
+
+    fn store(device: &Device, path: &Path, format: Option<Format>) -> Result<()> {
+        let data = match format.unwrap_or_else(|| Format::of(path)) {
+            Format::Yaml => device.to_config()?.into_bytes(),
+            Format::Json => device.to_json()?.into_bytes(),
+            Format::Binary => device.to_binary_vec()?,
+        };
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    /*
+     * Parses an address given as `board.slot.pin`.
+     */
+    fn address(s: &str) -> Option<Address> {
+        let mut parts = s.split('.').map(|p| p.parse::<u8>());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(board)), Some(Ok(slot)), Some(Ok(pin)), None) => {
+                Some(Address::new(board, slot, pin))
+            }
+            _ => None,
+        }
+    }
+
+    fn simulate(config: &Path, script: &Path, format: Option<Format>) -> Result<()> {
+        let mut device = load(config, format)?;
+        let script = fs::read_to_string(script)?;
+        let mut b = SimulatedBackend::new(device.boards().len(), device.boards().clone());
+
+        let mut outputs: Vec<OutputType, 1> = Vec::new();
+        outputs.push(OutputType::JsonOut(JsonOut::stdout())).ok();
+
+        async_std::task::block_on(async {
+            // simulated time in microseconds, moved on by `tick`
+            let mut now = 0;
+            device.init_inputs(&mut b).await;
+            for (n, line) in script.lines().enumerate() {
+                let line = line.split('#').next().unwrap_or_default();
+                let words: std::vec::Vec<&str> = line.split_whitespace().collect();
+                let invalid = || format!("line {}: invalid step `{}`", n + 1, line.trim());
+                match words[..] {
+                    [] => {}
+                    ["set", a, level] => {
+                        let a = address(a).ok_or_else(invalid)?;
+                        let level = level.parse::<u8>().map_err(|_| invalid())?;
+                        b.set_input(a, level != 0);
+                    }
+                    ["adc", a, value] => {
+                        let a = address(a).ok_or_else(invalid)?;
+                        b.set_adc(a, value.parse().map_err(|_| invalid())?);
+                    }
+                    ["update"] => {
+                        device.update(&mut b).await;
+                        device.run_handler(&outputs).await;
+                    }
+                    ["tick", us] => {
+                        let end = now + us.parse::<u64>().map_err(|_| invalid())?;
+                        // stop at every clock due in between
+                        loop {
+                            device.tick(&outputs, now).await;
+                            device.run_handler(&outputs).await;
+                            if now == end {
+                                break;
+                            }
+                            now = device
+                                .deadline(now)
+                                .filter(|d| *d > now)
+                                .map_or(end, |d| d.min(end));
+                        }
+                    }
+                    _ => return Err(invalid().into()),
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn dump(device: &Device) {
+        println!(
+            "{:<6}{:<15}{:<9}{:<12}message",
+            "input", "type", "address", "handler"
+        );
+        for (idx, input) in device.inputs().iter().enumerate() {
+            let address = match input.lines() {
+                Some((a, _)) => format!("{}.{}.{}", a.board, a.slot, a.pin),
+                None => "-".into(),
+            };
+            let mapping = match device.mapping(idx) {
+                Some(m) => m.to_string(),
+                None => "-".into(),
+            };
+            println!(
+                "{:<6}{:<15}{:<9}{:<12}{}",
+                idx,
+                input.name(),
+                address,
+                input.handler_name(),
+                mapping
+            );
+        }
+    }
+
+    fn connect(port: &Path) -> Result<Client<RawMidi>> {
+        let mut client = Client::new(RawMidi::open(port, Duration::from_millis(PORT_TIMEOUT))?);
+        let version = client.version()?;
+        if version.config != BINARY_VERSION {
+            return Err(format!(
+                "board firmware {} uses config version {}, expected {}",
+                version.firmware, version.config, BINARY_VERSION
+            )
+            .into());
+        }
+        Ok(client)
+    }
+
+    fn run(cli: Cli) -> Result<()> {
+        match cli.command {
+            Command::Validate { config, format } => {
+                load(&config, format)?;
+                println!("{}: ok", config.display());
+            }
+            Command::Convert {
+                input,
+                output,
+                from,
+                to,
+            } => store(&load(&input, from)?, &output, to)?,
+            Command::Simulate {
+                config,
+                script,
+                format,
+            } => simulate(&config, &script, format)?,
+            Command::Dump { config, format } => dump(&load(&config, format)?),
+            #[cfg(feature = "schema")]
+            Command::Schema => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&reset_ctrl::config::schema())?
+                );
+            }
+            Command::Push {
+                config,
+                port,
+                save,
+                format,
+            } => {
+                let data = load(&config, format)?.to_binary_vec()?;
+                let mut client = connect(&port)?;
+                client.push(&data)?;
+                if save {
+                    client.save()?;
+                }
+            }
+            Command::Pull {
+                output,
+                port,
+                format,
+            } => {
+                let data = connect(&port)?.pull()?;
+                store(&Device::from_binary(&data)?, &output, format)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn main() -> ExitCode {
+        match run(Cli::parse()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
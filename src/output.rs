@@ -1,21 +1,188 @@
+mod coalesce;
+#[cfg(target_os = "linux")]
+mod jsonl;
+mod merge;
 mod midi;
+mod notes;
+mod queue;
+mod route;
+#[cfg(target_os = "linux")]
+mod rtpmidi;
+#[cfg(target_os = "linux")]
+mod smf;
 mod stdout;
 #[cfg(target_os = "none")]
 mod usb;
 
-pub use self::{midi::MidiMsgCc, midi::MidiMsgNote, midi::MidiOut, stdout::StdOut};
+use crate::clock::ClockControl;
+use crate::ui::input::GeneratorControl;
+
+use heapless::Vec;
+
+pub use self::coalesce::Coalescer;
+#[cfg(target_os = "linux")]
+pub use self::{
+    jsonl::read_log, jsonl::JsonOut, jsonl::LogEntry, rtpmidi::RtpMidiOut, smf::SmfOut,
+    smf::SmfWriter,
+};
+pub use self::{merge::MergeOut, merge::MidiMerger, merge::UsbPacketizer};
+pub use self::{merge::MidiPort, merge::Thru, merge::MERGE_BUFFER_MAX};
+pub use self::{midi::from_usb_packet, midi::usb_packet_bytes, midi::MidiParser};
+pub use self::{midi::MidiMsgCc, midi::MidiMsgNote, midi::MidiMsgPitchBend};
+pub use self::{midi::MidiOut, stdout::StdOut};
+pub use self::{notes::ArpMode, notes::Arpeggiator, notes::Chord, notes::NoteProcessor};
+pub use self::{notes::NoteStage, notes::ARPEGGIATOR_INPUT, notes::NOTES_OUT_MAX};
+pub use self::{queue::OutputQueue, queue::OverflowPolicy};
+pub use self::{route::route, route::Filter, route::MessageType, route::Route};
 #[cfg(target_os = "none")]
-pub use self::{usb::UsbOut, usb::CHANNEL};
+pub use self::{usb::UsbOut, usb::UsbQueue, usb::CHANNEL, usb::USB_QUEUE_DEPTH_MAX};
 
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum OutputData {
     MidiMsgCc(MidiMsgCc),
     MidiMsgNote(MidiMsgNote),
+    MidiMsgPitchBend(MidiMsgPitchBend),
+    Clock,
+    Start,
+    Stop,
+    Continue,
+    // song position in MIDI beats (16th notes)
+    SongPosition(u16),
+    // command for the clock generator of the device, never sent
+    ClockControl(ClockControl),
+    // command for a generator input of the device, never sent
+    GeneratorControl(GeneratorControl),
     Dummy,
 }
 
+impl OutputData {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputData::MidiMsgCc(_) => "MidiMsgCc",
+            OutputData::MidiMsgNote(_) => "MidiMsgNote",
+            OutputData::MidiMsgPitchBend(_) => "MidiMsgPitchBend",
+            OutputData::Clock => "Clock",
+            OutputData::Start => "Start",
+            OutputData::Stop => "Stop",
+            OutputData::Continue => "Continue",
+            OutputData::SongPosition(_) => "SongPosition",
+            OutputData::ClockControl(_) => "ClockControl",
+            OutputData::GeneratorControl(_) => "GeneratorControl",
+            OutputData::Dummy => "Dummy",
+        }
+    }
+
+    pub fn message_type(&self) -> Option<MessageType> {
+        match self {
+            OutputData::MidiMsgCc(_) => Some(MessageType::Cc),
+            OutputData::MidiMsgNote(_) => Some(MessageType::Note),
+            OutputData::MidiMsgPitchBend(_) => Some(MessageType::PitchBend),
+            OutputData::Clock => Some(MessageType::Clock),
+            OutputData::Start
+            | OutputData::Stop
+            | OutputData::Continue
+            | OutputData::SongPosition(_) => Some(MessageType::Transport),
+            OutputData::ClockControl(_) | OutputData::GeneratorControl(_) | OutputData::Dummy => {
+                None
+            }
+        }
+    }
+
+    pub fn channel(&self) -> Option<u8> {
+        match self {
+            OutputData::MidiMsgCc(m) => Some(m.channel),
+            OutputData::MidiMsgNote(m) => Some(m.channel),
+            OutputData::MidiMsgPitchBend(m) => Some(m.channel),
+            _ => None,
+        }
+    }
+
+    pub fn with_channel(&self, channel: u8) -> Self {
+        match *self {
+            OutputData::MidiMsgCc(m) => OutputData::MidiMsgCc(MidiMsgCc { channel, ..m }),
+            OutputData::MidiMsgNote(m) => OutputData::MidiMsgNote(MidiMsgNote { channel, ..m }),
+            OutputData::MidiMsgPitchBend(m) => {
+                OutputData::MidiMsgPitchBend(MidiMsgPitchBend { channel, ..m })
+            }
+            data => data,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Option<Vec<u8, 3>> {
+        let bytes = match self {
+            OutputData::MidiMsgCc(m) => m.to_bytes(),
+            OutputData::MidiMsgNote(m) => m.to_bytes(),
+            OutputData::MidiMsgPitchBend(m) => m.to_bytes(),
+            OutputData::SongPosition(p) => [
+                midi::MIDI_MSG_SONG_POSITION,
+                (p & 0x7f) as u8,
+                (p >> 7 & 0x7f) as u8,
+            ],
+            OutputData::Clock => return Vec::from_slice(&[midi::MIDI_MSG_CLOCK]).ok(),
+            OutputData::Start => return Vec::from_slice(&[midi::MIDI_MSG_START]).ok(),
+            OutputData::Stop => return Vec::from_slice(&[midi::MIDI_MSG_STOP]).ok(),
+            OutputData::Continue => return Vec::from_slice(&[midi::MIDI_MSG_CONTINUE]).ok(),
+            OutputData::ClockControl(_) | OutputData::GeneratorControl(_) | OutputData::Dummy => {
+                return None
+            }
+        };
+        Vec::from_slice(&bytes).ok()
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum OutputError {
+    // there is no peer or host to receive the data
+    Disconnected,
+    // data had to be dropped because the output can't keep up
+    Overflow,
+    // the output can't handle this kind of output data
+    Unsupported,
+    Io,
+}
+
+/*
+ * Health counters of a single output.
+ */
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub struct OutputStats {
+    pub sent: u32,
+    pub dropped: u32,
+    pub failed: u32,
+}
+
+impl OutputStats {
+    pub fn record(&mut self, result: Result<(), OutputError>) {
+        let counter = match result {
+            Ok(()) => &mut self.sent,
+            Err(OutputError::Disconnected | OutputError::Overflow) => &mut self.dropped,
+            Err(OutputError::Unsupported | OutputError::Io) => &mut self.failed,
+        };
+        *counter = counter.saturating_add(1);
+    }
+}
+
+/*
+ * Describes the origin of output data passed to outputs.
+ */
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Event {
+    pub input: usize,
+    pub input_type: &'static str,
+    pub handler: &'static str,
+    pub timestamp: u64,
+}
+
 pub enum OutputType {
     StdOut(StdOut),
     MidiOut(MidiOut),
+    MergeOut(MergeOut),
+    #[cfg(target_os = "linux")]
+    RtpMidiOut(RtpMidiOut),
+    #[cfg(target_os = "linux")]
+    SmfOut(SmfOut),
+    #[cfg(target_os = "linux")]
+    JsonOut(JsonOut),
     #[cfg(target_os = "none")]
     UsbOut(UsbOut),
 }
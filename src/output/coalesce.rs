@@ -0,0 +1,205 @@
+use crate::output::{Event, OutputData};
+
+use heapless::Vec;
+
+/*
+ * Collects output data for the duration of a flush window. A CC replaces
+ * any pending CC of the same channel and controller and moves to the end,
+ * all other output data keeps its order. The window opens with the first
+ * pending data, timestamps are in microseconds.
+ */
+#[derive(Debug, Default, PartialEq)]
+pub struct Coalescer<const N: usize> {
+    pending: Vec<(OutputData, Event), N>,
+    opened: Option<u64>,
+}
+
+impl<const N: usize> Coalescer<N> {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            opened: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.pending.is_full()
+    }
+
+    /*
+     * Adds output data to the pending window. If the window is full the
+     * data is handed back and the window has to be flushed first.
+     */
+    pub fn push(&mut self, data: OutputData, event: Event) -> Result<(), (OutputData, Event)> {
+        if let OutputData::MidiMsgCc(new) = data {
+            let stale = self.pending.iter().position(|(d, _)| match d {
+                OutputData::MidiMsgCc(m) => m.channel == new.channel && m.control == new.control,
+                _ => false,
+            });
+            if let Some(idx) = stale {
+                self.pending.remove(idx);
+            }
+        }
+
+        self.pending.push((data, event))?;
+        self.opened.get_or_insert(event.timestamp);
+        Ok(())
+    }
+
+    pub fn is_due(&self, now: u64, window: u64) -> bool {
+        self.opened
+            .map(|opened| now.saturating_sub(opened) >= window)
+            .unwrap_or(false)
+    }
+
+    /*
+     * Hands out all pending output data in order and closes the window.
+     */
+    pub fn flush(&mut self) -> Vec<(OutputData, Event), N> {
+        self.opened = None;
+        core::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{MidiMsgCc, MidiMsgNote};
+
+    fn event(timestamp: u64) -> Event {
+        Event {
+            input: 0,
+            input_type: "Potentiometer",
+            handler: "MidiAbs",
+            timestamp,
+        }
+    }
+
+    fn cc(control: u8, value: u8) -> OutputData {
+        OutputData::MidiMsgCc(MidiMsgCc {
+            channel: 0,
+            control,
+            value,
+        })
+    }
+
+    fn note(key: u8) -> OutputData {
+        OutputData::MidiMsgNote(MidiMsgNote {
+            channel: 0,
+            key,
+            on: true,
+            velocity: 100,
+        })
+    }
+
+    fn flushed<const N: usize>(c: &mut Coalescer<N>) -> std::vec::Vec<OutputData> {
+        c.flush().into_iter().map(|(d, _)| d).collect()
+    }
+
+    #[test]
+    fn keep_latest_cc() {
+        let mut c: Coalescer<8> = Coalescer::new();
+
+        for (i, v) in [1, 2, 3].into_iter().enumerate() {
+            c.push(cc(7, v), event(i as u64)).unwrap();
+        }
+        c.push(cc(8, 1), event(4)).unwrap();
+
+        assert_eq!(flushed(&mut c), [cc(7, 3), cc(8, 1)]);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn keep_note_order() {
+        let mut c: Coalescer<8> = Coalescer::new();
+
+        c.push(cc(7, 1), event(0)).unwrap();
+        c.push(note(60), event(1)).unwrap();
+        c.push(note(61), event(2)).unwrap();
+        c.push(cc(7, 2), event(3)).unwrap();
+        c.push(note(60), event(4)).unwrap();
+
+        assert_eq!(flushed(&mut c), [note(60), note(61), cc(7, 2), note(60)]);
+    }
+
+    #[test]
+    fn window() {
+        let mut c: Coalescer<8> = Coalescer::new();
+        assert!(!c.is_due(1000, 500));
+
+        c.push(cc(7, 1), event(1000)).unwrap();
+        c.push(cc(7, 2), event(1400)).unwrap();
+        assert!(!c.is_due(1499, 500));
+        assert!(c.is_due(1500, 500));
+
+        c.flush();
+        assert!(!c.is_due(2000, 500));
+    }
+
+    #[test]
+    fn full() {
+        let mut c: Coalescer<2> = Coalescer::new();
+
+        c.push(cc(1, 1), event(0)).unwrap();
+        c.push(cc(2, 1), event(0)).unwrap();
+        // replacing a pending value doesn't need space
+        c.push(cc(2, 2), event(0)).unwrap();
+        assert!(c.is_full());
+        assert_eq!(c.push(cc(3, 1), event(0)), Err((cc(3, 1), event(0))));
+    }
+
+    #[test]
+    fn never_sends_stale_value_last() {
+        let mut c: Coalescer<16> = Coalescer::new();
+        let mut seed = 0x2545_f491u32;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+
+        let mut last_pushed = [None; 4];
+        let mut last_sent = [None; 4];
+        let mut notes_pushed = std::vec::Vec::new();
+        let mut notes_sent = std::vec::Vec::new();
+
+        for t in 0..10_000u64 {
+            let r = random();
+            let data = if r % 5 == 0 {
+                note((r >> 8) as u8 & 0x7f)
+            } else {
+                cc((r >> 8) as u8 % 4, (r >> 16) as u8 & 0x7f)
+            };
+
+            if c.is_full() || r % 7 == 0 {
+                for (d, _) in c.flush() {
+                    match d {
+                        OutputData::MidiMsgCc(m) => last_sent[m.control as usize] = Some(m.value),
+                        n => notes_sent.push(n),
+                    }
+                }
+            }
+
+            match data {
+                OutputData::MidiMsgCc(m) => last_pushed[m.control as usize] = Some(m.value),
+                n => notes_pushed.push(n),
+            }
+            c.push(data, event(t)).unwrap();
+        }
+
+        for (d, _) in c.flush() {
+            match d {
+                OutputData::MidiMsgCc(m) => last_sent[m.control as usize] = Some(m.value),
+                n => notes_sent.push(n),
+            }
+        }
+
+        assert_eq!(last_sent, last_pushed);
+        assert_eq!(notes_sent, notes_pushed);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::output::{Event, OutputData, OutputError};
+
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+
+/*
+ * A single line of the event log.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub input: usize,
+    pub input_type: String,
+    pub handler: String,
+    pub message: String,
+    pub bytes: Vec<u8>,
+    pub timestamp: u64,
+}
+
+/*
+ * Writes one JSON object per output data and line. Every line is flushed
+ * right away so the log can be followed while the device is running.
+ */
+pub struct JsonOut {
+    writer: RefCell<Box<dyn Write>>,
+}
+
+impl JsonOut {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn new(writer: impl Write + 'static) -> Self {
+        Self {
+            writer: RefCell::new(Box::new(writer)),
+        }
+    }
+
+    pub async fn run(&self, data: &OutputData, event: &Event) -> Result<(), OutputError> {
+        let entry = LogEntry {
+            input: event.input,
+            input_type: event.input_type.into(),
+            handler: event.handler.into(),
+            message: data.name().into(),
+            bytes: data.to_bytes().map(|b| b.to_vec()).unwrap_or_default(),
+            timestamp: event.timestamp,
+        };
+
+        let mut writer = self.writer.borrow_mut();
+        serde_json::to_writer(&mut *writer, &entry).map_err(|_| OutputError::Io)?;
+        writer
+            .write_all(b"\n")
+            .and_then(|_| writer.flush())
+            .map_err(|_| OutputError::Io)
+    }
+}
+
+/*
+ * Reads back a log written by `JsonOut`. Empty lines are skipped.
+ */
+pub fn read_log(reader: impl BufRead) -> io::Result<Vec<LogEntry>> {
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line)?);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::MidiMsgNote;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[async_std::test]
+    async fn write_and_read_back() {
+        let buffer = SharedBuffer::default();
+        let out = JsonOut::new(buffer.clone());
+        let event = Event {
+            input: 1,
+            input_type: "Encoder",
+            handler: "MidiNote",
+            timestamp: 42,
+        };
+        let data = OutputData::MidiMsgNote(MidiMsgNote {
+            channel: 1,
+            key: 60,
+            on: true,
+            velocity: 100,
+        });
+
+        out.run(&data, &event).await.unwrap();
+        out.run(&OutputData::Dummy, &event).await.unwrap();
+
+        let log = buffer.0.borrow();
+        assert_eq!(log.iter().filter(|b| **b == b'\n').count(), 2);
+
+        let entries = read_log(&log[..]).unwrap();
+        assert_eq!(
+            entries[0],
+            LogEntry {
+                input: 1,
+                input_type: "Encoder".into(),
+                handler: "MidiNote".into(),
+                message: "MidiMsgNote".into(),
+                bytes: vec![0x91, 60, 100],
+                timestamp: 42,
+            }
+        );
+        assert_eq!(entries[1].message, "Dummy");
+        assert!(entries[1].bytes.is_empty());
+    }
+}
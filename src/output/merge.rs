@@ -0,0 +1,451 @@
+use crate::output::midi::{
+    data_len, MIDI_MSG_STATUS_BIT, MIDI_MSG_STATUS_REALTIME, MIDI_MSG_STATUS_SYSEX,
+    MIDI_MSG_STATUS_SYSEX_END, MIDI_MSG_STATUS_SYSTEM, USB_MIDI_CIN_SINGLE_BYTE,
+    USB_MIDI_CIN_SYSEX, USB_MIDI_CIN_SYSEX_END_1, USB_MIDI_CIN_SYSTEM_2, USB_MIDI_CIN_SYSTEM_3,
+};
+use crate::output::{OutputData, OutputError};
+
+use core::cell::RefCell;
+use heapless::{Deque, Vec};
+use serde::{Deserialize, Serialize};
+
+const MERGE_HELD_MAX: usize = 16;
+pub const MERGE_BUFFER_MAX: usize = 64;
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum MidiPort {
+    Din,
+    Usb,
+}
+
+/*
+ * Forwards MIDI received on one port to another port, merged with the
+ * data generated by the device.
+ */
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Thru {
+    pub from: MidiPort,
+    pub to: MidiPort,
+}
+
+/*
+ * Merges an incoming MIDI byte stream with locally generated messages
+ * into the stream of one output port. Incoming channel and system common
+ * messages are only forwarded once complete, local messages arriving
+ * during an incoming SysEx are held back until it ended. Realtime
+ * messages are forwarded right away. With running status enabled
+ * repeated status bytes are left out of the merged stream.
+ */
+pub struct MidiMerger<const N: usize> {
+    running_status: bool,
+    in_status: u8,
+    in_data: [u8; 2],
+    in_len: usize,
+    in_sysex: bool,
+    out_status: u8,
+    held: Deque<Vec<u8, 3>, MERGE_HELD_MAX>,
+    out: Deque<u8, N>,
+}
+
+impl<const N: usize> MidiMerger<N> {
+    pub fn new(running_status: bool) -> Self {
+        Self {
+            running_status,
+            in_status: 0,
+            in_data: [0; 2],
+            in_len: 0,
+            in_sysex: false,
+            out_status: 0,
+            held: Deque::new(),
+            out: Deque::new(),
+        }
+    }
+
+    /*
+     * Adds a byte received on the incoming port.
+     */
+    pub fn input(&mut self, byte: u8) -> Result<(), OutputError> {
+        if byte >= MIDI_MSG_STATUS_REALTIME {
+            return self.write(byte);
+        }
+
+        if byte == MIDI_MSG_STATUS_SYSEX {
+            self.in_sysex = true;
+            self.in_status = 0;
+            self.out_status = 0;
+            return self.write(byte);
+        }
+
+        if byte & MIDI_MSG_STATUS_BIT != 0 {
+            if self.in_sysex {
+                self.in_sysex = false;
+                if byte == MIDI_MSG_STATUS_SYSEX_END {
+                    self.write(byte)?;
+                }
+                self.release()?;
+                if byte == MIDI_MSG_STATUS_SYSEX_END {
+                    return Ok(());
+                }
+            }
+
+            self.in_status = byte;
+            self.in_len = 0;
+            if data_len(byte) == 0 {
+                self.in_status = 0;
+                return self.write_msg(&[byte]);
+            }
+            return Ok(());
+        }
+
+        if self.in_sysex {
+            return self.write(byte);
+        }
+
+        // data without status is dropped
+        if self.in_status == 0 {
+            return Ok(());
+        }
+
+        self.in_data[self.in_len] = byte;
+        self.in_len += 1;
+        let len = data_len(self.in_status);
+        if self.in_len < len {
+            return Ok(());
+        }
+        self.in_len = 0;
+
+        let msg = [self.in_status, self.in_data[0], self.in_data[1]];
+        if self.in_status >= MIDI_MSG_STATUS_SYSTEM {
+            // system common messages have no running status
+            self.in_status = 0;
+        }
+        self.write_msg(&msg[..len + 1])
+    }
+
+    /*
+     * Adds output data generated by the device.
+     */
+    pub fn local(&mut self, data: &OutputData) -> Result<(), OutputError> {
+        let msg = data.to_bytes().ok_or(OutputError::Unsupported)?;
+
+        if self.in_sysex {
+            return self.held.push_back(msg).map_err(|_| OutputError::Overflow);
+        }
+        self.write_msg(&msg)
+    }
+
+    /*
+     * Takes the next byte of the merged stream.
+     */
+    pub fn pop(&mut self) -> Option<u8> {
+        self.out.pop_front()
+    }
+
+    fn release(&mut self) -> Result<(), OutputError> {
+        while let Some(msg) = self.held.pop_front() {
+            self.write_msg(&msg)?;
+        }
+        Ok(())
+    }
+
+    fn write_msg(&mut self, msg: &[u8]) -> Result<(), OutputError> {
+        let status = msg[0];
+        let skip_status = self.running_status && status == self.out_status;
+
+        let msg = if skip_status { &msg[1..] } else { msg };
+        if self.out.capacity() - self.out.len() < msg.len() {
+            return Err(OutputError::Overflow);
+        }
+        for b in msg {
+            self.write(*b)?;
+        }
+
+        // only a status which made it into the stream is running
+        self.out_status = if status < MIDI_MSG_STATUS_SYSTEM {
+            status
+        } else {
+            0
+        };
+        Ok(())
+    }
+
+    fn write(&mut self, byte: u8) -> Result<(), OutputError> {
+        self.out.push_back(byte).map_err(|_| OutputError::Overflow)
+    }
+}
+
+/*
+ * Output of a MIDI port which merges the output data of the device with
+ * MIDI forwarded from other ports. The port driver takes the merged
+ * stream with `pop`.
+ */
+pub struct MergeOut {
+    port: MidiPort,
+    merger: RefCell<MidiMerger<MERGE_BUFFER_MAX>>,
+}
+
+impl MergeOut {
+    pub fn new(port: MidiPort, running_status: bool) -> Self {
+        Self {
+            port,
+            merger: RefCell::new(MidiMerger::new(running_status)),
+        }
+    }
+
+    pub fn port(&self) -> MidiPort {
+        self.port
+    }
+
+    pub fn input(&self, byte: u8) -> Result<(), OutputError> {
+        self.merger.borrow_mut().input(byte)
+    }
+
+    pub fn pop(&self) -> Option<u8> {
+        self.merger.borrow_mut().pop()
+    }
+
+    pub async fn run(&self, data: &OutputData) -> Result<(), OutputError> {
+        self.merger.borrow_mut().local(data)
+    }
+}
+
+/*
+ * Packs a MIDI byte stream into USB MIDI event packets of cable 0.
+ */
+#[derive(Debug, Default)]
+pub struct UsbPacketizer {
+    status: u8,
+    buf: Vec<u8, 3>,
+    sysex: bool,
+}
+
+impl UsbPacketizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<[u8; 4]> {
+        if byte >= MIDI_MSG_STATUS_REALTIME {
+            return Some([USB_MIDI_CIN_SINGLE_BYTE, byte, 0, 0]);
+        }
+
+        if byte == MIDI_MSG_STATUS_SYSEX {
+            self.sysex = true;
+            self.status = 0;
+            self.buf.clear();
+        }
+
+        if self.sysex {
+            self.buf.push(byte).ok();
+            if byte == MIDI_MSG_STATUS_SYSEX_END {
+                self.sysex = false;
+                let cin = USB_MIDI_CIN_SYSEX_END_1 + self.buf.len() as u8 - 1;
+                return Some(self.packet(cin));
+            }
+            if self.buf.is_full() {
+                return Some(self.packet(USB_MIDI_CIN_SYSEX));
+            }
+            return None;
+        }
+
+        if byte & MIDI_MSG_STATUS_BIT != 0 {
+            self.status = byte;
+            self.buf.clear();
+            self.buf.push(byte).ok();
+        } else if self.status != 0 {
+            if self.buf.is_empty() {
+                // running status
+                self.buf.push(self.status).ok();
+            }
+            self.buf.push(byte).ok();
+        } else {
+            return None;
+        }
+
+        let status = self.buf[0];
+        if self.buf.len() < data_len(status) + 1 {
+            return None;
+        }
+
+        let cin = match (status, self.buf.len()) {
+            (0x80..=0xef, _) => status >> 4,
+            (_, 1) => USB_MIDI_CIN_SYSEX_END_1,
+            (_, 2) => USB_MIDI_CIN_SYSTEM_2,
+            _ => USB_MIDI_CIN_SYSTEM_3,
+        };
+        if status >= MIDI_MSG_STATUS_SYSTEM {
+            self.status = 0;
+        }
+        Some(self.packet(cin))
+    }
+
+    fn packet(&mut self, cin: u8) -> [u8; 4] {
+        let mut packet = [cin, 0, 0, 0];
+        packet[1..1 + self.buf.len()].copy_from_slice(&self.buf);
+        self.buf.clear();
+        packet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{usb_packet_bytes, MidiMsgCc, MidiMsgNote};
+
+    fn merged<const N: usize>(m: &mut MidiMerger<N>) -> std::vec::Vec<u8> {
+        core::iter::from_fn(|| m.pop()).collect()
+    }
+
+    fn cc(value: u8) -> OutputData {
+        OutputData::MidiMsgCc(MidiMsgCc {
+            channel: 0,
+            control: 7,
+            value,
+        })
+    }
+
+    #[test]
+    fn forward_complete_messages_only() {
+        let mut m: MidiMerger<32> = MidiMerger::new(false);
+
+        m.input(0x90).unwrap();
+        m.input(60).unwrap();
+        // a local message can't end up between status and data
+        m.local(&cc(1)).unwrap();
+        m.input(100).unwrap();
+
+        assert_eq!(merged(&mut m), [0xb0, 7, 1, 0x90, 60, 100]);
+    }
+
+    #[test]
+    fn restore_incoming_running_status() {
+        let mut m: MidiMerger<32> = MidiMerger::new(false);
+
+        for b in [0x90, 60, 100] {
+            m.input(b).unwrap();
+        }
+        m.local(&cc(1)).unwrap();
+        for b in [61, 100] {
+            m.input(b).unwrap();
+        }
+
+        assert_eq!(merged(&mut m), [0x90, 60, 100, 0xb0, 7, 1, 0x90, 61, 100]);
+    }
+
+    #[test]
+    fn output_running_status() {
+        let mut m: MidiMerger<32> = MidiMerger::new(true);
+
+        m.local(&cc(1)).unwrap();
+        for b in [0xb0, 7, 2, 0xf8] {
+            m.input(b).unwrap();
+        }
+        m.local(&cc(3)).unwrap();
+        m.local(&OutputData::MidiMsgNote(MidiMsgNote {
+            channel: 0,
+            key: 60,
+            on: true,
+            velocity: 1,
+        }))
+        .unwrap();
+
+        assert_eq!(merged(&mut m), [0xb0, 7, 1, 7, 2, 0xf8, 7, 3, 0x90, 60, 1]);
+    }
+
+    #[test]
+    fn hold_local_during_sysex() {
+        let mut m: MidiMerger<32> = MidiMerger::new(true);
+
+        m.local(&cc(1)).unwrap();
+        for b in [0xf0, 0x7d, 1] {
+            m.input(b).unwrap();
+        }
+        m.local(&cc(2)).unwrap();
+        // realtime may interrupt SysEx
+        m.input(0xf8).unwrap();
+        for b in [2, 0xf7] {
+            m.input(b).unwrap();
+        }
+
+        assert_eq!(
+            merged(&mut m),
+            [0xb0, 7, 1, 0xf0, 0x7d, 1, 0xf8, 2, 0xf7, 0xb0, 7, 2]
+        );
+    }
+
+    #[test]
+    fn sysex_ended_by_status() {
+        let mut m: MidiMerger<32> = MidiMerger::new(false);
+
+        for b in [0xf0, 0x7d, 0xb0, 7, 1] {
+            m.input(b).unwrap();
+        }
+        assert_eq!(merged(&mut m), [0xf0, 0x7d, 0xb0, 7, 1]);
+    }
+
+    #[test]
+    fn overflow() {
+        let mut m: MidiMerger<4> = MidiMerger::new(false);
+
+        m.local(&cc(1)).unwrap();
+        assert_eq!(m.local(&cc(2)), Err(OutputError::Overflow));
+        assert_eq!(merged(&mut m), [0xb0, 7, 1]);
+    }
+
+    #[test]
+    fn overflow_keeps_running_status() {
+        let mut m: MidiMerger<4> = MidiMerger::new(true);
+        let note = OutputData::MidiMsgNote(MidiMsgNote {
+            channel: 0,
+            key: 60,
+            on: true,
+            velocity: 1,
+        });
+
+        m.local(&cc(1)).unwrap();
+        assert_eq!(m.local(&note), Err(OutputError::Overflow));
+        assert_eq!(merged(&mut m), [0xb0, 7, 1]);
+
+        // the rejected status never went out
+        m.local(&note).unwrap();
+        assert_eq!(merged(&mut m), [0x90, 60, 1]);
+    }
+
+    #[test]
+    fn usb_packets() {
+        let mut p = UsbPacketizer::new();
+        let bytes = [
+            0xb0, 7, 1, 2, 3, 0xf8, 0xf0, 0x7d, 1, 2, 3, 0xf7, 0xf2, 1, 2, 0xf6,
+        ];
+        let packets: std::vec::Vec<[u8; 4]> = bytes.iter().filter_map(|b| p.push(*b)).collect();
+
+        assert_eq!(
+            packets,
+            [
+                [0x0b, 0xb0, 7, 1],
+                [0x0b, 0xb0, 2, 3],
+                [0x0f, 0xf8, 0, 0],
+                [0x04, 0xf0, 0x7d, 1],
+                [0x07, 2, 3, 0xf7],
+                [0x03, 0xf2, 1, 2],
+                [0x05, 0xf6, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn usb_packets_roundtrip() {
+        let mut p = UsbPacketizer::new();
+        let bytes = [0x90, 60, 100, 0xf0, 1, 2, 3, 4, 0xf7, 0xc0, 5];
+
+        let unpacked: std::vec::Vec<u8> = bytes
+            .iter()
+            .filter_map(|b| p.push(*b))
+            .flat_map(|packet| usb_packet_bytes(&packet).to_vec())
+            .collect();
+
+        assert_eq!(unpacked, bytes);
+    }
+}
@@ -1,3 +1,30 @@
+use crate::output::OutputData;
+
+pub(crate) const MIDI_MSG_STATUS_BIT: u8 = 1 << 7;
+pub(crate) const MIDI_MSG_STATUS_SYSEX: u8 = 0xf0;
+pub(crate) const MIDI_MSG_STATUS_SYSEX_END: u8 = 0xf7;
+pub(crate) const MIDI_MSG_STATUS_SYSTEM: u8 = 0xf0;
+pub(crate) const MIDI_MSG_STATUS_REALTIME: u8 = 0xf8;
+const MIDI_MSG_STATUS_PROGRAM_CHANGE: u8 = 0b1100u8 << 4;
+const MIDI_MSG_STATUS_CHANNEL_PRESSURE: u8 = 0b1101u8 << 4;
+
+pub(crate) const MIDI_MSG_SONG_POSITION: u8 = 0xf2;
+pub(crate) const MIDI_MSG_CLOCK: u8 = 0xf8;
+pub(crate) const MIDI_MSG_START: u8 = 0xfa;
+pub(crate) const MIDI_MSG_CONTINUE: u8 = 0xfb;
+pub(crate) const MIDI_MSG_STOP: u8 = 0xfc;
+
+const USB_MIDI_CIN_MASK: u8 = 0xf;
+pub(crate) const USB_MIDI_CIN_SYSTEM_2: u8 = 0x2;
+pub(crate) const USB_MIDI_CIN_SYSTEM_3: u8 = 0x3;
+pub(crate) const USB_MIDI_CIN_SYSEX: u8 = 0x4;
+pub(crate) const USB_MIDI_CIN_SYSEX_END_1: u8 = 0x5;
+const USB_MIDI_CIN_SYSEX_END_2: u8 = 0x6;
+const USB_MIDI_CIN_SYSEX_END_3: u8 = 0x7;
+pub(crate) const USB_MIDI_CIN_SINGLE_BYTE: u8 = 0xf;
+const USB_MIDI_CIN_NOTE_OFF: u8 = 0x8;
+const USB_MIDI_CIN_PITCH_BEND: u8 = 0xe;
+
 const MIDI_MSG_STATUS_CC: u8 = 0b1011u8 << 4;
 const MIDI_MSG_STATUS_CHANNEL_MASK: u8 = 0xf;
 const MIDI_MSG_CC_VAL_MASK: u8 = !(1 << 7);
@@ -8,9 +35,12 @@ const MIDI_MSG_STATUS_NOTE_OFF: u8 = 0b1000u8 << 4;
 const MIDI_MSG_NOTE_VEL_MASK: u8 = !(1 << 7);
 const MIDI_MSG_NOTE_KEY_MASK: u8 = !(1 << 7);
 
+const MIDI_MSG_STATUS_PITCH_BEND: u8 = 0b1110u8 << 4;
+const MIDI_MSG_DATA_MASK: u16 = 0x7f;
+
 pub struct MidiOut {}
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub struct MidiMsgCc {
     pub channel: u8,
     pub control: u8,
@@ -27,7 +57,7 @@ impl MidiMsgCc {
     }
 }
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub struct MidiMsgNote {
     pub channel: u8,
     pub key: u8,
@@ -50,3 +80,266 @@ impl MidiMsgNote {
         ]
     }
 }
+
+/*
+ * Pitch bend with a 14 bit value, 0x2000 is the center.
+ */
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct MidiMsgPitchBend {
+    pub channel: u8,
+    pub value: u16,
+}
+
+impl MidiMsgPitchBend {
+    pub fn to_bytes(&self) -> [u8; 3] {
+        [
+            MIDI_MSG_STATUS_PITCH_BEND | (MIDI_MSG_STATUS_CHANNEL_MASK & self.channel),
+            (self.value & MIDI_MSG_DATA_MASK) as u8,
+            (self.value >> 7 & MIDI_MSG_DATA_MASK) as u8,
+        ]
+    }
+}
+
+/*
+ * Parses a MIDI byte stream as received on DIN or unpacked from USB
+ * packets. Running status is supported, realtime messages are handed out
+ * wherever they appear. Besides realtime only CC, note, pitch bend and song
+ * position messages are handed out, other messages including SysEx are skipped.
+ */
+#[derive(Debug, Default)]
+pub struct MidiParser {
+    status: u8,
+    data: [u8; 2],
+    len: usize,
+}
+
+impl MidiParser {
+    pub const fn new() -> Self {
+        Self {
+            status: 0,
+            data: [0; 2],
+            len: 0,
+        }
+    }
+
+    pub fn parse(&mut self, byte: u8) -> Option<OutputData> {
+        if byte >= MIDI_MSG_STATUS_REALTIME {
+            // realtime messages don't touch the running status
+            return match byte {
+                MIDI_MSG_CLOCK => Some(OutputData::Clock),
+                MIDI_MSG_START => Some(OutputData::Start),
+                MIDI_MSG_CONTINUE => Some(OutputData::Continue),
+                MIDI_MSG_STOP => Some(OutputData::Stop),
+                _ => None,
+            };
+        }
+
+        if byte & MIDI_MSG_STATUS_BIT != 0 {
+            self.len = 0;
+            // system common messages and SysEx cancel the running status
+            self.status = if byte < MIDI_MSG_STATUS_SYSTEM || byte == MIDI_MSG_SONG_POSITION {
+                byte
+            } else {
+                0
+            };
+            return None;
+        }
+
+        if self.status == 0 {
+            return None;
+        }
+
+        self.data[self.len] = byte;
+        self.len += 1;
+        if self.len < self.data_len() {
+            return None;
+        }
+        self.len = 0;
+
+        if self.status == MIDI_MSG_SONG_POSITION {
+            self.status = 0;
+            let position = self.data[0] as u16 | (self.data[1] as u16) << 7;
+            return Some(OutputData::SongPosition(position));
+        }
+
+        let channel = self.status & MIDI_MSG_STATUS_CHANNEL_MASK;
+        match self.status & !MIDI_MSG_STATUS_CHANNEL_MASK {
+            MIDI_MSG_STATUS_CC => Some(OutputData::MidiMsgCc(MidiMsgCc {
+                channel,
+                control: self.data[0],
+                value: self.data[1],
+            })),
+            MIDI_MSG_STATUS_NOTE_ON | MIDI_MSG_STATUS_NOTE_OFF => {
+                Some(OutputData::MidiMsgNote(MidiMsgNote {
+                    channel,
+                    key: self.data[0],
+                    // a note on without velocity is a note off
+                    on: self.status & !MIDI_MSG_STATUS_CHANNEL_MASK == MIDI_MSG_STATUS_NOTE_ON
+                        && self.data[1] != 0,
+                    velocity: self.data[1],
+                }))
+            }
+            MIDI_MSG_STATUS_PITCH_BEND => Some(OutputData::MidiMsgPitchBend(MidiMsgPitchBend {
+                channel,
+                value: self.data[0] as u16 | (self.data[1] as u16) << 7,
+            })),
+            _ => None,
+        }
+    }
+
+    fn data_len(&self) -> usize {
+        data_len(self.status)
+    }
+}
+
+/*
+ * Number of data bytes following a status byte.
+ */
+pub(crate) fn data_len(status: u8) -> usize {
+    match status {
+        0xf1 | 0xf3 => 1,
+        MIDI_MSG_SONG_POSITION => 2,
+        MIDI_MSG_STATUS_SYSTEM..=0xff => 0,
+        _ => match status & !MIDI_MSG_STATUS_CHANNEL_MASK {
+            MIDI_MSG_STATUS_PROGRAM_CHANGE | MIDI_MSG_STATUS_CHANNEL_PRESSURE => 1,
+            _ => 2,
+        },
+    }
+}
+
+/*
+ * Unpacks the MIDI bytes of a USB MIDI event packet.
+ */
+pub fn usb_packet_bytes(packet: &[u8; 4]) -> &[u8] {
+    let len = match packet[0] & USB_MIDI_CIN_MASK {
+        USB_MIDI_CIN_SYSEX_END_1 | USB_MIDI_CIN_SINGLE_BYTE => 1,
+        USB_MIDI_CIN_SYSTEM_2 | USB_MIDI_CIN_SYSEX_END_2 => 2,
+        USB_MIDI_CIN_SYSTEM_3 | USB_MIDI_CIN_SYSEX | USB_MIDI_CIN_SYSEX_END_3 => 3,
+        cin @ USB_MIDI_CIN_NOTE_OFF..=USB_MIDI_CIN_PITCH_BEND => 1 + data_len(cin << 4),
+        _ => 0,
+    };
+    &packet[1..1 + len]
+}
+
+/*
+ * Extracts the channel or clock message of a USB MIDI event packet.
+ */
+pub fn from_usb_packet(packet: &[u8]) -> Option<OutputData> {
+    let packet: &[u8; 4] = packet.get(..4)?.try_into().ok()?;
+
+    let mut parser = MidiParser::new();
+    match packet[0] & USB_MIDI_CIN_MASK {
+        USB_MIDI_CIN_NOTE_OFF..=USB_MIDI_CIN_PITCH_BEND
+        | USB_MIDI_CIN_SYSTEM_3
+        | USB_MIDI_CIN_SINGLE_BYTE => usb_packet_bytes(packet)
+            .iter()
+            .find_map(|b| parser.parse(*b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(bytes: &[u8]) -> std::vec::Vec<OutputData> {
+        let mut parser = MidiParser::new();
+        bytes.iter().filter_map(|b| parser.parse(*b)).collect()
+    }
+
+    fn cc(channel: u8, control: u8, value: u8) -> OutputData {
+        OutputData::MidiMsgCc(MidiMsgCc {
+            channel,
+            control,
+            value,
+        })
+    }
+
+    #[test]
+    fn parse_running_status() {
+        assert_eq!(
+            parse_all(&[0xb3, 7, 100, 7, 101, 8, 0]),
+            [cc(3, 7, 100), cc(3, 7, 101), cc(3, 8, 0)]
+        );
+    }
+
+    #[test]
+    fn parse_note_on_without_velocity() {
+        assert_eq!(
+            parse_all(&[0x91, 60, 0, 0x81, 61, 64]),
+            [
+                OutputData::MidiMsgNote(MidiMsgNote {
+                    channel: 1,
+                    key: 60,
+                    on: false,
+                    velocity: 0,
+                }),
+                OutputData::MidiMsgNote(MidiMsgNote {
+                    channel: 1,
+                    key: 61,
+                    on: false,
+                    velocity: 64,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_realtime_skip_sysex() {
+        assert_eq!(
+            parse_all(&[0xb0, 0xf8, 7, 0xfe, 1, 0xf0, 0x7d, 1, 2, 0xf7, 7, 2, 0xb0, 7, 3]),
+            [OutputData::Clock, cc(0, 7, 1), cc(0, 7, 3)]
+        );
+    }
+
+    #[test]
+    fn parse_song_position() {
+        assert_eq!(
+            parse_all(&[0xb0, 7, 1, 0xf2, 0x10, 0x01, 7, 2, 0xfa]),
+            [
+                cc(0, 7, 1),
+                OutputData::SongPosition(0x90),
+                OutputData::Start
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_program_change() {
+        assert_eq!(parse_all(&[0xc0, 5, 6, 0xb0, 7, 1]), [cc(0, 7, 1)]);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let msg = MidiMsgCc {
+            channel: 9,
+            control: 74,
+            value: 23,
+        };
+        assert_eq!(parse_all(&msg.to_bytes()), [OutputData::MidiMsgCc(msg)]);
+
+        let msg = MidiMsgPitchBend {
+            channel: 3,
+            value: 0x3fff,
+        };
+        assert_eq!(msg.to_bytes(), [0xe3, 0x7f, 0x7f]);
+        assert_eq!(
+            parse_all(&msg.to_bytes()),
+            [OutputData::MidiMsgPitchBend(msg)]
+        );
+    }
+
+    #[test]
+    fn usb_packet() {
+        assert_eq!(from_usb_packet(&[0x0b, 0xb2, 4, 5]), Some(cc(2, 4, 5)));
+        assert_eq!(from_usb_packet(&[0x04, 0xf0, 0x7d, 1]), None);
+        assert_eq!(
+            from_usb_packet(&[0x0f, 0xf8, 0, 0]),
+            Some(OutputData::Clock)
+        );
+        assert_eq!(
+            from_usb_packet(&[0x03, 0xf2, 0, 1]),
+            Some(OutputData::SongPosition(0x80))
+        );
+    }
+}
@@ -0,0 +1,612 @@
+use crate::clock::{CLOCK_PPQN, GATE_MAX, MICROS_PER_MINUTE};
+use crate::output::{Event, Filter, MidiMsgNote, OutputData};
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+const NOTES_HELD_MAX: usize = 16;
+const CHORD_NOTES_MAX: usize = 8;
+const ARP_NOTES_MAX: usize = 64;
+const ARP_DIVISION_DEFAULT: u8 = 6;
+const ARP_GATE_DEFAULT: u8 = 50;
+const ARP_OCTAVES_MAX: u8 = 4;
+const MIDI_KEY_MAX: i16 = 0x7f;
+
+/*
+ * Most output data a single call of the note stage hands out.
+ */
+pub const NOTES_OUT_MAX: usize = 2 * CHORD_NOTES_MAX;
+
+/*
+ * Input index used in events of notes generated by the arpeggiator.
+ */
+pub const ARPEGGIATOR_INPUT: usize = usize::MAX - 2;
+
+/*
+ * Plays a stored set of intervals for every note. Each note off ends the
+ * notes its note on started, even if the intervals changed meanwhile.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Chord {
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "std::vec::Vec<i8>", length(max = "CHORD_NOTES_MAX"))
+    )]
+    pub intervals: Vec<i8, CHORD_NOTES_MAX>,
+    #[serde(skip)]
+    held: Vec<(u8, u8, Vec<u8, CHORD_NOTES_MAX>), NOTES_HELD_MAX>,
+}
+
+#[derive(Debug, Default, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ArpMode {
+    #[default]
+    Up,
+    Down,
+    Random,
+    // in the order the notes were pressed
+    Played,
+}
+
+/*
+ * Plays the held notes one after another, every `division` clocks at 24
+ * ppqn of the clock the device runs on, for `gate` percent of a step. The
+ * first note plays on the first clock after a key went down, nothing is
+ * played while the clock is stopped.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Arpeggiator {
+    #[serde(default)]
+    pub mode: ArpMode,
+    #[serde(default = "Arpeggiator::default_octaves")]
+    #[cfg_attr(feature = "schema", schemars(range(min = 1, max = "ARP_OCTAVES_MAX")))]
+    pub octaves: u8,
+    #[serde(default = "Arpeggiator::default_division")]
+    #[cfg_attr(feature = "schema", schemars(range(min = 1)))]
+    pub division: u8,
+    #[serde(default = "Arpeggiator::default_gate")]
+    #[cfg_attr(feature = "schema", schemars(range(max = "GATE_MAX")))]
+    pub gate: u8,
+    // held notes in the order they were pressed
+    #[serde(skip)]
+    held: Vec<MidiMsgNote, NOTES_HELD_MAX>,
+    #[serde(skip)]
+    sounding: Option<MidiMsgNote>,
+    #[serde(skip)]
+    step: usize,
+    // clocks since the first note was held
+    #[serde(skip)]
+    clocks: u32,
+    // time of the clock the next note is due on
+    #[serde(skip)]
+    due: Option<u64>,
+    #[serde(skip)]
+    off_at: Option<u64>,
+    #[serde(skip, default = "Arpeggiator::default_seed")]
+    seed: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum NoteProcessor {
+    Chord(Chord),
+    Arpeggiator(Arpeggiator),
+}
+
+/*
+ * Processes the notes matching the filter on their way from the handlers
+ * to the outputs. All other output data passes unchanged.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct NoteStage {
+    #[serde(default)]
+    pub filter: Filter,
+    pub processor: NoteProcessor,
+}
+
+fn note(m: &MidiMsgNote, key: u8, on: bool) -> OutputData {
+    OutputData::MidiMsgNote(MidiMsgNote {
+        key,
+        on,
+        velocity: if on { m.velocity } else { 0 },
+        ..*m
+    })
+}
+
+impl Chord {
+    pub fn new(intervals: &[i8]) -> Self {
+        Self {
+            intervals: Vec::from_slice(intervals).unwrap_or_default(),
+            held: Vec::new(),
+        }
+    }
+
+    fn process(&mut self, m: &MidiMsgNote, out: &mut Vec<OutputData, NOTES_OUT_MAX>) {
+        let held = self
+            .held
+            .iter()
+            .position(|(channel, key, _)| *channel == m.channel && *key == m.key);
+
+        // a retriggered note ends its notes first
+        if let Some(idx) = held {
+            let (_, _, keys) = self.held.swap_remove(idx);
+            for key in keys {
+                out.push(note(m, key, false)).ok();
+            }
+        } else if !m.on {
+            // not started here, pass it on as is
+            out.push(OutputData::MidiMsgNote(*m)).ok();
+        }
+
+        if !m.on {
+            return;
+        }
+        let keys: Vec<u8, CHORD_NOTES_MAX> = self
+            .intervals
+            .iter()
+            .map(|i| m.key as i16 + *i as i16)
+            .filter(|k| (0..=MIDI_KEY_MAX).contains(k))
+            .map(|k| k as u8)
+            .collect();
+        if self.held.push((m.channel, m.key, keys.clone())).is_err() {
+            return;
+        }
+        for key in keys {
+            out.push(note(m, key, true)).ok();
+        }
+    }
+
+    fn release(&mut self) -> Option<OutputData> {
+        loop {
+            let (channel, _, keys) = self.held.last_mut()?;
+            if let Some(key) = keys.pop() {
+                return Some(OutputData::MidiMsgNote(MidiMsgNote {
+                    channel: *channel,
+                    key,
+                    on: false,
+                    velocity: 0,
+                }));
+            }
+            self.held.pop();
+        }
+    }
+}
+
+impl Arpeggiator {
+    pub fn new(mode: ArpMode, octaves: u8) -> Self {
+        Self {
+            mode,
+            octaves,
+            division: Self::default_division(),
+            gate: Self::default_gate(),
+            held: Vec::new(),
+            sounding: None,
+            step: 0,
+            clocks: 0,
+            due: None,
+            off_at: None,
+            seed: Self::default_seed(),
+        }
+    }
+
+    fn default_octaves() -> u8 {
+        1
+    }
+
+    fn default_division() -> u8 {
+        ARP_DIVISION_DEFAULT
+    }
+
+    fn default_gate() -> u8 {
+        ARP_GATE_DEFAULT
+    }
+
+    fn default_seed() -> u32 {
+        0x2545_f491
+    }
+
+    fn process(&mut self, m: &MidiMsgNote, out: &mut Vec<OutputData, NOTES_OUT_MAX>) {
+        let held = self
+            .held
+            .iter()
+            .position(|h| h.channel == m.channel && h.key == m.key);
+        if let Some(idx) = held {
+            self.held.remove(idx);
+        }
+
+        if m.on {
+            self.held.push(*m).ok();
+        } else if held.is_none() {
+            out.push(OutputData::MidiMsgNote(*m)).ok();
+        }
+
+        if self.held.is_empty() {
+            self.end(out);
+            self.reset();
+        }
+    }
+
+    /*
+     * Counts a clock received at the given time, every `division` clocks a
+     * note becomes due.
+     */
+    fn clock(&mut self, now: u64) {
+        if self.held.is_empty() {
+            return;
+        }
+        let clocks = self.clocks;
+        self.clocks = self.clocks.saturating_add(1);
+        if clocks % self.division.max(1) as u32 == 0 {
+            self.due = Some(now);
+        }
+    }
+
+    fn tick(&mut self, now: u64, bpm: u16, out: &mut Vec<OutputData, NOTES_OUT_MAX>) {
+        if self.off_at.is_some_and(|off_at| now >= off_at) {
+            self.end(out);
+        }
+        // a late tick plays the due note once, it doesn't catch up
+        if self.due.filter(|due| now >= *due).is_none() {
+            return;
+        }
+        self.due = None;
+        self.end(out);
+
+        let Some(next) = self.pick() else {
+            return;
+        };
+        let step_us =
+            self.division.max(1) as u64 * MICROS_PER_MINUTE / (bpm.max(1) as u64 * CLOCK_PPQN);
+        out.push(OutputData::MidiMsgNote(next)).ok();
+        self.sounding = Some(next);
+        self.off_at = Some(now + step_us * self.gate.min(GATE_MAX) as u64 / GATE_MAX as u64);
+    }
+
+    fn release(&mut self) -> Option<OutputData> {
+        self.held.clear();
+        self.reset();
+        self.off_at = None;
+        self.sounding.take().map(|m| note(&m, m.key, false))
+    }
+
+    fn reset(&mut self) {
+        self.step = 0;
+        self.clocks = 0;
+        self.due = None;
+    }
+
+    /*
+     * Ends the sounding note, if any.
+     */
+    fn end(&mut self, out: &mut Vec<OutputData, NOTES_OUT_MAX>) {
+        if let Some(m) = self.sounding.take() {
+            out.push(note(&m, m.key, false)).ok();
+        }
+        self.off_at = None;
+    }
+
+    fn pick(&mut self) -> Option<MidiMsgNote> {
+        let mut notes: Vec<MidiMsgNote, NOTES_HELD_MAX> = self.held.clone();
+        match self.mode {
+            ArpMode::Up | ArpMode::Random => notes.sort_unstable_by_key(|m| m.key),
+            ArpMode::Down => notes.sort_unstable_by_key(|m| u8::MAX - m.key),
+            ArpMode::Played => (),
+        }
+
+        let mut pattern: Vec<MidiMsgNote, ARP_NOTES_MAX> = Vec::new();
+        for octave in 0..self.octaves.clamp(1, ARP_OCTAVES_MAX) as i16 {
+            for m in notes.iter() {
+                let offset = if self.mode == ArpMode::Down {
+                    -12 * octave
+                } else {
+                    12 * octave
+                };
+                let key = m.key as i16 + offset;
+                if (0..=MIDI_KEY_MAX).contains(&key) {
+                    pattern
+                        .push(MidiMsgNote {
+                            key: key as u8,
+                            ..*m
+                        })
+                        .ok();
+                }
+            }
+        }
+        if pattern.is_empty() {
+            return None;
+        }
+
+        let idx = match self.mode {
+            ArpMode::Random => self.random() as usize % pattern.len(),
+            _ => self.step % pattern.len(),
+        };
+        self.step = self.step.wrapping_add(1);
+        Some(pattern[idx])
+    }
+
+    fn random(&mut self) -> u32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+}
+
+impl NoteStage {
+    pub fn new(processor: NoteProcessor) -> Self {
+        Self {
+            filter: Filter::default(),
+            processor,
+        }
+    }
+
+    /*
+     * Passes output data of a handler through the stage.
+     */
+    pub fn process(&mut self, data: OutputData, event: &Event) -> Vec<OutputData, NOTES_OUT_MAX> {
+        let mut out = Vec::new();
+        let OutputData::MidiMsgNote(m) = data else {
+            out.push(data).ok();
+            return out;
+        };
+        if !self.filter.matches(&data, event) {
+            out.push(data).ok();
+            return out;
+        }
+
+        match &mut self.processor {
+            NoteProcessor::Chord(c) => c.process(&m, &mut out),
+            NoteProcessor::Arpeggiator(a) => a.process(&m, &mut out),
+        }
+        out
+    }
+
+    /*
+     * Follows the clock the device runs on, received at the given time in
+     * microseconds.
+     */
+    pub fn receive(&mut self, data: &OutputData, now: u64) {
+        if let (OutputData::Clock, NoteProcessor::Arpeggiator(a)) = (data, &mut self.processor) {
+            a.clock(now);
+        }
+    }
+
+    /*
+     * Hands out the note offs of all notes the stage started, one at a
+     * time. Afterwards the stage holds no notes.
+     */
+    pub fn release(&mut self) -> Option<OutputData> {
+        match &mut self.processor {
+            NoteProcessor::Chord(c) => c.release(),
+            NoteProcessor::Arpeggiator(a) => a.release(),
+        }
+    }
+
+    /*
+     * Moves the stage forward to the given time in microseconds at the
+     * tempo the device runs on.
+     */
+    pub fn tick(&mut self, now: u64, bpm: u16) -> Vec<OutputData, NOTES_OUT_MAX> {
+        let mut out = Vec::new();
+        if let NoteProcessor::Arpeggiator(a) = &mut self.processor {
+            a.tick(now, bpm, &mut out);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 16th at 125 BPM lasts 120ms
+    const BPM: u16 = 125;
+    const CLOCK_US: u64 = 20_000;
+
+    fn event() -> Event {
+        Event {
+            input: 0,
+            input_type: "Button",
+            handler: "MidiNote",
+            timestamp: 0,
+        }
+    }
+
+    fn key(key: u8, on: bool) -> OutputData {
+        OutputData::MidiMsgNote(MidiMsgNote {
+            channel: 0,
+            key,
+            on,
+            velocity: if on { 100 } else { 0 },
+        })
+    }
+
+    fn keys(data: &[OutputData]) -> std::vec::Vec<(u8, bool)> {
+        data.iter()
+            .filter_map(|d| match d {
+                OutputData::MidiMsgNote(m) => Some((m.key, m.on)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn play(stage: &mut NoteStage, until: u64) -> std::vec::Vec<(u64, u8, bool)> {
+        let mut played = std::vec::Vec::new();
+        for now in (0..until).step_by(1000) {
+            if now % CLOCK_US == 0 {
+                stage.receive(&OutputData::Clock, now);
+            }
+            for (k, on) in keys(&stage.tick(now, BPM)) {
+                played.push((now, k, on));
+            }
+        }
+        played
+    }
+
+    #[test]
+    fn chord() {
+        let mut stage = NoteStage::new(NoteProcessor::Chord(Chord::new(&[0, 4, 7])));
+
+        assert_eq!(
+            keys(&stage.process(key(60, true), &event())),
+            [(60, true), (64, true), (67, true)]
+        );
+        // changing the chord doesn't leave notes hanging
+        stage.processor = NoteProcessor::Chord(Chord {
+            intervals: Vec::from_slice(&[0, 3]).unwrap(),
+            held: match &stage.processor {
+                NoteProcessor::Chord(c) => c.held.clone(),
+                _ => unreachable!(),
+            },
+        });
+        assert_eq!(
+            keys(&stage.process(key(60, false), &event())),
+            [(60, false), (64, false), (67, false)]
+        );
+        // unknown note offs pass
+        assert_eq!(
+            keys(&stage.process(key(61, false), &event())),
+            [(61, false)]
+        );
+    }
+
+    #[test]
+    fn chord_out_of_range() {
+        let mut stage = NoteStage::new(NoteProcessor::Chord(Chord::new(&[0, 12])));
+
+        assert_eq!(
+            keys(&stage.process(key(120, true), &event())),
+            [(120, true)]
+        );
+        assert_eq!(
+            keys(&stage.process(key(120, false), &event())),
+            [(120, false)]
+        );
+    }
+
+    #[test]
+    fn filter() {
+        let mut stage = NoteStage::new(NoteProcessor::Chord(Chord::new(&[0, 7])));
+        stage.filter.inputs = Some(Vec::from_slice(&[1]).unwrap());
+
+        assert_eq!(keys(&stage.process(key(60, true), &event())), [(60, true)]);
+    }
+
+    #[test]
+    fn arp_up_two_octaves() {
+        let mut stage =
+            NoteStage::new(NoteProcessor::Arpeggiator(Arpeggiator::new(ArpMode::Up, 2)));
+
+        assert!(stage.process(key(64, true), &event()).is_empty());
+        assert!(stage.process(key(60, true), &event()).is_empty());
+        assert_eq!(
+            play(&mut stage, 480_000),
+            [
+                (0, 60, true),
+                (60_000, 60, false),
+                (120_000, 64, true),
+                (180_000, 64, false),
+                (240_000, 72, true),
+                (300_000, 72, false),
+                (360_000, 76, true),
+                (420_000, 76, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn arp_played_order_and_release() {
+        let mut arp = Arpeggiator::new(ArpMode::Played, 1);
+        arp.gate = 100;
+        let mut stage = NoteStage::new(NoteProcessor::Arpeggiator(arp));
+
+        stage.process(key(64, true), &event());
+        stage.process(key(60, true), &event());
+        assert_eq!(
+            play(&mut stage, 240_000),
+            [(0, 64, true), (120_000, 64, false), (120_000, 60, true)]
+        );
+
+        // releasing the last note ends the sounding one right away
+        stage.process(key(64, false), &event());
+        assert_eq!(
+            keys(&stage.process(key(60, false), &event())),
+            [(60, false)]
+        );
+        assert!(play(&mut stage, 480_000).is_empty());
+    }
+
+    #[test]
+    fn arp_down_and_random() {
+        let mut stage = NoteStage::new(NoteProcessor::Arpeggiator(Arpeggiator::new(
+            ArpMode::Down,
+            1,
+        )));
+        stage.process(key(60, true), &event());
+        stage.process(key(67, true), &event());
+        let on: std::vec::Vec<u8> = play(&mut stage, 480_000)
+            .into_iter()
+            .filter(|(_, _, on)| *on)
+            .map(|(_, k, _)| k)
+            .collect();
+        assert_eq!(on, [67, 60, 67, 60]);
+
+        let mut stage = NoteStage::new(NoteProcessor::Arpeggiator(Arpeggiator::new(
+            ArpMode::Random,
+            1,
+        )));
+        stage.process(key(60, true), &event());
+        stage.process(key(67, true), &event());
+        let played = play(&mut stage, 2_400_000);
+        let ons = played.iter().filter(|(_, _, on)| *on).count();
+        let offs = played.iter().filter(|(_, _, on)| !*on).count();
+        assert_eq!((ons, offs), (20, 20));
+        assert!(played.iter().all(|(_, k, _)| *k == 60 || *k == 67));
+    }
+
+    #[test]
+    fn chord_release() {
+        let mut stage = NoteStage::new(NoteProcessor::Chord(Chord::new(&[0, 7])));
+        stage.process(key(60, true), &event());
+        stage.process(key(62, true), &event());
+
+        let released: std::vec::Vec<OutputData> = core::iter::from_fn(|| stage.release()).collect();
+        assert_eq!(
+            keys(&released),
+            [(69, false), (62, false), (67, false), (60, false)]
+        );
+        assert_eq!(stage.release(), None);
+    }
+
+    #[test]
+    fn arp_needs_clock() {
+        let mut stage =
+            NoteStage::new(NoteProcessor::Arpeggiator(Arpeggiator::new(ArpMode::Up, 1)));
+        stage.process(key(60, true), &event());
+
+        for now in (0..480_000).step_by(1000) {
+            assert!(stage.tick(now, BPM).is_empty());
+        }
+    }
+
+    #[test]
+    fn arp_no_burst_after_stall() {
+        let mut stage =
+            NoteStage::new(NoteProcessor::Arpeggiator(Arpeggiator::new(ArpMode::Up, 1)));
+        stage.process(key(60, true), &event());
+        stage.process(key(64, true), &event());
+
+        // clocks came in for four steps, but the stage wasn't ticked
+        for now in (0..480_000).step_by(CLOCK_US as usize) {
+            stage.receive(&OutputData::Clock, now);
+        }
+        assert_eq!(keys(&stage.tick(480_000, BPM)), [(60, true)]);
+        assert!(stage.tick(481_000, BPM).is_empty());
+
+        assert_eq!(stage.release(), Some(key(60, false)));
+        assert_eq!(stage.release(), None);
+    }
+}
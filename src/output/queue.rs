@@ -0,0 +1,192 @@
+use crate::output::{OutputData, OutputError};
+
+use heapless::Deque;
+use serde::{Deserialize, Serialize};
+
+/*
+ * Decides what happens to output data pushed to a full queue.
+ */
+#[derive(Debug, Default, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum OverflowPolicy {
+    DropOldest,
+    #[default]
+    DropNewest,
+    // replaces the latest queued value of the same controller, drops the
+    // newest data if there is none
+    CoalesceCc,
+}
+
+/*
+ * Bounded queue in front of outputs which can't keep up. The depth can be
+ * lowered at runtime up to the capacity N. Pushing never blocks, on
+ * overflow data is dropped according to the policy and reported as
+ * `OutputError::Overflow`.
+ */
+pub struct OutputQueue<const N: usize> {
+    buf: Deque<OutputData, N>,
+    depth: usize,
+    policy: OverflowPolicy,
+}
+
+impl<const N: usize> OutputQueue<N> {
+    pub const fn new(depth: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            buf: Deque::new(),
+            depth: if depth == 0 {
+                1
+            } else if depth < N {
+                depth
+            } else {
+                N
+            },
+            policy,
+        }
+    }
+
+    pub fn configure(&mut self, depth: usize, policy: OverflowPolicy) {
+        self.depth = depth.clamp(1, N);
+        self.policy = policy;
+        while self.buf.len() > self.depth {
+            self.buf.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn push(&mut self, data: OutputData) -> Result<(), OutputError> {
+        if data.to_bytes().is_none() {
+            return Err(OutputError::Unsupported);
+        }
+
+        if self.buf.len() < self.depth {
+            self.buf.push_back(data).ok();
+            return Ok(());
+        }
+
+        match (self.policy, data) {
+            (OverflowPolicy::DropOldest, _) => {
+                self.buf.pop_front();
+                self.buf.push_back(data).ok();
+                Err(OutputError::Overflow)
+            }
+            (OverflowPolicy::CoalesceCc, OutputData::MidiMsgCc(new)) => {
+                let queued = self.buf.iter_mut().rev().find_map(|d| match d {
+                    OutputData::MidiMsgCc(m)
+                        if m.channel == new.channel && m.control == new.control =>
+                    {
+                        Some(m)
+                    }
+                    _ => None,
+                });
+                match queued {
+                    Some(m) => {
+                        m.value = new.value;
+                        Ok(())
+                    }
+                    None => Err(OutputError::Overflow),
+                }
+            }
+            _ => Err(OutputError::Overflow),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<OutputData> {
+        self.buf.pop_front()
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{MidiMsgCc, MidiMsgNote};
+
+    fn cc(control: u8, value: u8) -> OutputData {
+        OutputData::MidiMsgCc(MidiMsgCc {
+            channel: 0,
+            control,
+            value,
+        })
+    }
+
+    fn note(key: u8) -> OutputData {
+        OutputData::MidiMsgNote(MidiMsgNote {
+            channel: 0,
+            key,
+            on: true,
+            velocity: 100,
+        })
+    }
+
+    fn drain<const N: usize>(q: &mut OutputQueue<N>) -> std::vec::Vec<OutputData> {
+        core::iter::from_fn(|| q.pop()).collect()
+    }
+
+    #[test]
+    fn reject_dummy() {
+        let mut q: OutputQueue<2> = OutputQueue::new(2, OverflowPolicy::DropNewest);
+
+        assert_eq!(q.push(OutputData::Dummy), Err(OutputError::Unsupported));
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn drop_newest() {
+        let mut q: OutputQueue<4> = OutputQueue::new(2, OverflowPolicy::DropNewest);
+
+        assert_eq!(q.push(cc(1, 1)), Ok(()));
+        assert_eq!(q.push(cc(1, 2)), Ok(()));
+        assert_eq!(q.push(cc(1, 3)), Err(OutputError::Overflow));
+        assert_eq!(drain(&mut q), [cc(1, 1), cc(1, 2)]);
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let mut q: OutputQueue<2> = OutputQueue::new(2, OverflowPolicy::DropOldest);
+
+        q.push(cc(1, 1)).unwrap();
+        q.push(note(60)).unwrap();
+        assert_eq!(q.push(cc(1, 3)), Err(OutputError::Overflow));
+        assert_eq!(drain(&mut q), [note(60), cc(1, 3)]);
+    }
+
+    #[test]
+    fn coalesce_cc() {
+        let mut q: OutputQueue<3> = OutputQueue::new(3, OverflowPolicy::CoalesceCc);
+
+        q.push(cc(1, 1)).unwrap();
+        q.push(note(60)).unwrap();
+        q.push(cc(1, 2)).unwrap();
+        assert_eq!(q.push(cc(1, 3)), Ok(()));
+        assert_eq!(q.push(cc(2, 1)), Err(OutputError::Overflow));
+        assert_eq!(q.push(note(61)), Err(OutputError::Overflow));
+        assert_eq!(drain(&mut q), [cc(1, 1), note(60), cc(1, 3)]);
+    }
+
+    #[test]
+    fn configure_depth() {
+        let mut q: OutputQueue<4> = OutputQueue::new(4, OverflowPolicy::DropNewest);
+        for v in 0..4 {
+            q.push(cc(1, v)).unwrap();
+        }
+
+        q.configure(2, OverflowPolicy::DropOldest);
+        assert_eq!(drain(&mut q), [cc(1, 2), cc(1, 3)]);
+
+        q.configure(8, OverflowPolicy::DropNewest);
+        for v in 0..4 {
+            q.push(cc(1, v)).unwrap();
+        }
+        assert_eq!(q.push(cc(1, 4)), Err(OutputError::Overflow));
+    }
+}
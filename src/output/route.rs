@@ -0,0 +1,206 @@
+#[cfg(feature = "schema")]
+use crate::config::MIDI_CHANNEL_MAX;
+use crate::output::{Event, OutputData};
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+const ROUTE_FILTER_ITEMS_MAX: usize = 16;
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum MessageType {
+    Cc,
+    Note,
+    PitchBend,
+    Clock,
+    Transport,
+}
+
+/*
+ * Selects output data by its channel, message type and originating
+ * input. Criteria which are not set match everything.
+ */
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Filter {
+    #[serde(default)]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(
+            with = "Option<std::vec::Vec<u8>>",
+            length(max = "ROUTE_FILTER_ITEMS_MAX"),
+            inner(range(max = "MIDI_CHANNEL_MAX"))
+        )
+    )]
+    pub channels: Option<Vec<u8, ROUTE_FILTER_ITEMS_MAX>>,
+    #[serde(default)]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(
+            with = "Option<std::vec::Vec<MessageType>>",
+            length(max = "ROUTE_FILTER_ITEMS_MAX")
+        )
+    )]
+    pub messages: Option<Vec<MessageType, ROUTE_FILTER_ITEMS_MAX>>,
+    #[serde(default)]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(
+            with = "Option<std::vec::Vec<usize>>",
+            length(max = "ROUTE_FILTER_ITEMS_MAX")
+        )
+    )]
+    pub inputs: Option<Vec<usize, ROUTE_FILTER_ITEMS_MAX>>,
+}
+
+/*
+ * Passes output data matching the filter to the output with the given
+ * index, optionally moving it to another channel.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Route {
+    pub output: usize,
+    #[serde(default)]
+    pub filter: Filter,
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_CHANNEL_MAX")))]
+    pub channel: Option<u8>,
+}
+
+impl Filter {
+    pub fn matches(&self, data: &OutputData, event: &Event) -> bool {
+        let channel = match (&self.channels, data.channel()) {
+            (Some(channels), Some(c)) => channels.contains(&c),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let message = match (&self.messages, data.message_type()) {
+            (Some(messages), Some(m)) => messages.contains(&m),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let input = match &self.inputs {
+            Some(inputs) => inputs.contains(&event.input),
+            None => true,
+        };
+
+        channel && message && input
+    }
+}
+
+/*
+ * Returns the output data to send to the given output or `None` if it
+ * should be skipped. Outputs without any route receive everything, all
+ * others receive the data of the first matching route.
+ */
+pub fn route(
+    routes: &[Route],
+    output: usize,
+    data: &OutputData,
+    event: &Event,
+) -> Option<OutputData> {
+    let mut routes = routes.iter().filter(|r| r.output == output).peekable();
+    if routes.peek().is_none() {
+        return Some(*data);
+    }
+
+    routes
+        .find(|r| r.filter.matches(data, event))
+        .map(|r| match r.channel {
+            Some(c) => data.with_channel(c),
+            None => *data,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{MidiMsgCc, MidiMsgNote};
+
+    const EVENT: Event = Event {
+        input: 1,
+        input_type: "Encoder",
+        handler: "MidiRel",
+        timestamp: 0,
+    };
+
+    fn cc(channel: u8) -> OutputData {
+        OutputData::MidiMsgCc(MidiMsgCc {
+            channel,
+            control: 4,
+            value: 63,
+        })
+    }
+
+    fn note(channel: u8) -> OutputData {
+        OutputData::MidiMsgNote(MidiMsgNote {
+            channel,
+            key: 60,
+            on: true,
+            velocity: 100,
+        })
+    }
+
+    fn route_to(output: usize, filter: Filter, channel: Option<u8>) -> Route {
+        Route {
+            output,
+            filter,
+            channel,
+        }
+    }
+
+    #[test]
+    fn unrouted_output_receives_all() {
+        let routes = [route_to(0, Filter::default(), None)];
+
+        assert_eq!(route(&routes, 1, &cc(2), &EVENT), Some(cc(2)));
+        assert_eq!(route(&routes, 1, &note(2), &EVENT), Some(note(2)));
+    }
+
+    #[test]
+    fn filter_by_message_type() {
+        let notes = Filter {
+            messages: Some(Vec::from_slice(&[MessageType::Note]).unwrap()),
+            ..Filter::default()
+        };
+        let ccs = Filter {
+            messages: Some(Vec::from_slice(&[MessageType::Cc]).unwrap()),
+            ..Filter::default()
+        };
+        let routes = [route_to(0, notes, None), route_to(1, ccs, None)];
+
+        assert_eq!(route(&routes, 0, &cc(2), &EVENT), None);
+        assert_eq!(route(&routes, 0, &note(2), &EVENT), Some(note(2)));
+        assert_eq!(route(&routes, 1, &cc(2), &EVENT), Some(cc(2)));
+        assert_eq!(route(&routes, 1, &note(2), &EVENT), None);
+    }
+
+    #[test]
+    fn filter_by_channel_and_input() {
+        let filter = Filter {
+            channels: Some(Vec::from_slice(&[2, 3]).unwrap()),
+            inputs: Some(Vec::from_slice(&[1]).unwrap()),
+            ..Filter::default()
+        };
+        let routes = [route_to(0, filter, None)];
+        let other_input = Event { input: 0, ..EVENT };
+
+        assert_eq!(route(&routes, 0, &cc(3), &EVENT), Some(cc(3)));
+        assert_eq!(route(&routes, 0, &cc(4), &EVENT), None);
+        assert_eq!(route(&routes, 0, &cc(3), &other_input), None);
+        assert_eq!(route(&routes, 0, &OutputData::Dummy, &EVENT), None);
+    }
+
+    #[test]
+    fn remap_channel_of_first_match() {
+        let routes = [
+            route_to(0, Filter::default(), Some(9)),
+            route_to(0, Filter::default(), Some(5)),
+        ];
+
+        assert_eq!(route(&routes, 0, &cc(2), &EVENT), Some(cc(9)));
+        assert_eq!(route(&routes, 0, &note(0), &EVENT), Some(note(9)));
+    }
+}
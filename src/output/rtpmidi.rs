@@ -0,0 +1,308 @@
+use crate::output::{OutputData, OutputError};
+
+use async_std::net::{SocketAddr, UdpSocket};
+use std::cell::Cell;
+use std::io;
+use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const APPLEMIDI_SIGNATURE: [u8; 2] = [0xff, 0xff];
+const APPLEMIDI_PROTOCOL_VERSION: u32 = 2;
+const APPLEMIDI_CMD_INVITATION: [u8; 2] = *b"IN";
+const APPLEMIDI_CMD_ACCEPT: [u8; 2] = *b"OK";
+const APPLEMIDI_CMD_SYNC: [u8; 2] = *b"CK";
+const APPLEMIDI_CMD_END: [u8; 2] = *b"BY";
+const APPLEMIDI_SYNC_LEN: usize = 36;
+
+const RTP_HEADER_VERSION: u8 = 2 << 6;
+const RTP_HEADER_MARKER: u8 = 1 << 7;
+const RTP_PAYLOAD_TYPE_MIDI: u8 = 0x61;
+const RTP_HEADER_LEN: usize = 12;
+const RTP_MIDI_LEN_MASK: u8 = 0xf;
+
+const PACKET_SIZE_MAX: usize = 128;
+
+/*
+ * An AppleMIDI command as received on either the control or the data port.
+ */
+enum Command {
+    Invitation { token: u32 },
+    Sync { count: u8, timestamps: [u64; 3] },
+    End,
+}
+
+/*
+ * Network MIDI session output. Both ports are bound on creation, the data
+ * port being the control port + 1. The output acts as session responder,
+ * it accepts invitations and answers clock synchronization requests. As
+ * long as no peer has joined, output data is discarded. MIDI commands are
+ * sent without recovery journal.
+ */
+pub struct RtpMidiOut {
+    name: &'static str,
+    control: UdpSocket,
+    data: UdpSocket,
+    ssrc: u32,
+    start: Instant,
+    sequence: Cell<u16>,
+    control_peer: Cell<Option<SocketAddr>>,
+    data_peer: Cell<Option<SocketAddr>>,
+}
+
+impl RtpMidiOut {
+    pub async fn bind(name: &'static str, addr: SocketAddr) -> io::Result<Self> {
+        // the data port follows the control port
+        let port = addr.port().checked_add(1).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no data port after the control port",
+            )
+        })?;
+        let mut data_addr = addr;
+        data_addr.set_port(port);
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+
+        Ok(Self {
+            name,
+            control: UdpSocket::bind(addr).await?,
+            data: UdpSocket::bind(data_addr).await?,
+            ssrc: nanos ^ process::id(),
+            start: Instant::now(),
+            sequence: Cell::new(0),
+            control_peer: Cell::new(None),
+            data_peer: Cell::new(None),
+        })
+    }
+
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.data_peer.get().is_some()
+    }
+
+    /*
+     * Handles incoming commands until a peer has joined the session on
+     * both the control and the data port.
+     */
+    pub async fn accept(&self) -> io::Result<()> {
+        while self.control_peer.get().is_none() {
+            self.handle_control().await?;
+        }
+        while self.data_peer.get().is_none() {
+            self.handle_data().await?;
+        }
+        Ok(())
+    }
+
+    /*
+     * Receives and answers a single command on the control port.
+     */
+    pub async fn handle_control(&self) -> io::Result<()> {
+        let mut buf = [0u8; PACKET_SIZE_MAX];
+        let (len, src) = self.control.recv_from(&mut buf).await?;
+
+        match parse_command(&buf[..len]) {
+            Some(Command::Invitation { token }) => {
+                self.control_peer.set(Some(src));
+                self.accept_invitation(&self.control, token, src).await
+            }
+            Some(Command::End) => {
+                self.control_peer.set(None);
+                self.data_peer.set(None);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /*
+     * Receives and answers a single command on the data port.
+     */
+    pub async fn handle_data(&self) -> io::Result<()> {
+        let mut buf = [0u8; PACKET_SIZE_MAX];
+        let (len, src) = self.data.recv_from(&mut buf).await?;
+
+        match parse_command(&buf[..len]) {
+            Some(Command::Invitation { token }) => {
+                self.data_peer.set(Some(src));
+                self.accept_invitation(&self.data, token, src).await
+            }
+            Some(Command::Sync {
+                count: 0,
+                timestamps,
+            }) => {
+                let timestamps = [timestamps[0], self.timestamp() as u64, 0];
+                let len = encode_sync(self.ssrc, 1, &timestamps, &mut buf);
+                self.data.send_to(&buf[..len], src).await.map(|_| ())
+            }
+            Some(Command::End) => {
+                self.control_peer.set(None);
+                self.data_peer.set(None);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn run(&self, data: &OutputData) -> Result<(), OutputError> {
+        let msg = data.to_bytes().ok_or(OutputError::Unsupported)?;
+        let peer = self.data_peer.get().ok_or(OutputError::Disconnected)?;
+
+        let mut buf = [0u8; RTP_HEADER_LEN + 1 + 3];
+        let sequence = self.sequence.get();
+        self.sequence.set(sequence.wrapping_add(1));
+
+        encode_rtp_header(sequence, self.timestamp(), self.ssrc, &mut buf);
+        buf[RTP_HEADER_LEN] = RTP_MIDI_LEN_MASK & msg.len() as u8;
+        buf[RTP_HEADER_LEN + 1..][..msg.len()].copy_from_slice(&msg);
+        let len = RTP_HEADER_LEN + 1 + msg.len();
+
+        // there is no recovery journal, a lost packet is lost
+        self.data
+            .send_to(&buf[..len], peer)
+            .await
+            .map(|_| ())
+            .map_err(|_| OutputError::Io)
+    }
+
+    async fn accept_invitation(
+        &self,
+        socket: &UdpSocket,
+        token: u32,
+        peer: SocketAddr,
+    ) -> io::Result<()> {
+        let mut buf = [0u8; PACKET_SIZE_MAX];
+        let len = encode_exchange(
+            APPLEMIDI_CMD_ACCEPT,
+            token,
+            self.ssrc,
+            self.name.as_bytes(),
+            &mut buf,
+        );
+        socket.send_to(&buf[..len], peer).await.map(|_| ())
+    }
+
+    /*
+     * Session time in units of 100 microseconds as used by AppleMIDI.
+     */
+    fn timestamp(&self) -> u32 {
+        (self.start.elapsed().as_micros() / 100) as u32
+    }
+}
+
+fn parse_command(buf: &[u8]) -> Option<Command> {
+    if buf.len() < 4 || buf[0..2] != APPLEMIDI_SIGNATURE {
+        return None;
+    }
+
+    let cmd = [buf[2], buf[3]];
+    if cmd == APPLEMIDI_CMD_SYNC {
+        if buf.len() < APPLEMIDI_SYNC_LEN {
+            return None;
+        }
+        let mut timestamps = [0u64; 3];
+        for (i, ts) in timestamps.iter_mut().enumerate() {
+            let offset = 12 + i * 8;
+            *ts = u64::from_be_bytes(buf[offset..offset + 8].try_into().ok()?);
+        }
+        return Some(Command::Sync {
+            count: buf[8],
+            timestamps,
+        });
+    }
+
+    if buf.len() < 16 {
+        return None;
+    }
+    let token = u32::from_be_bytes(buf[8..12].try_into().ok()?);
+    match cmd {
+        APPLEMIDI_CMD_INVITATION => Some(Command::Invitation { token }),
+        APPLEMIDI_CMD_END => Some(Command::End),
+        _ => None,
+    }
+}
+
+fn encode_exchange(cmd: [u8; 2], token: u32, ssrc: u32, name: &[u8], buf: &mut [u8]) -> usize {
+    let name_len = name.len().min(buf.len() - 17);
+
+    buf[0..2].copy_from_slice(&APPLEMIDI_SIGNATURE);
+    buf[2..4].copy_from_slice(&cmd);
+    buf[4..8].copy_from_slice(&APPLEMIDI_PROTOCOL_VERSION.to_be_bytes());
+    buf[8..12].copy_from_slice(&token.to_be_bytes());
+    buf[12..16].copy_from_slice(&ssrc.to_be_bytes());
+    buf[16..16 + name_len].copy_from_slice(&name[..name_len]);
+    buf[16 + name_len] = 0;
+
+    17 + name_len
+}
+
+fn encode_sync(ssrc: u32, count: u8, timestamps: &[u64; 3], buf: &mut [u8]) -> usize {
+    buf[0..2].copy_from_slice(&APPLEMIDI_SIGNATURE);
+    buf[2..4].copy_from_slice(&APPLEMIDI_CMD_SYNC);
+    buf[4..8].copy_from_slice(&ssrc.to_be_bytes());
+    buf[8] = count;
+    buf[9..12].copy_from_slice(&[0; 3]);
+    for (i, ts) in timestamps.iter().enumerate() {
+        let offset = 12 + i * 8;
+        buf[offset..offset + 8].copy_from_slice(&ts.to_be_bytes());
+    }
+
+    APPLEMIDI_SYNC_LEN
+}
+
+fn encode_rtp_header(sequence: u16, timestamp: u32, ssrc: u32, buf: &mut [u8]) {
+    buf[0] = RTP_HEADER_VERSION;
+    buf[1] = RTP_HEADER_MARKER | RTP_PAYLOAD_TYPE_MIDI;
+    buf[2..4].copy_from_slice(&sequence.to_be_bytes());
+    buf[4..8].copy_from_slice(&timestamp.to_be_bytes());
+    buf[8..12].copy_from_slice(&ssrc.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exchange_roundtrip() {
+        let mut buf = [0u8; PACKET_SIZE_MAX];
+        let len = encode_exchange(APPLEMIDI_CMD_INVITATION, 42, 23, b"peer", &mut buf);
+
+        assert_eq!(len, 21);
+        match parse_command(&buf[..len]) {
+            Some(Command::Invitation { token }) => assert_eq!(token, 42),
+            _ => panic!("Wrong command parsed"),
+        }
+    }
+
+    #[test]
+    fn sync_roundtrip() {
+        let mut buf = [0u8; PACKET_SIZE_MAX];
+        let len = encode_sync(23, 1, &[1, 2, 3], &mut buf);
+
+        match parse_command(&buf[..len]) {
+            Some(Command::Sync { count, timestamps }) => {
+                assert_eq!(count, 1);
+                assert_eq!(timestamps, [1, 2, 3]);
+            }
+            _ => panic!("Wrong command parsed"),
+        }
+    }
+
+    #[test]
+    fn reject_non_applemidi() {
+        assert!(parse_command(&[0x80, 0x61, 0, 0]).is_none());
+    }
+
+    #[async_std::test]
+    async fn no_data_port() {
+        let addr = "127.0.0.1:65535".parse().unwrap();
+        let err = RtpMidiOut::bind("reset_ctrl", addr).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::output::{Event, MessageType, OutputData, OutputError};
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const SMF_FORMAT_SINGLE_TRACK: u16 = 0;
+// ticks per quarter note, together with the tempo below one tick is 1ms
+const SMF_DIVISION: u16 = 1000;
+const SMF_TEMPO_US_PER_QUARTER: u32 = 1_000_000;
+const SMF_META_TEMPO: [u8; 3] = [0xff, 0x51, 0x03];
+const SMF_META_END_OF_TRACK: [u8; 3] = [0xff, 0x2f, 0x00];
+const SMF_TRACK_LEN_OFFSET: u64 = 18;
+
+/*
+ * Writes a type 0 Standard MIDI File. Events are appended as they are
+ * written, the track length in the header is patched in on finish.
+ * Event timestamps are in microseconds, the first event marks the
+ * beginning of the track.
+ */
+pub struct SmfWriter<W: Write + Seek> {
+    inner: W,
+    track_len: u32,
+    last_timestamp: Option<u64>,
+    finished: bool,
+}
+
+impl<W: Write + Seek> SmfWriter<W> {
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        inner.write_all(b"MThd")?;
+        inner.write_all(&6u32.to_be_bytes())?;
+        inner.write_all(&SMF_FORMAT_SINGLE_TRACK.to_be_bytes())?;
+        inner.write_all(&1u16.to_be_bytes())?;
+        inner.write_all(&SMF_DIVISION.to_be_bytes())?;
+        inner.write_all(b"MTrk")?;
+        inner.write_all(&0u32.to_be_bytes())?;
+
+        let mut writer = Self {
+            inner,
+            track_len: 0,
+            last_timestamp: None,
+            finished: false,
+        };
+        writer.write_delta(0)?;
+        writer.write_raw(&SMF_META_TEMPO)?;
+        writer.write_raw(&SMF_TEMPO_US_PER_QUARTER.to_be_bytes()[1..])?;
+
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, timestamp: u64, msg: &[u8]) -> io::Result<()> {
+        // nothing may follow the end of track
+        if self.finished {
+            return Err(io::Error::other("track already finished"));
+        }
+        let last = self.last_timestamp.unwrap_or(timestamp);
+        let delta = timestamp.saturating_sub(last) / 1000;
+        // keep the remainder to not accumulate rounding errors
+        self.last_timestamp = Some(last + delta * 1000);
+
+        self.write_delta(delta as u32)?;
+        self.write_raw(msg)
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        self.write_delta(0)?;
+        self.write_raw(&SMF_META_END_OF_TRACK)?;
+
+        let end = self.inner.stream_position()?;
+        self.inner.seek(SeekFrom::Start(SMF_TRACK_LEN_OFFSET))?;
+        self.inner.write_all(&self.track_len.to_be_bytes())?;
+        self.inner.seek(SeekFrom::Start(end))?;
+        self.inner.flush()
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.finish()?;
+        Ok(self.inner)
+    }
+
+    fn write_delta(&mut self, delta: u32) -> io::Result<()> {
+        let mut buf = [0u8; 4];
+        let mut len = 0;
+        let mut v = delta & 0x0fff_ffff;
+
+        loop {
+            buf[len] = (v & 0x7f) as u8;
+            len += 1;
+            v >>= 7;
+            if v == 0 {
+                break;
+            }
+        }
+        buf[..len].reverse();
+        for b in &mut buf[..len - 1] {
+            *b |= 0x80;
+        }
+
+        self.write_raw(&buf[..len])
+    }
+
+    fn write_raw(&mut self, data: &[u8]) -> io::Result<()> {
+        self.inner.write_all(data)?;
+        self.track_len += data.len() as u32;
+        Ok(())
+    }
+}
+
+/*
+ * Records all output data into a Standard MIDI File. The file is
+ * finalized on drop or by calling `finish`.
+ */
+pub struct SmfOut {
+    writer: RefCell<SmfWriter<BufWriter<File>>>,
+}
+
+impl SmfOut {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self {
+            writer: RefCell::new(SmfWriter::new(file)?),
+        })
+    }
+
+    pub fn finish(&self) -> io::Result<()> {
+        self.writer.borrow_mut().finish()
+    }
+
+    pub async fn run(&self, data: &OutputData, event: &Event) -> Result<(), OutputError> {
+        // system realtime and common messages aren't events of a MIDI file
+        if let Some(MessageType::Clock | MessageType::Transport) = data.message_type() {
+            return Err(OutputError::Unsupported);
+        }
+        let msg = data.to_bytes().ok_or(OutputError::Unsupported)?;
+        self.writer
+            .borrow_mut()
+            .write(event.timestamp, &msg)
+            .map_err(|_| OutputError::Io)
+    }
+}
+
+impl Drop for SmfOut {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::MidiMsgCc;
+    use std::io::Cursor;
+
+    const HEADER: [u8; 18] = [
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0x03, 0xe8, b'M', b'T', b'r', b'k',
+    ];
+    const TEMPO: [u8; 7] = [0, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40];
+    const END: [u8; 4] = [0, 0xff, 0x2f, 0x00];
+
+    #[test]
+    fn empty_track() {
+        let writer = SmfWriter::new(Cursor::new(Vec::new())).unwrap();
+        let data = writer.into_inner().unwrap().into_inner();
+
+        assert_eq!(&data[..18], &HEADER);
+        assert_eq!(&data[18..22], &11u32.to_be_bytes());
+        assert_eq!(&data[22..29], &TEMPO);
+        assert_eq!(&data[29..], &END);
+    }
+
+    #[test]
+    fn delta_times() {
+        let mut writer = SmfWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.write(5_000_000, &[0xb0, 4, 63]).unwrap();
+        writer.write(5_001_500, &[0xb0, 4, 65]).unwrap();
+        writer.write(5_202_000, &[0x90, 60, 100]).unwrap();
+        let data = writer.into_inner().unwrap().into_inner();
+
+        let events = &data[29..data.len() - END.len()];
+        assert_eq!(
+            events,
+            &[
+                0x00, 0xb0, 4, 63, //
+                0x01, 0xb0, 4, 65, //
+                0x81, 0x49, 0x90, 60, 100, // 201ms incl. remainder of 500us
+            ]
+        );
+        assert_eq!(&data[18..22], &(11u32 + 13).to_be_bytes());
+    }
+
+    #[test]
+    fn write_after_finish() {
+        let mut writer = SmfWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.finish().unwrap();
+        assert!(writer.write(0, &[0x90, 60, 100]).is_err());
+
+        let data = writer.into_inner().unwrap().into_inner();
+        assert_eq!(&data[18..22], &11u32.to_be_bytes());
+        assert_eq!(&data[29..], &END);
+    }
+
+    #[async_std::test]
+    async fn only_channel_events() {
+        let path = std::env::temp_dir().join(format!("reset_ctrl-{}.mid", std::process::id()));
+        let out = SmfOut::create(&path).unwrap();
+        let event = |timestamp| Event {
+            input: 0,
+            input_type: "Clock",
+            handler: "ClockGenerator",
+            timestamp,
+        };
+        let cc = OutputData::MidiMsgCc(MidiMsgCc {
+            channel: 0,
+            control: 4,
+            value: 63,
+        });
+
+        assert_eq!(
+            out.run(&OutputData::Start, &event(0)).await,
+            Err(OutputError::Unsupported)
+        );
+        assert_eq!(
+            out.run(&OutputData::Clock, &event(0)).await,
+            Err(OutputError::Unsupported)
+        );
+        out.run(&cc, &event(10_000)).await.unwrap();
+        assert_eq!(
+            out.run(&OutputData::SongPosition(4), &event(20_000)).await,
+            Err(OutputError::Unsupported)
+        );
+        out.finish().unwrap();
+        drop(out);
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(&data[29..data.len() - END.len()], &[0x00, 0xb0, 4, 63]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::output::OutputData;
+use crate::output::{OutputData, OutputError};
 
 #[cfg(target_os = "none")]
 use defmt::info;
@@ -7,7 +7,7 @@ pub struct StdOut {}
 
 #[cfg(target_os = "linux")]
 impl StdOut {
-    pub async fn run(&self, data: &OutputData) {
+    pub async fn run(&self, data: &OutputData) -> Result<(), OutputError> {
         match data {
             OutputData::MidiMsgCc(m) => println!(
                 "[Midi CC| Channel: {}, Control: {}, value: {}]",
@@ -17,14 +17,23 @@ impl StdOut {
                 "[Midi Note: Channel: {}, Key: {}, Velocity: {}]",
                 m.channel, m.key, m.velocity
             ),
-            _ => println!("Unknown output data"),
+            OutputData::MidiMsgPitchBend(m) => println!(
+                "[Midi Pitch Bend: Channel: {}, Value: {}]",
+                m.channel, m.value
+            ),
+            OutputData::SongPosition(p) => println!("[Midi Song Position: {}]", p),
+            OutputData::Clock | OutputData::Start | OutputData::Stop | OutputData::Continue => {
+                println!("[Midi {}]", data.name())
+            }
+            _ => return Err(OutputError::Unsupported),
         }
+        Ok(())
     }
 }
 
 #[cfg(target_os = "none")]
 impl StdOut {
-    pub async fn run(&self, data: &OutputData) {
+    pub async fn run(&self, data: &OutputData) -> Result<(), OutputError> {
         match data {
             OutputData::MidiMsgCc(m) => info!(
                 "[Midi CC| Channel: {}, Control: {}, value: {}]",
@@ -34,7 +43,16 @@ impl StdOut {
                 "[Midi Note: Channel: {}, Key: {}, Velocity: {}]",
                 m.channel, m.key, m.velocity
             ),
-            _ => info!("Unknown output data"),
+            OutputData::MidiMsgPitchBend(m) => info!(
+                "[Midi Pitch Bend: Channel: {}, Value: {}]",
+                m.channel, m.value
+            ),
+            OutputData::SongPosition(p) => info!("[Midi Song Position: {}]", p),
+            OutputData::Clock | OutputData::Start | OutputData::Stop | OutputData::Continue => {
+                info!("[Midi {}]", data.name())
+            }
+            _ => return Err(OutputError::Unsupported),
         }
+        Ok(())
     }
 }
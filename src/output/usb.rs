@@ -1,19 +1,77 @@
+use core::cell::RefCell;
+
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
-use embassy_sync::channel::Channel;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use heapless::Vec;
+
+use crate::output::{OutputData, OutputError, OutputQueue, OverflowPolicy};
+
+pub const USB_QUEUE_DEPTH_MAX: usize = 32;
+
+pub static CHANNEL: UsbQueue = UsbQueue::new();
+
+/*
+ * Queue between the device and the USB MIDI task. Pushing never blocks,
+ * the MIDI task waits until data is available.
+ */
+pub struct UsbQueue {
+    queue: Mutex<ThreadModeRawMutex, RefCell<OutputQueue<USB_QUEUE_DEPTH_MAX>>>,
+    signal: Signal<ThreadModeRawMutex, ()>,
+}
+
+impl UsbQueue {
+    const fn new() -> Self {
+        Self {
+            queue: Mutex::new(RefCell::new(OutputQueue::new(
+                USB_QUEUE_DEPTH_MAX,
+                OverflowPolicy::DropNewest,
+            ))),
+            signal: Signal::new(),
+        }
+    }
+
+    pub fn configure(&self, depth: usize, policy: OverflowPolicy) {
+        self.queue.lock(|q| q.borrow_mut().configure(depth, policy));
+    }
 
-use crate::output::OutputData;
+    pub fn push(&self, data: OutputData) -> Result<(), OutputError> {
+        let result = self.queue.lock(|q| q.borrow_mut().push(data));
+        self.signal.signal(());
+        result
+    }
 
-pub static CHANNEL: Channel<ThreadModeRawMutex, [u8; 3], 1> = Channel::new();
+    /*
+     * Drops all queued data, e.g. when nobody is there to receive it.
+     */
+    pub fn clear(&self) {
+        self.queue.lock(|q| q.borrow_mut().clear());
+    }
+
+    pub async fn receive(&self) -> Vec<u8, 3> {
+        loop {
+            let data = self.queue.lock(|q| q.borrow_mut().pop());
+            if let Some(bytes) = data.and_then(|d| d.to_bytes()) {
+                return bytes;
+            }
+            self.signal.wait().await;
+        }
+    }
+}
 
 pub struct UsbOut {}
 
-#[cfg(target_os = "none")]
 impl UsbOut {
-    pub async fn run(&self, data: &OutputData) {
-        match data {
-            OutputData::MidiMsgCc(m) => CHANNEL.send(m.to_bytes()).await,
-            OutputData::MidiMsgNote(m) => CHANNEL.send(m.to_bytes()).await,
-            _ => CHANNEL.send([0; 3]).await,
-        }
+    /*
+     * Configures the shared USB queue, the depth is limited to
+     * USB_QUEUE_DEPTH_MAX.
+     */
+    pub fn new(depth: usize, policy: OverflowPolicy) -> Self {
+        CHANNEL.configure(depth, policy);
+        Self {}
+    }
+
+    pub async fn run(&self, data: &OutputData) -> Result<(), OutputError> {
+        CHANNEL.push(*data)
     }
 }
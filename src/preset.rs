@@ -0,0 +1,315 @@
+use crate::handler::{
+    ButtonHandler, EncoderHandler, Fader, McuButton, McuFunction, PotentiometerHandler, VPot,
+    MCU_CHANNEL, MCU_STRIPS,
+};
+use crate::output::OutputData;
+use crate::ui::InputType;
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+const MCU_BUTTONS_MAX: usize = 16;
+const MCU_RING_CONTROL: u8 = 0x30;
+const MCU_RING_CENTER: u8 = 1 << 6;
+const MCU_RING_MODE_SHIFT: u8 = 4;
+const MCU_RING_MODE_MASK: u8 = 0x3;
+const MCU_RING_POSITION_MASK: u8 = 0xf;
+const MCU_LCD_LEN: usize = 2 * MCU_LCD_LINE_LEN;
+const MCU_LCD_LINE_LEN: usize = 56;
+const MCU_LCD_COMMAND: u8 = 0x12;
+const MCU_SYSEX_HEADER: [u8; 3] = [0x00, 0x00, 0x66];
+const MCU_MODEL: u8 = 0x14;
+const MCU_MODEL_EXTENDER: u8 = 0x15;
+const MIDI_SYSEX_START: u8 = 0xf0;
+const MIDI_STATUS_BIT: u8 = 1 << 7;
+const MIDI_STATUS_REALTIME: u8 = 0xf8;
+
+/*
+ * Set of handlers attached to all inputs of the device, replacing the
+ * handlers configured per input.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Preset {
+    Mcu(Mcu),
+}
+
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub enum RingMode {
+    #[default]
+    Dot,
+    BoostCut,
+    Wrap,
+    Spread,
+}
+
+/*
+ * LED ring around a V-Pot as set by the DAW. A position of 0 turns the
+ * ring off, 1 to 11 select the LED.
+ */
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub struct Ring {
+    pub mode: RingMode,
+    pub position: u8,
+    pub center: bool,
+}
+
+/*
+ * Mackie Control Universal emulation. The n-th encoder becomes the V-Pot
+ * and the n-th potentiometer the fader of strip n, the potentiometer after
+ * the strips the master fader. Buttons get the functions listed in order,
+ * the select buttons of the strips otherwise. Inputs left over keep their
+ * own handlers.
+ * The LED ring and LCD feedback of the DAW is kept for display.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Mcu {
+    #[serde(default)]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "std::vec::Vec<McuFunction>", length(max = "MCU_BUTTONS_MAX"))
+    )]
+    pub buttons: Vec<McuFunction, MCU_BUTTONS_MAX>,
+    #[serde(skip)]
+    rings: [Ring; MCU_STRIPS as usize],
+    #[serde(skip, default = "Mcu::default_lcd")]
+    lcd: [u8; MCU_LCD_LEN],
+    // bytes of the SysEx message received so far, if any
+    #[serde(skip)]
+    sysex: Option<usize>,
+    #[serde(skip)]
+    lcd_offset: usize,
+}
+
+impl Preset {
+    pub fn apply(&self, inputs: &mut [InputType]) {
+        match self {
+            Preset::Mcu(p) => p.apply(inputs),
+        }
+    }
+
+    /*
+     * Updates the feedback state from MIDI data received from the host.
+     */
+    pub fn receive(&mut self, data: &OutputData) {
+        match self {
+            Preset::Mcu(p) => p.receive(data),
+        }
+    }
+
+    /*
+     * Feeds raw bytes received from the host, for feedback not covered by
+     * `receive` like SysEx.
+     */
+    pub fn input(&mut self, byte: u8) {
+        match self {
+            Preset::Mcu(p) => p.input(byte),
+        }
+    }
+}
+
+impl Default for Mcu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mcu {
+    pub fn new() -> Self {
+        Self {
+            buttons: Vec::new(),
+            rings: [Ring::default(); MCU_STRIPS as usize],
+            lcd: Self::default_lcd(),
+            sysex: None,
+            lcd_offset: 0,
+        }
+    }
+
+    fn default_lcd() -> [u8; MCU_LCD_LEN] {
+        [b' '; MCU_LCD_LEN]
+    }
+
+    pub fn apply(&self, inputs: &mut [InputType]) {
+        let (mut encoders, mut pots, mut buttons) = (0usize, 0usize, 0usize);
+        let strips = MCU_STRIPS as usize;
+        for input in inputs.iter_mut() {
+            match input {
+                InputType::Encoder(i) => {
+                    if encoders < strips {
+                        let strip = encoders as u8;
+                        i.attach_handler(EncoderHandler::VPot(VPot { strip }));
+                    }
+                    encoders += 1;
+                }
+                InputType::Potentiometer(i) => {
+                    if pots <= strips {
+                        let strip = pots as u8;
+                        i.attach_handler(PotentiometerHandler::Fader(Fader { strip }));
+                    }
+                    pots += 1;
+                }
+                InputType::Button(i) => {
+                    let button = match self.buttons.get(buttons) {
+                        Some(function) => Some(McuButton {
+                            function: *function,
+                            strip: 0,
+                        }),
+                        None => Some(buttons - self.buttons.len())
+                            .filter(|strip| *strip < strips)
+                            .map(|strip| McuButton {
+                                function: McuFunction::Select,
+                                strip: strip as u8,
+                            }),
+                    };
+                    if let Some(button) = button {
+                        i.attach_handler(ButtonHandler::Mcu(button));
+                    }
+                    buttons += 1;
+                }
+                InputType::Generator(_) => (),
+            }
+        }
+    }
+
+    pub fn ring(&self, strip: usize) -> Option<&Ring> {
+        self.rings.get(strip)
+    }
+
+    /*
+     * Line 0 or 1 of the LCD, 7 characters per strip.
+     */
+    pub fn lcd_line(&self, line: usize) -> Option<&[u8]> {
+        let start = line * MCU_LCD_LINE_LEN;
+        self.lcd.get(start..start + MCU_LCD_LINE_LEN)
+    }
+
+    pub fn receive(&mut self, data: &OutputData) {
+        let OutputData::MidiMsgCc(m) = data else {
+            return;
+        };
+        if m.channel != MCU_CHANNEL || m.control < MCU_RING_CONTROL {
+            return;
+        }
+        let Some(ring) = self.rings.get_mut((m.control - MCU_RING_CONTROL) as usize) else {
+            return;
+        };
+        ring.center = m.value & MCU_RING_CENTER != 0;
+        ring.position = m.value & MCU_RING_POSITION_MASK;
+        ring.mode = match m.value >> MCU_RING_MODE_SHIFT & MCU_RING_MODE_MASK {
+            0 => RingMode::Dot,
+            1 => RingMode::BoostCut,
+            2 => RingMode::Wrap,
+            _ => RingMode::Spread,
+        };
+    }
+
+    /*
+     * Parses LCD SysEx messages: F0 00 00 66 14 12 <offset> <chars> F7.
+     */
+    pub fn input(&mut self, byte: u8) {
+        if byte >= MIDI_STATUS_REALTIME {
+            return;
+        }
+        if byte & MIDI_STATUS_BIT != 0 {
+            // any status byte including the SysEx end ends the message
+            self.sysex = (byte == MIDI_SYSEX_START).then_some(0);
+            return;
+        }
+        let Some(pos) = self.sysex else {
+            return;
+        };
+        self.sysex = Some(pos + 1);
+
+        let valid = match pos {
+            0..=2 => byte == MCU_SYSEX_HEADER[pos],
+            3 => byte == MCU_MODEL || byte == MCU_MODEL_EXTENDER,
+            4 => byte == MCU_LCD_COMMAND,
+            5 => {
+                self.lcd_offset = byte as usize;
+                true
+            }
+            _ => {
+                if let Some(c) = self.lcd.get_mut(self.lcd_offset + pos - 6) {
+                    *c = byte;
+                }
+                true
+            }
+        };
+        if !valid {
+            self.sysex = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::MidiMsgCc;
+
+    fn ring(control: u8, value: u8) -> OutputData {
+        OutputData::MidiMsgCc(MidiMsgCc {
+            channel: 0,
+            control,
+            value,
+        })
+    }
+
+    #[test]
+    fn rings() {
+        let mut mcu = Mcu::new();
+        mcu.receive(&ring(0x32, 0x46));
+        mcu.receive(&ring(0x37, 0x2b));
+        mcu.receive(&ring(0x38, 0x7f));
+
+        assert_eq!(
+            mcu.ring(2),
+            Some(&Ring {
+                mode: RingMode::Dot,
+                position: 6,
+                center: true,
+            })
+        );
+        assert_eq!(
+            mcu.ring(7),
+            Some(&Ring {
+                mode: RingMode::Wrap,
+                position: 11,
+                center: false,
+            })
+        );
+        assert_eq!(mcu.ring(0), Some(&Ring::default()));
+    }
+
+    #[test]
+    fn lcd() {
+        let mut mcu = Mcu::new();
+        let msg = [
+            0xf0, 0x00, 0x00, 0x66, 0x14, 0x12, 0x38, b'K', b'i', b'c', b'k', 0xf7,
+        ];
+        for byte in msg {
+            mcu.input(byte);
+        }
+        // another device's SysEx is ignored
+        for byte in [0xf0, 0x00, 0x00, 0x67, 0x14, 0x12, 0x00, b'X', 0xf7] {
+            mcu.input(byte);
+        }
+
+        assert_eq!(mcu.lcd_line(0), Some(&[b' '; 56][..]));
+        assert_eq!(&mcu.lcd_line(1).unwrap()[..6], b"Kick  ");
+        assert_eq!(mcu.lcd_line(2), None);
+    }
+
+    #[test]
+    fn lcd_realtime_and_overflow() {
+        let mut mcu = Mcu::new();
+        let msg = [
+            0xf0, 0x00, 0x00, 0x66, 0x14, 0x12, 0x6e, b'a', 0xf8, b'b', b'c', 0xf7,
+        ];
+        for byte in msg {
+            mcu.input(byte);
+        }
+
+        assert_eq!(&mcu.lcd_line(1).unwrap()[54..], b"ab");
+    }
+}
@@ -0,0 +1,420 @@
+use crate::clock::{CLOCK_CLOCKS_PER_BEAT, CLOCK_PPQN, GATE_MAX, MICROS_PER_MINUTE};
+#[cfg(feature = "schema")]
+use crate::config::{MIDI_CHANNEL_MAX, MIDI_DATA_MAX};
+use crate::output::{MidiMsgCc, MidiMsgNote, OutputData};
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+const SEQUENCER_TRACKS_MAX: usize = 4;
+const SEQUENCER_STEPS_MAX: usize = 16;
+const SEQUENCER_PENDING_MAX: usize = 32;
+const SEQUENCER_DIVISION_DEFAULT: u8 = 6;
+const SEQUENCER_SWING_STRAIGHT: u8 = 50;
+const SEQUENCER_SWING_MAX: u8 = 75;
+
+/*
+ * Input index used in events of output data generated by the sequencer.
+ */
+pub const SEQUENCER_INPUT: usize = usize::MAX - 1;
+
+/*
+ * A step plays its note for `gate` percent of the step length, a gate of
+ * 0 is a rest. The CC value is sent on the control of the track.
+ */
+#[derive(Debug, Default, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Step {
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
+    pub note: u8,
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
+    pub velocity: u8,
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(range(max = "GATE_MAX")))]
+    pub gate: u8,
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
+    pub cc: Option<u8>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Track {
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_CHANNEL_MAX")))]
+    pub channel: u8,
+    // the control per-step CC values are sent on
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
+    pub control: Option<u8>,
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "std::vec::Vec<Step>", length(max = "SEQUENCER_STEPS_MAX"))
+    )]
+    pub steps: Vec<Step, SEQUENCER_STEPS_MAX>,
+    // pattern length in steps, all steps if unset
+    #[serde(default)]
+    pub length: Option<u8>,
+}
+
+/*
+ * Plays tracks of steps following the clock the device runs on. A step
+ * lasts `division` clocks at 24 ppqn. Swing delays every second step, 50
+ * is straight and 75 delays it by half a step.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Sequencer {
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "std::vec::Vec<Track>", length(max = "SEQUENCER_TRACKS_MAX"))
+    )]
+    pub tracks: Vec<Track, SEQUENCER_TRACKS_MAX>,
+    #[serde(default = "Sequencer::default_division")]
+    #[cfg_attr(feature = "schema", schemars(range(min = 1)))]
+    pub division: u8,
+    #[serde(default = "Sequencer::default_swing")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(range(min = "SEQUENCER_SWING_STRAIGHT", max = "SEQUENCER_SWING_MAX"))
+    )]
+    pub swing: u8,
+    #[serde(skip)]
+    running: bool,
+    // clocks since the song start
+    #[serde(skip)]
+    clocks: u32,
+    // output data waiting for its time in microseconds
+    #[serde(skip)]
+    pending: Vec<(u64, OutputData), SEQUENCER_PENDING_MAX>,
+    // output data dropped because too much was pending
+    #[serde(skip)]
+    dropped: u32,
+}
+
+impl Track {
+    pub fn new(channel: u8) -> Self {
+        Self {
+            channel,
+            control: None,
+            steps: Vec::new(),
+            length: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let steps = self.steps.len();
+        self.length.map_or(steps, |l| (l as usize).min(steps))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn note(&self, step: &Step, on: bool) -> OutputData {
+        OutputData::MidiMsgNote(MidiMsgNote {
+            channel: self.channel,
+            key: step.note,
+            on,
+            velocity: if on { step.velocity } else { 0 },
+        })
+    }
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Self {
+            tracks: Vec::new(),
+            division: Self::default_division(),
+            swing: Self::default_swing(),
+            running: false,
+            clocks: 0,
+            pending: Vec::new(),
+            dropped: 0,
+        }
+    }
+
+    fn default_division() -> u8 {
+        SEQUENCER_DIVISION_DEFAULT
+    }
+
+    fn default_swing() -> u8 {
+        SEQUENCER_SWING_STRAIGHT
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /*
+     * Index of the step played last, counted from the song start.
+     */
+    pub fn position(&self) -> u32 {
+        self.clocks.saturating_sub(1) / self.division.max(1) as u32
+    }
+
+    /*
+     * Number of notes and CCs dropped because more were pending than the
+     * sequencer can hold.
+     */
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /*
+     * Follows clock and transport messages of the clock the device runs
+     * on, received at the given time in microseconds.
+     */
+    pub fn receive(&mut self, data: &OutputData, now: u64, bpm: u16) {
+        match *data {
+            OutputData::Start => {
+                self.clocks = 0;
+                self.running = true;
+            }
+            OutputData::Continue => self.running = true,
+            OutputData::Stop => self.stop(now),
+            OutputData::SongPosition(position) if !self.running => {
+                self.clocks = position as u32 * CLOCK_CLOCKS_PER_BEAT;
+            }
+            OutputData::Clock if self.running => self.clock(now, bpm),
+            _ => (),
+        }
+    }
+
+    /*
+     * Hands out the next output data due at the given time.
+     */
+    pub fn poll(&mut self, now: u64) -> Option<OutputData> {
+        let (idx, _) = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, (due, _))| *due <= now)
+            .min_by_key(|(_, (due, _))| *due)?;
+        Some(self.pending.remove(idx).1)
+    }
+
+    fn clock(&mut self, now: u64, bpm: u16) {
+        let division = self.division.max(1) as u32;
+        let clocks = self.clocks;
+        self.clocks = self.clocks.saturating_add(1);
+        if clocks % division != 0 {
+            return;
+        }
+
+        let step_us = division as u64 * MICROS_PER_MINUTE / (bpm.max(1) as u64 * CLOCK_PPQN);
+        let index = clocks / division;
+        let swing = self
+            .swing
+            .clamp(SEQUENCER_SWING_STRAIGHT, SEQUENCER_SWING_MAX);
+        let delay = if index % 2 == 1 {
+            (swing - SEQUENCER_SWING_STRAIGHT) as u64 * step_us / SEQUENCER_SWING_STRAIGHT as u64
+        } else {
+            0
+        };
+        let start = now + delay;
+
+        for track in self.tracks.iter() {
+            if track.is_empty() {
+                continue;
+            }
+            let step = &track.steps[index as usize % track.len()];
+
+            if let (Some(control), Some(value)) = (track.control, step.cc) {
+                let cc = OutputData::MidiMsgCc(MidiMsgCc {
+                    channel: track.channel,
+                    control,
+                    value,
+                });
+                if self.pending.push((start, cc)).is_err() {
+                    self.dropped = self.dropped.saturating_add(1);
+                }
+            }
+
+            if step.gate == 0 {
+                continue;
+            }
+            // a note on needs room for its note off
+            if self.pending.capacity() - self.pending.len() < 2 {
+                self.dropped = self.dropped.saturating_add(2);
+                continue;
+            }
+            let gate = step.gate.min(GATE_MAX) as u64 * step_us / GATE_MAX as u64;
+            // end early so a following note on the same key isn't cut
+            self.pending.push((start, track.note(step, true))).ok();
+            self.pending
+                .push((start + gate.max(1) - 1, track.note(step, false)))
+                .ok();
+        }
+    }
+
+    /*
+     * Ends all playing notes right away and drops everything else pending.
+     */
+    fn stop(&mut self, now: u64) {
+        self.running = false;
+        self.pending.retain(|(_, data)| match data {
+            OutputData::MidiMsgNote(m) => !m.on,
+            _ => false,
+        });
+        for (due, _) in self.pending.iter_mut() {
+            *due = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 125 BPM has a clock every 20ms and a 16th of 120ms
+    const BPM: u16 = 125;
+    const CLOCK_US: u64 = 20_000;
+
+    fn step(note: u8, gate: u8) -> Step {
+        Step {
+            note,
+            velocity: 100,
+            gate,
+            cc: None,
+        }
+    }
+
+    fn sequencer(steps: &[Step]) -> Sequencer {
+        let mut track = Track::new(0);
+        track.steps.extend_from_slice(steps).unwrap();
+        let mut s = Sequencer::new();
+        s.tracks.push(track).unwrap();
+        s
+    }
+
+    fn play(s: &mut Sequencer, clocks: u64) -> std::vec::Vec<(u64, u8, bool)> {
+        let mut played = std::vec::Vec::new();
+        s.receive(&OutputData::Start, 0, BPM);
+        for now in 0..clocks * CLOCK_US {
+            if now % CLOCK_US == 0 {
+                s.receive(&OutputData::Clock, now, BPM);
+            }
+            while let Some(data) = s.poll(now) {
+                if let OutputData::MidiMsgNote(m) = data {
+                    played.push((now, m.key, m.on));
+                }
+            }
+        }
+        played
+    }
+
+    #[test]
+    fn steps_and_gates() {
+        let mut s = sequencer(&[step(60, 50), step(61, 0), step(62, 100)]);
+
+        assert_eq!(
+            play(&mut s, 24),
+            [
+                (0, 60, true),
+                (59_999, 60, false),
+                (240_000, 62, true),
+                (359_999, 62, false),
+                (360_000, 60, true),
+                (419_999, 60, false),
+            ]
+        );
+        assert_eq!(s.position(), 3);
+    }
+
+    #[test]
+    fn pattern_length() {
+        let mut s = sequencer(&[step(60, 10), step(61, 10), step(62, 10)]);
+        s.tracks[0].length = Some(2);
+
+        let notes: std::vec::Vec<u8> = play(&mut s, 24)
+            .into_iter()
+            .filter(|(_, _, on)| *on)
+            .map(|(_, key, _)| key)
+            .collect();
+        assert_eq!(notes, [60, 61, 60, 61]);
+    }
+
+    #[test]
+    fn swing() {
+        let mut s = sequencer(&[step(60, 10)]);
+        s.swing = 75;
+
+        let starts: std::vec::Vec<u64> = play(&mut s, 24)
+            .into_iter()
+            .filter(|(_, _, on)| *on)
+            .map(|(t, _, _)| t)
+            .collect();
+        assert_eq!(starts, [0, 180_000, 240_000, 420_000]);
+    }
+
+    #[test]
+    fn stop_ends_notes() {
+        let mut s = sequencer(&[step(60, 100)]);
+
+        play(&mut s, 1);
+        s.receive(&OutputData::Stop, 30_000, BPM);
+        assert_eq!(
+            s.poll(30_000),
+            Some(OutputData::MidiMsgNote(MidiMsgNote {
+                channel: 0,
+                key: 60,
+                on: false,
+                velocity: 0,
+            }))
+        );
+        assert_eq!(s.poll(u64::MAX), None);
+        s.receive(&OutputData::Clock, 40_000, BPM);
+        assert_eq!(s.poll(u64::MAX), None);
+    }
+
+    #[test]
+    fn step_cc() {
+        let mut s = sequencer(&[Step {
+            cc: Some(23),
+            ..step(60, 0)
+        }]);
+        s.tracks[0].control = Some(74);
+        s.receive(&OutputData::Start, 0, BPM);
+        s.receive(&OutputData::Clock, 0, BPM);
+
+        assert_eq!(
+            s.poll(0),
+            Some(OutputData::MidiMsgCc(MidiMsgCc {
+                channel: 0,
+                control: 74,
+                value: 23,
+            }))
+        );
+        assert_eq!(s.poll(u64::MAX), None);
+    }
+
+    #[test]
+    fn count_dropped() {
+        let mut s = sequencer(&[Step {
+            cc: Some(23),
+            ..step(60, 50)
+        }]);
+        s.tracks[0].control = Some(74);
+        s.division = 1;
+        s.receive(&OutputData::Start, 0, BPM);
+
+        // nothing is polled, every step leaves a CC and two notes pending
+        for _ in 0..10 {
+            s.receive(&OutputData::Clock, 0, BPM);
+        }
+        assert_eq!(s.dropped(), 0);
+        s.receive(&OutputData::Clock, 0, BPM);
+        assert_eq!(s.dropped(), 2);
+        s.receive(&OutputData::Clock, 0, BPM);
+        assert_eq!(s.dropped(), 4);
+        s.receive(&OutputData::Clock, 0, BPM);
+        assert_eq!(s.dropped(), 7);
+    }
+}
@@ -0,0 +1,356 @@
+mod ram;
+pub use self::ram::RamFlash;
+
+#[cfg(target_os = "linux")]
+mod file;
+#[cfg(target_os = "linux")]
+pub use self::file::FileFlash;
+
+#[cfg(target_os = "none")]
+mod stm32;
+#[cfg(target_os = "none")]
+pub use self::stm32::Stm32Flash;
+
+const RECORD_MAGIC: [u8; 4] = *b"RCST";
+const RECORD_HEADER_LEN: u32 = 16;
+// records start on a multiple of the largest flash write size
+const RECORD_ALIGN: u32 = 4;
+const ERASED: u8 = 0xff;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum StorageError {
+    // the flash failed, e.g. because power was lost while writing
+    Flash,
+    OutOfBounds,
+    // written to without erasing first
+    NotErased,
+    // the data doesn't fit into a bank or the given buffer
+    TooLarge,
+}
+
+/*
+ * Region of NOR flash reserved for the storage, offsets are relative to
+ * its start. Bits can only be cleared by writes and set again by erasing
+ * whole pages.
+ */
+pub trait Flash {
+    fn capacity(&self) -> u32;
+    fn erase_size(&self) -> u32;
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), StorageError>;
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), StorageError>;
+    /*
+     * Erases the pages from `from` up to `to`, both aligned to the erase
+     * size.
+     */
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), StorageError>;
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Record {
+    bank: u32,
+    offset: u32,
+    seq: u32,
+    len: u32,
+    crc: u32,
+}
+
+/*
+ * Keeps the latest version of a blob, e.g. the binary device config, in
+ * flash. The region is split into two banks A and B, saves are appended
+ * to the active bank as records with a sequence number and a CRC. Only
+ * once the active bank is full the other one is erased and takes over,
+ * so pages are erased once per bank worth of saves and the previous
+ * record is still intact if power fails during a save.
+ */
+pub struct Storage<F: Flash> {
+    flash: F,
+    bank_size: u32,
+    latest: Option<Record>,
+    // next free offset in each bank, the bank size if it can't be written
+    ends: [u32; 2],
+}
+
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+// the CRC covers sequence number, length and data
+fn header_crc(seq: u32, len: u32) -> u32 {
+    crc32(crc32(0, &seq.to_le_bytes()), &len.to_le_bytes())
+}
+
+const fn aligned(len: u32) -> u32 {
+    len.div_ceil(RECORD_ALIGN) * RECORD_ALIGN
+}
+
+impl<F: Flash> Storage<F> {
+    /*
+     * Scans the flash for the latest record.
+     */
+    pub fn new(flash: F) -> Result<Self, StorageError> {
+        let erase_size = flash.erase_size();
+        let bank_size = flash.capacity() / 2 / erase_size * erase_size;
+        if bank_size < RECORD_HEADER_LEN {
+            return Err(StorageError::OutOfBounds);
+        }
+
+        let mut storage = Self {
+            flash,
+            bank_size,
+            latest: None,
+            ends: [0; 2],
+        };
+        for bank in 0..2 {
+            let (latest, end) = storage.scan(bank)?;
+            storage.ends[bank as usize] = end;
+            if latest.map(|r| r.seq) > storage.latest.map(|r| r.seq) {
+                storage.latest = latest;
+            }
+        }
+        Ok(storage)
+    }
+
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    /*
+     * Reads the latest record into the buffer, None if nothing was saved
+     * yet.
+     */
+    pub fn load<'a>(&mut self, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>, StorageError> {
+        let Some(record) = self.latest else {
+            return Ok(None);
+        };
+        let data = buf
+            .get_mut(..record.len as usize)
+            .ok_or(StorageError::TooLarge)?;
+        let start = record.bank * self.bank_size + record.offset + RECORD_HEADER_LEN;
+        self.flash.read(start, data)?;
+        Ok(Some(data))
+    }
+
+    /*
+     * Appends the data as new record, unless it equals the latest one.
+     */
+    pub fn save(&mut self, data: &[u8]) -> Result<(), StorageError> {
+        let len = data.len() as u32;
+        let size = RECORD_HEADER_LEN + aligned(len);
+        if data.len() > self.bank_size as usize || size > self.bank_size {
+            return Err(StorageError::TooLarge);
+        }
+
+        let seq = self.latest.map_or(0, |r| r.seq.wrapping_add(1));
+        let crc = crc32(header_crc(seq, len), data);
+        if let Some(latest) = self.latest {
+            if latest.len == len && crc32(header_crc(latest.seq, len), data) == latest.crc {
+                return Ok(());
+            }
+        }
+
+        let mut bank = self.latest.map_or(0, |r| r.bank);
+        if self.ends[bank as usize] + size > self.bank_size {
+            bank = 1 - bank;
+            self.ends[bank as usize] = self.bank_size;
+            let from = bank * self.bank_size;
+            self.flash.erase(from, from + self.bank_size)?;
+            self.ends[bank as usize] = 0;
+        }
+
+        let offset = self.ends[bank as usize];
+        let record = Record {
+            bank,
+            offset,
+            seq,
+            len,
+            crc,
+        };
+
+        // nothing after a failed write can be trusted
+        self.ends[bank as usize] = self.bank_size;
+        self.write(&record, data)?;
+        self.ends[bank as usize] = offset + size;
+        self.latest = Some(record);
+        Ok(())
+    }
+
+    /*
+     * Writes the header before the data, a record torn by a power loss
+     * then fails its CRC check.
+     */
+    fn write(&mut self, record: &Record, data: &[u8]) -> Result<(), StorageError> {
+        let start = record.bank * self.bank_size + record.offset;
+        let mut header = [0; RECORD_HEADER_LEN as usize];
+        header[..4].copy_from_slice(&RECORD_MAGIC);
+        header[4..8].copy_from_slice(&record.seq.to_le_bytes());
+        header[8..12].copy_from_slice(&record.len.to_le_bytes());
+        header[12..].copy_from_slice(&record.crc.to_le_bytes());
+        self.flash.write(start, &header)?;
+
+        let start = start + RECORD_HEADER_LEN;
+        let (body, tail) =
+            data.split_at(data.len() / RECORD_ALIGN as usize * RECORD_ALIGN as usize);
+        if !body.is_empty() {
+            self.flash.write(start, body)?;
+        }
+        if !tail.is_empty() {
+            let mut padded = [ERASED; RECORD_ALIGN as usize];
+            padded[..tail.len()].copy_from_slice(tail);
+            self.flash.write(start + body.len() as u32, &padded)?;
+        }
+        Ok(())
+    }
+
+    /*
+     * Walks the records of a bank, returns the latest valid one and the
+     * offset the next one goes to.
+     */
+    fn scan(&mut self, bank: u32) -> Result<(Option<Record>, u32), StorageError> {
+        let base = bank * self.bank_size;
+        let mut latest: Option<Record> = None;
+        let mut offset = 0;
+
+        while offset + RECORD_HEADER_LEN <= self.bank_size {
+            let mut header = [0; RECORD_HEADER_LEN as usize];
+            self.flash.read(base + offset, &mut header)?;
+            if header.iter().all(|b| *b == ERASED) {
+                return Ok((latest, offset));
+            }
+
+            let word = |i: usize| {
+                u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]])
+            };
+            let record = Record {
+                bank,
+                offset,
+                seq: word(4),
+                len: word(8),
+                crc: word(12),
+            };
+            let size = RECORD_HEADER_LEN.saturating_add(aligned(record.len.min(self.bank_size)));
+            if header[..4] != RECORD_MAGIC
+                || record.len > self.bank_size
+                || offset + size > self.bank_size
+            {
+                break;
+            }
+            if self.crc(&record)? != record.crc {
+                break;
+            }
+
+            if latest.map(|l| l.seq) < Some(record.seq) {
+                latest = Some(record);
+            }
+            offset += size;
+        }
+        Ok((latest, self.bank_size))
+    }
+
+    fn crc(&mut self, record: &Record) -> Result<u32, StorageError> {
+        let mut crc = header_crc(record.seq, record.len);
+        let mut start = record.bank * self.bank_size + record.offset + RECORD_HEADER_LEN;
+        let end = start + record.len;
+        let mut chunk = [0; 32];
+        while start < end {
+            let n = (end - start).min(chunk.len() as u32);
+            self.flash.read(start, &mut chunk[..n as usize])?;
+            crc = crc32(crc, &chunk[..n as usize]);
+            start += n;
+        }
+        Ok(crc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 512;
+    const PAGE: u32 = 128;
+
+    fn load(storage: &mut Storage<RamFlash<SIZE>>) -> Option<std::vec::Vec<u8>> {
+        let mut buf = [0; 64];
+        storage.load(&mut buf).unwrap().map(|d| d.to_vec())
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(0, b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn save_and_reload() {
+        let mut storage = Storage::new(RamFlash::<SIZE>::new(PAGE)).unwrap();
+        assert_eq!(load(&mut storage), None);
+
+        storage.save(b"first").unwrap();
+        storage.save(b"second").unwrap();
+        assert_eq!(load(&mut storage).unwrap(), b"second");
+
+        // unchanged data isn't written again
+        let used = |s: &Storage<_>| s.ends[0];
+        let end = used(&storage);
+        storage.save(b"second").unwrap();
+        assert_eq!(used(&storage), end);
+
+        let mut storage = Storage::new(storage.into_inner()).unwrap();
+        assert_eq!(load(&mut storage).unwrap(), b"second");
+
+        let mut buf = [0; 4];
+        assert_eq!(storage.load(&mut buf), Err(StorageError::TooLarge));
+        assert_eq!(storage.save(&[0; 256]), Err(StorageError::TooLarge));
+    }
+
+    #[test]
+    fn banks_take_turns() {
+        let mut storage = Storage::new(RamFlash::<SIZE>::new(PAGE)).unwrap();
+        // 8 records of 32 bytes fit into a bank of 256 bytes
+        for i in 0..100u8 {
+            storage.save(&[i; 13]).unwrap();
+        }
+        assert_eq!(load(&mut storage).unwrap(), [99; 13]);
+
+        let flash = storage.into_inner();
+        // bank switches after 8, 16, ... 96 saves, two pages each
+        assert_eq!(flash.erases(), 12 * 2);
+        let mut storage = Storage::new(flash).unwrap();
+        assert_eq!(load(&mut storage).unwrap(), [99; 13]);
+    }
+
+    #[test]
+    fn power_loss_keeps_previous_record() {
+        // fail at every byte up to the last of the data of the save that
+        // switches banks and of the one appending to the bank
+        for saves in [8, 9] {
+            for budget in 0..16 + 13 {
+                let mut storage = Storage::new(RamFlash::<SIZE>::new(PAGE)).unwrap();
+                for i in 0..saves {
+                    storage.save(&[i; 13]).unwrap();
+                }
+
+                let mut flash = storage.into_inner();
+                flash.fail_after(budget);
+                let mut storage = Storage::new(flash).unwrap();
+                assert_eq!(storage.save(&[0xaa; 13]), Err(StorageError::Flash));
+
+                let mut flash = storage.into_inner();
+                flash.restore_power();
+                let mut storage = Storage::new(flash).unwrap();
+                assert_eq!(load(&mut storage).unwrap(), [saves - 1; 13]);
+
+                // the next save succeeds and wins
+                storage.save(&[0xbb; 13]).unwrap();
+                let mut storage = Storage::new(storage.into_inner()).unwrap();
+                assert_eq!(load(&mut storage).unwrap(), [0xbb; 13]);
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::storage::{Flash, StorageError, ERASED};
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/*
+ * Flash emulated by a file, to keep the storage of a simulated device
+ * across runs.
+ */
+pub struct FileFlash {
+    file: File,
+    capacity: u32,
+    erase_size: u32,
+}
+
+impl FileFlash {
+    /*
+     * Opens or creates the file, extending it with erased bytes up to the
+     * capacity.
+     */
+    pub fn open(path: impl AsRef<Path>, capacity: u32, erase_size: u32) -> std::io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let len = file.metadata()?.len();
+        if len < capacity as u64 {
+            file.seek(SeekFrom::End(0))?;
+            file.write_all(&vec![ERASED; (capacity as u64 - len) as usize])?;
+        }
+        Ok(Self {
+            file,
+            capacity,
+            erase_size,
+        })
+    }
+
+    fn check(&self, offset: u32, len: usize) -> Result<(), StorageError> {
+        match (offset as usize).checked_add(len) {
+            Some(end) if end <= self.capacity as usize => Ok(()),
+            _ => Err(StorageError::OutOfBounds),
+        }
+    }
+
+    fn read_at(&mut self, offset: u32, buf: &mut [u8]) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.read_exact(buf)
+    }
+
+    fn write_at(&mut self, offset: u32, data: &[u8]) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.write_all(data)
+    }
+}
+
+impl Flash for FileFlash {
+    fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    fn erase_size(&self) -> u32 {
+        self.erase_size
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), StorageError> {
+        self.check(offset, buf.len())?;
+        self.read_at(offset, buf).map_err(|_| StorageError::Flash)
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), StorageError> {
+        self.check(offset, data.len())?;
+        let mut current = vec![0; data.len()];
+        self.read_at(offset, &mut current)
+            .map_err(|_| StorageError::Flash)?;
+        if current.iter().any(|b| *b != ERASED) {
+            return Err(StorageError::NotErased);
+        }
+        self.write_at(offset, data).map_err(|_| StorageError::Flash)
+    }
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), StorageError> {
+        if from % self.erase_size != 0 || to % self.erase_size != 0 || from > to {
+            return Err(StorageError::OutOfBounds);
+        }
+        self.check(from, (to - from) as usize)?;
+        self.write_at(from, &vec![ERASED; (to - from) as usize])
+            .map_err(|_| StorageError::Flash)
+    }
+}
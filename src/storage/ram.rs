@@ -0,0 +1,116 @@
+use crate::storage::{Flash, StorageError, ERASED};
+
+/*
+ * Flash emulated in memory, enforcing that only erased bytes are written.
+ * Power loss is simulated by letting writes fail after a number of bytes.
+ */
+pub struct RamFlash<const N: usize> {
+    data: [u8; N],
+    erase_size: u32,
+    // bytes left to write before failing
+    budget: Option<usize>,
+    erases: usize,
+}
+
+impl<const N: usize> RamFlash<N> {
+    pub fn new(erase_size: u32) -> Self {
+        Self {
+            data: [ERASED; N],
+            erase_size,
+            budget: None,
+            erases: 0,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /*
+     * Cuts the power after the given number of bytes written.
+     */
+    pub fn fail_after(&mut self, bytes: usize) {
+        self.budget = Some(bytes);
+    }
+
+    pub fn restore_power(&mut self) {
+        self.budget = None;
+    }
+
+    /*
+     * Number of pages erased so far.
+     */
+    pub fn erases(&self) -> usize {
+        self.erases
+    }
+
+    fn range(&self, offset: u32, len: usize) -> Result<core::ops::Range<usize>, StorageError> {
+        let start = offset as usize;
+        match start.checked_add(len) {
+            Some(end) if end <= N => Ok(start..end),
+            _ => Err(StorageError::OutOfBounds),
+        }
+    }
+}
+
+impl<const N: usize> Flash for RamFlash<N> {
+    fn capacity(&self) -> u32 {
+        N as u32
+    }
+
+    fn erase_size(&self) -> u32 {
+        self.erase_size
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), StorageError> {
+        let range = self.range(offset, buf.len())?;
+        buf.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), StorageError> {
+        let range = self.range(offset, data.len())?;
+        if self.data[range.clone()].iter().any(|b| *b != ERASED) {
+            return Err(StorageError::NotErased);
+        }
+        for (dst, src) in self.data[range].iter_mut().zip(data) {
+            match &mut self.budget {
+                Some(0) => return Err(StorageError::Flash),
+                Some(n) => *n -= 1,
+                None => (),
+            }
+            *dst = *src;
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), StorageError> {
+        if from % self.erase_size != 0 || to % self.erase_size != 0 || from > to {
+            return Err(StorageError::OutOfBounds);
+        }
+        let range = self.range(from, (to - from) as usize)?;
+        self.data[range].fill(ERASED);
+        self.erases += ((to - from) / self.erase_size) as usize;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nor_semantics() {
+        let mut flash = RamFlash::<64>::new(32);
+        flash.write(4, &[1, 2]).unwrap();
+        assert_eq!(flash.write(5, &[3]), Err(StorageError::NotErased));
+        assert_eq!(flash.write(63, &[3, 4]), Err(StorageError::OutOfBounds));
+        assert_eq!(flash.erase(0, 16), Err(StorageError::OutOfBounds));
+
+        flash.erase(0, 32).unwrap();
+        flash.fail_after(1);
+        assert_eq!(flash.write(4, &[1, 2]), Err(StorageError::Flash));
+        assert_eq!(&flash.data()[4..6], [1, ERASED]);
+        assert_eq!(flash.erases(), 1);
+    }
+}
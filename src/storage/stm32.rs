@@ -0,0 +1,60 @@
+use embassy_stm32::flash::{Blocking, Flash as HalFlash};
+
+use crate::storage::{Flash, StorageError};
+
+// last 4 KiB of the 64 KiB flash of the STM32F103C8, made of 1 KiB pages,
+// memory.x ends the flash of the firmware below it
+const STORAGE_OFFSET: u32 = 60 * 1024;
+const STORAGE_SIZE: u32 = 4 * 1024;
+const STORAGE_PAGE_SIZE: u32 = 1024;
+
+/*
+ * Flash region reserved for the storage at the end of the on-chip flash,
+ * the linker keeps the firmware below it.
+ */
+pub struct Stm32Flash {
+    flash: HalFlash<'static, Blocking>,
+}
+
+impl Stm32Flash {
+    pub fn new(flash: HalFlash<'static, Blocking>) -> Self {
+        Self { flash }
+    }
+}
+
+impl Flash for Stm32Flash {
+    fn capacity(&self) -> u32 {
+        STORAGE_SIZE
+    }
+
+    fn erase_size(&self) -> u32 {
+        STORAGE_PAGE_SIZE
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), StorageError> {
+        if offset as usize + buf.len() > STORAGE_SIZE as usize {
+            return Err(StorageError::OutOfBounds);
+        }
+        self.flash
+            .blocking_read(STORAGE_OFFSET + offset, buf)
+            .map_err(|_| StorageError::Flash)
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), StorageError> {
+        if offset as usize + data.len() > STORAGE_SIZE as usize {
+            return Err(StorageError::OutOfBounds);
+        }
+        self.flash
+            .blocking_write(STORAGE_OFFSET + offset, data)
+            .map_err(|_| StorageError::Flash)
+    }
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), StorageError> {
+        if from > to || to > STORAGE_SIZE {
+            return Err(StorageError::OutOfBounds);
+        }
+        self.flash
+            .blocking_erase(STORAGE_OFFSET + from, STORAGE_OFFSET + to)
+            .map_err(|_| StorageError::Flash)
+    }
+}
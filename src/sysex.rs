@@ -0,0 +1,516 @@
+mod server;
+pub use self::server::{ConfigServer, SYSEX_CONFIG_MAX};
+
+#[cfg(target_os = "linux")]
+mod client;
+#[cfg(target_os = "linux")]
+pub use self::client::{Client, ClientError, Transport, Version};
+#[cfg(target_os = "linux")]
+mod rawmidi;
+#[cfg(target_os = "linux")]
+pub use self::rawmidi::RawMidi;
+
+use heapless::{String, Vec};
+
+/*
+ * Configuration over SysEx. Messages are framed as
+ * `F0 7D <command> <payload> F7`, numbers are sent as 7 or 14 bit values,
+ * least significant 7 bits first, and binary data packed 7 bytes into 8.
+ * The config is transferred in chunks of SYSEX_CHUNK_MAX bytes.
+ */
+pub const SYSEX_MANUFACTURER: u8 = 0x7d;
+pub const SYSEX_PROTOCOL_VERSION: u8 = 1;
+pub const SYSEX_CHUNK_MAX: usize = 64;
+pub const SYSEX_MESSAGE_MAX: usize = 96;
+
+const SYSEX_START: u8 = 0xf0;
+const SYSEX_END: u8 = 0xf7;
+const MIDI_REALTIME: u8 = 0xf8;
+const RESPONSE_BIT: u8 = 0x40;
+const RESPONSE_ACK: u8 = 0x7e;
+const RESPONSE_NAK: u8 = 0x7f;
+const FIRMWARE_VERSION_MAX: usize = 16;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Command {
+    GetVersion = 0x01,
+    GetConfig = 0x02,
+    SetConfig = 0x03,
+    // applies the config uploaded with SetConfig
+    CommitConfig = 0x04,
+    GetInput = 0x05,
+    SetInput = 0x06,
+    Save = 0x07,
+    Reboot = 0x08,
+}
+
+impl Command {
+    fn from_u8(v: u8) -> Option<Self> {
+        Some(match v {
+            0x01 => Self::GetVersion,
+            0x02 => Self::GetConfig,
+            0x03 => Self::SetConfig,
+            0x04 => Self::CommitConfig,
+            0x05 => Self::GetInput,
+            0x06 => Self::SetInput,
+            0x07 => Self::Save,
+            0x08 => Self::Reboot,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum NakReason {
+    Malformed = 0x01,
+    UnknownCommand = 0x02,
+    OutOfRange = 0x03,
+    // the config or input doesn't pass validation
+    Invalid = 0x04,
+    Storage = 0x05,
+    TooLarge = 0x06,
+}
+
+impl NakReason {
+    fn from_u8(v: u8) -> Option<Self> {
+        Some(match v {
+            0x01 => Self::Malformed,
+            0x02 => Self::UnknownCommand,
+            0x03 => Self::OutOfRange,
+            0x04 => Self::Invalid,
+            0x05 => Self::Storage,
+            0x06 => Self::TooLarge,
+            _ => return None,
+        })
+    }
+}
+
+pub type Chunk = Vec<u8, SYSEX_CHUNK_MAX>;
+pub type Message = Vec<u8, SYSEX_MESSAGE_MAX>;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Request {
+    GetVersion,
+    GetConfig { offset: u16 },
+    SetConfig { offset: u16, data: Chunk },
+    CommitConfig { len: u16 },
+    GetInput { input: u8 },
+    // the input encoded with postcard
+    SetInput { input: u8, data: Chunk },
+    Save,
+    Reboot,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Response {
+    Version {
+        protocol: u8,
+        config: u8,
+        firmware: String<FIRMWARE_VERSION_MAX>,
+    },
+    // chunk of the binary config, len is its total length
+    Config {
+        offset: u16,
+        len: u16,
+        data: Chunk,
+    },
+    Input {
+        input: u8,
+        data: Chunk,
+    },
+    Ack(Command),
+    // the raw command as it might be unknown
+    Nak(u8, NakReason),
+}
+
+/*
+ * Writes the payload fields of a message.
+ */
+struct Writer(Message);
+
+impl Writer {
+    fn new(command: u8) -> Self {
+        let mut msg = Message::new();
+        msg.extend_from_slice(&[SYSEX_START, SYSEX_MANUFACTURER, command])
+            .ok();
+        Self(msg)
+    }
+
+    fn u7(mut self, v: u8) -> Self {
+        self.0.push(v & 0x7f).ok();
+        self
+    }
+
+    fn u14(self, v: u16) -> Self {
+        self.u7(v as u8).u7((v >> 7) as u8)
+    }
+
+    /*
+     * Packs groups of 7 bytes into a byte holding their most significant
+     * bits followed by their lower 7 bits.
+     */
+    fn packed(mut self, data: &[u8]) -> Self {
+        for group in data.chunks(7) {
+            let msbs = group
+                .iter()
+                .enumerate()
+                .fold(0, |m, (i, b)| m | ((b >> 7) << i));
+            self.0.push(msbs).ok();
+            for b in group {
+                self.0.push(b & 0x7f).ok();
+            }
+        }
+        self
+    }
+
+    fn ascii(mut self, s: &str) -> Self {
+        self.0
+            .extend(s.bytes().filter(|b| b.is_ascii()).map(|b| b & 0x7f));
+        self
+    }
+
+    fn end(mut self) -> Message {
+        self.0.push(SYSEX_END).ok();
+        self.0
+    }
+}
+
+/*
+ * Reads the payload fields of a message.
+ */
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /*
+     * Strips the framing and returns the command.
+     */
+    fn new(msg: &'a [u8]) -> Result<(u8, Self), NakReason> {
+        match msg {
+            [SYSEX_START, SYSEX_MANUFACTURER, command, payload @ .., SYSEX_END]
+                if *command < 0x80 && payload.iter().all(|b| *b < 0x80) =>
+            {
+                Ok((*command, Self(payload)))
+            }
+            _ => Err(NakReason::Malformed),
+        }
+    }
+
+    fn u7(&mut self) -> Result<u8, NakReason> {
+        let (v, rest) = self.0.split_first().ok_or(NakReason::Malformed)?;
+        self.0 = rest;
+        Ok(*v)
+    }
+
+    fn u14(&mut self) -> Result<u16, NakReason> {
+        Ok(self.u7()? as u16 | (self.u7()? as u16) << 7)
+    }
+
+    fn packed(&mut self) -> Result<Chunk, NakReason> {
+        let mut data = Chunk::new();
+        for group in self.0.chunks(8) {
+            let (msbs, bytes) = group.split_first().ok_or(NakReason::Malformed)?;
+            for (i, b) in bytes.iter().enumerate() {
+                data.push(b | ((msbs >> i) & 1) << 7)
+                    .map_err(|_| NakReason::TooLarge)?;
+            }
+        }
+        self.0 = &[];
+        Ok(data)
+    }
+
+    fn ascii<const N: usize>(&mut self) -> Result<String<N>, NakReason> {
+        let s = core::str::from_utf8(self.0).map_err(|_| NakReason::Malformed)?;
+        self.0 = &[];
+        String::try_from(s).map_err(|_| NakReason::TooLarge)
+    }
+
+    fn end(&self) -> Result<(), NakReason> {
+        match self.0 {
+            [] => Ok(()),
+            _ => Err(NakReason::Malformed),
+        }
+    }
+}
+
+impl Request {
+    pub fn command(&self) -> Command {
+        match self {
+            Request::GetVersion => Command::GetVersion,
+            Request::GetConfig { .. } => Command::GetConfig,
+            Request::SetConfig { .. } => Command::SetConfig,
+            Request::CommitConfig { .. } => Command::CommitConfig,
+            Request::GetInput { .. } => Command::GetInput,
+            Request::SetInput { .. } => Command::SetInput,
+            Request::Save => Command::Save,
+            Request::Reboot => Command::Reboot,
+        }
+    }
+
+    pub fn to_sysex(&self) -> Message {
+        let w = Writer::new(self.command() as u8);
+        match self {
+            Request::GetConfig { offset } => w.u14(*offset),
+            Request::SetConfig { offset, data } => w.u14(*offset).packed(data),
+            Request::CommitConfig { len } => w.u14(*len),
+            Request::GetInput { input } => w.u7(*input),
+            Request::SetInput { input, data } => w.u7(*input).packed(data),
+            Request::GetVersion | Request::Save | Request::Reboot => w,
+        }
+        .end()
+    }
+
+    /*
+     * Parses a complete SysEx message, on error returns the command as
+     * far as known along with the reason.
+     */
+    pub fn parse(msg: &[u8]) -> Result<Self, (u8, NakReason)> {
+        let (command, mut r) = Reader::new(msg).map_err(|e| (0, e))?;
+        Self::parse_payload(command, &mut r).map_err(|e| (command, e))
+    }
+
+    fn parse_payload(command: u8, r: &mut Reader) -> Result<Self, NakReason> {
+        let request = match Command::from_u8(command).ok_or(NakReason::UnknownCommand)? {
+            Command::GetVersion => Request::GetVersion,
+            Command::GetConfig => Request::GetConfig { offset: r.u14()? },
+            Command::SetConfig => Request::SetConfig {
+                offset: r.u14()?,
+                data: r.packed()?,
+            },
+            Command::CommitConfig => Request::CommitConfig { len: r.u14()? },
+            Command::GetInput => Request::GetInput { input: r.u7()? },
+            Command::SetInput => Request::SetInput {
+                input: r.u7()?,
+                data: r.packed()?,
+            },
+            Command::Save => Request::Save,
+            Command::Reboot => Request::Reboot,
+        };
+        r.end()?;
+        Ok(request)
+    }
+}
+
+impl Response {
+    pub fn to_sysex(&self) -> Message {
+        match self {
+            Response::Version {
+                protocol,
+                config,
+                firmware,
+            } => Writer::new(RESPONSE_BIT | Command::GetVersion as u8)
+                .u7(*protocol)
+                .u7(*config)
+                .ascii(firmware),
+            Response::Config { offset, len, data } => {
+                Writer::new(RESPONSE_BIT | Command::GetConfig as u8)
+                    .u14(*offset)
+                    .u14(*len)
+                    .packed(data)
+            }
+            Response::Input { input, data } => Writer::new(RESPONSE_BIT | Command::GetInput as u8)
+                .u7(*input)
+                .packed(data),
+            Response::Ack(command) => Writer::new(RESPONSE_ACK).u7(*command as u8),
+            Response::Nak(command, reason) => {
+                Writer::new(RESPONSE_NAK).u7(*command).u7(*reason as u8)
+            }
+        }
+        .end()
+    }
+
+    pub fn parse(msg: &[u8]) -> Result<Self, NakReason> {
+        let (command, mut r) = Reader::new(msg)?;
+        let response = match command {
+            RESPONSE_ACK => {
+                Response::Ack(Command::from_u8(r.u7()?).ok_or(NakReason::UnknownCommand)?)
+            }
+            RESPONSE_NAK => Response::Nak(
+                r.u7()?,
+                NakReason::from_u8(r.u7()?).ok_or(NakReason::Malformed)?,
+            ),
+            c if c == RESPONSE_BIT | Command::GetVersion as u8 => Response::Version {
+                protocol: r.u7()?,
+                config: r.u7()?,
+                firmware: r.ascii()?,
+            },
+            c if c == RESPONSE_BIT | Command::GetConfig as u8 => Response::Config {
+                offset: r.u14()?,
+                len: r.u14()?,
+                data: r.packed()?,
+            },
+            c if c == RESPONSE_BIT | Command::GetInput as u8 => Response::Input {
+                input: r.u7()?,
+                data: r.packed()?,
+            },
+            _ => return Err(NakReason::UnknownCommand),
+        };
+        r.end()?;
+        Ok(response)
+    }
+}
+
+/*
+ * Collects the bytes of a SysEx message, realtime messages in between
+ * are skipped. Messages longer than N bytes are dropped.
+ */
+pub struct SysexReader<const N: usize> {
+    buf: Vec<u8, N>,
+    active: bool,
+}
+
+impl<const N: usize> Default for SysexReader<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> SysexReader<N> {
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            active: false,
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<&[u8]> {
+        if byte >= MIDI_REALTIME {
+            return None;
+        }
+        if byte == SYSEX_START {
+            self.buf.clear();
+            self.active = true;
+        } else if !self.active {
+            return None;
+        } else if byte & 0x80 != 0 && byte != SYSEX_END {
+            // aborted by another status byte
+            self.active = false;
+            return None;
+        }
+
+        if self.buf.push(byte).is_err() {
+            self.active = false;
+            return None;
+        }
+        if byte == SYSEX_END {
+            self.active = false;
+            return Some(&self.buf);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(data: &[u8]) -> Chunk {
+        Chunk::from_slice(data).unwrap()
+    }
+
+    #[test]
+    fn pack_7_into_8() {
+        let data: std::vec::Vec<u8> = (0..9).map(|i| 0x7e + i).collect();
+        let msg = Request::SetInput {
+            input: 3,
+            data: chunk(&data),
+        }
+        .to_sysex();
+
+        assert_eq!(
+            msg,
+            [
+                0xf0, 0x7d, 0x06, 3, // header
+                0b1111100, 0x7e, 0x7f, 0, 1, 2, 3, 4, // first group
+                0b11, 5, 6, // second group
+                0xf7
+            ]
+        );
+        assert_eq!(
+            Request::parse(&msg),
+            Ok(Request::SetInput {
+                input: 3,
+                data: chunk(&data),
+            })
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let requests = [
+            Request::GetVersion,
+            Request::GetConfig { offset: 300 },
+            Request::SetConfig {
+                offset: 64,
+                data: chunk(&[0xff; SYSEX_CHUNK_MAX]),
+            },
+            Request::CommitConfig { len: 1000 },
+            Request::GetInput { input: 7 },
+            Request::Save,
+            Request::Reboot,
+        ];
+        for r in requests {
+            assert_eq!(Request::parse(&r.to_sysex()), Ok(r));
+        }
+
+        let responses = [
+            Response::Version {
+                protocol: 1,
+                config: 1,
+                firmware: "0.1.0".try_into().unwrap(),
+            },
+            Response::Config {
+                offset: 128,
+                len: 200,
+                data: chunk(&[0x80, 0, 0x42]),
+            },
+            Response::Input {
+                input: 2,
+                data: chunk(&[]),
+            },
+            Response::Ack(Command::Save),
+            Response::Nak(0x13, NakReason::UnknownCommand),
+        ];
+        for r in responses {
+            assert_eq!(Response::parse(&r.to_sysex()), Ok(r));
+        }
+    }
+
+    #[test]
+    fn reject_malformed() {
+        assert_eq!(
+            Request::parse(&[0xf0, 0x7d, 0x13, 0xf7]),
+            Err((0x13, NakReason::UnknownCommand))
+        );
+        assert_eq!(
+            Request::parse(&[0xf0, 0x7d, 0x02, 1, 0xf7]),
+            Err((0x02, NakReason::Malformed))
+        );
+        assert_eq!(
+            Request::parse(&[0xf0, 0x7d, 0x07, 0]),
+            Err((0, NakReason::Malformed))
+        );
+        // more data than fits a chunk
+        let mut msg = std::vec![0xf0, 0x7d, 0x03, 0, 0];
+        msg.extend([0; 80]);
+        msg.push(0xf7);
+        assert_eq!(Request::parse(&msg), Err((0x03, NakReason::TooLarge)));
+    }
+
+    #[test]
+    fn read_sysex_bytes() {
+        let mut reader = SysexReader::<8>::new();
+        let mut read = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .filter_map(|b| reader.push(*b).map(|m| m.to_vec()))
+                .collect::<std::vec::Vec<_>>()
+        };
+
+        // realtime in between, aborted by a status byte and too long
+        assert_eq!(
+            read(&[0xf0, 0x7d, 0xf8, 1, 0xf7, 0x90, 0xf0, 1, 0xb0, 0xf7]),
+            [[0xf0, 0x7d, 1, 0xf7]]
+        );
+        assert!(read(&[0xf0, 1, 2, 3, 4, 5, 6, 7, 8, 0xf7]).is_empty());
+    }
+}
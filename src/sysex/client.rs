@@ -0,0 +1,180 @@
+use crate::sysex::{
+    Chunk, Command, NakReason, Request, Response, SysexReader, SYSEX_CHUNK_MAX, SYSEX_MANUFACTURER,
+    SYSEX_MESSAGE_MAX,
+};
+use crate::ui::InputType;
+
+use std::fmt;
+
+/*
+ * Connection to a device, e.g. a MIDI port.
+ */
+pub trait Transport {
+    fn send(&mut self, msg: &[u8]) -> std::io::Result<()>;
+    /*
+     * Waits for the next byte from the device, fails on timeout.
+     */
+    fn receive(&mut self) -> std::io::Result<u8>;
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(std::io::Error),
+    Nak(u8, NakReason),
+    // the device answered with something not matching the request
+    Protocol,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "{}", e),
+            ClientError::Nak(command, reason) => {
+                write!(f, "command {:#04x} refused: {:?}", command, reason)
+            }
+            ClientError::Protocol => write!(f, "unexpected response"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<std::io::Error> for ClientError {
+    fn from(e: std::io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Version {
+    pub protocol: u8,
+    // version of the binary config format
+    pub config: u8,
+    pub firmware: std::string::String,
+}
+
+/*
+ * Host side of the protocol.
+ */
+pub struct Client<T: Transport> {
+    transport: T,
+    reader: SysexReader<SYSEX_MESSAGE_MAX>,
+}
+
+impl<T: Transport> Client<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            reader: SysexReader::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    pub fn version(&mut self) -> Result<Version, ClientError> {
+        match self.request(&Request::GetVersion)? {
+            Response::Version {
+                protocol,
+                config,
+                firmware,
+            } => Ok(Version {
+                protocol,
+                config,
+                firmware: firmware.as_str().into(),
+            }),
+            _ => Err(ClientError::Protocol),
+        }
+    }
+
+    /*
+     * Reads the binary config of the device.
+     */
+    pub fn pull(&mut self) -> Result<Vec<u8>, ClientError> {
+        let mut config = Vec::new();
+        loop {
+            let offset = config.len() as u16;
+            match self.request(&Request::GetConfig { offset })? {
+                Response::Config {
+                    offset: o,
+                    len,
+                    data,
+                } if o == offset => {
+                    config.extend_from_slice(&data);
+                    if config.len() >= len as usize || data.is_empty() {
+                        return Ok(config);
+                    }
+                }
+                _ => return Err(ClientError::Protocol),
+            }
+        }
+    }
+
+    /*
+     * Uploads a binary config, the device only takes it over if valid.
+     * It's kept until the next reboot unless saved.
+     */
+    pub fn push(&mut self, config: &[u8]) -> Result<(), ClientError> {
+        for (n, chunk) in config.chunks(SYSEX_CHUNK_MAX).enumerate() {
+            self.ack(&Request::SetConfig {
+                offset: (n * SYSEX_CHUNK_MAX) as u16,
+                data: Chunk::from_slice(chunk).unwrap_or_default(),
+            })?;
+        }
+        self.ack(&Request::CommitConfig {
+            len: config.len() as u16,
+        })
+    }
+
+    pub fn get_input(&mut self, input: u8) -> Result<InputType, ClientError> {
+        match self.request(&Request::GetInput { input })? {
+            Response::Input { input: i, data } if i == input => {
+                postcard::from_bytes(&data).map_err(|_| ClientError::Protocol)
+            }
+            _ => Err(ClientError::Protocol),
+        }
+    }
+
+    pub fn set_input(&mut self, input: u8, i: &InputType) -> Result<(), ClientError> {
+        let mut buf = [0; SYSEX_CHUNK_MAX];
+        let data = postcard::to_slice(i, &mut buf)
+            .map_err(|_| ClientError::Nak(Command::SetInput as u8, NakReason::TooLarge))?;
+        self.ack(&Request::SetInput {
+            input,
+            data: Chunk::from_slice(data).unwrap_or_default(),
+        })
+    }
+
+    pub fn save(&mut self) -> Result<(), ClientError> {
+        self.ack(&Request::Save)
+    }
+
+    pub fn reboot(&mut self) -> Result<(), ClientError> {
+        self.ack(&Request::Reboot)
+    }
+
+    fn ack(&mut self, request: &Request) -> Result<(), ClientError> {
+        match self.request(request)? {
+            Response::Ack(command) if command == request.command() => Ok(()),
+            _ => Err(ClientError::Protocol),
+        }
+    }
+
+    fn request(&mut self, request: &Request) -> Result<Response, ClientError> {
+        self.transport.send(&request.to_sysex())?;
+        loop {
+            let byte = self.transport.receive()?;
+            let Some(msg) = self.reader.push(byte) else {
+                continue;
+            };
+            if msg.get(1) != Some(&SYSEX_MANUFACTURER) {
+                continue;
+            }
+            return match Response::parse(msg).map_err(|_| ClientError::Protocol)? {
+                Response::Nak(command, reason) => Err(ClientError::Nak(command, reason)),
+                response => Ok(response),
+            };
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::sysex::Transport;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/*
+ * Raw MIDI device of the sound system like `/dev/snd/midiC1D0`, which
+ * the board shows up as when plugged in over USB. A thread reads the
+ * incoming bytes, so receiving can time out if the device doesn't answer.
+ */
+pub struct RawMidi {
+    file: File,
+    bytes: Receiver<io::Result<u8>>,
+    timeout: Duration,
+}
+
+impl RawMidi {
+    pub fn open(path: impl AsRef<Path>, timeout: Duration) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut reader = file.try_clone()?;
+        let (sender, bytes) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0; 64];
+            loop {
+                let result = match reader.read(&mut buf) {
+                    Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+                    Ok(len) => Ok(&buf[..len]),
+                    Err(e) => Err(e),
+                };
+                let sent = match result {
+                    Ok(data) => data.iter().all(|b| sender.send(Ok(*b)).is_ok()),
+                    Err(e) => {
+                        sender.send(Err(e)).ok();
+                        false
+                    }
+                };
+                if !sent {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            file,
+            bytes,
+            timeout,
+        })
+    }
+}
+
+impl Transport for RawMidi {
+    fn send(&mut self, msg: &[u8]) -> io::Result<()> {
+        self.file.write_all(msg)?;
+        self.file.flush()
+    }
+
+    fn receive(&mut self) -> io::Result<u8> {
+        match self.bytes.recv_timeout(self.timeout) {
+            Ok(byte) => byte,
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no answer from the device",
+            )),
+            Err(RecvTimeoutError::Disconnected) => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crate::config::BINARY_VERSION;
+use crate::device::Device;
+use crate::output::OutputType;
+use crate::storage::{Flash, Storage};
+use crate::sysex::{
+    Chunk, NakReason, Request, Response, SysexReader, SYSEX_CHUNK_MAX, SYSEX_MANUFACTURER,
+    SYSEX_MESSAGE_MAX, SYSEX_PROTOCOL_VERSION,
+};
+use crate::ui::InputType;
+
+use heapless::{String, Vec};
+
+pub const SYSEX_CONFIG_MAX: usize = 1024;
+
+// what the config buffer of the server holds
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Buffer {
+    Empty,
+    Upload,
+    Snapshot,
+}
+
+/*
+ * Device side of the protocol, answers the requests read byte by byte
+ * from the host. An uploaded config is collected in order and replaces
+ * the device only once committed and validated. A pull serializes the
+ * device once at offset 0 and hands out chunks of that snapshot. Both
+ * share a single buffer, so pulling or saving drops an upload in
+ * progress.
+ */
+pub struct ConfigServer {
+    reader: SysexReader<SYSEX_MESSAGE_MAX>,
+    buf: Vec<u8, SYSEX_CONFIG_MAX>,
+    content: Buffer,
+    reboot: bool,
+}
+
+impl Default for ConfigServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigServer {
+    pub fn new() -> Self {
+        Self {
+            reader: SysexReader::new(),
+            buf: Vec::new(),
+            content: Buffer::Empty,
+            reboot: false,
+        }
+    }
+
+    /*
+     * Set once a reboot was acknowledged, which is then up to the
+     * firmware.
+     */
+    pub fn reboot_requested(&self) -> bool {
+        self.reboot
+    }
+
+    /*
+     * Feeds a byte received from the host, returns the response once a
+     * request is complete. SysEx of other manufacturers is ignored. The
+     * outputs get the note offs of a device replaced by a new config.
+     */
+    pub async fn receive<const N: usize, F: Flash>(
+        &mut self,
+        byte: u8,
+        device: &mut Device<N>,
+        storage: &mut Storage<F>,
+        outputs: &[OutputType],
+    ) -> Option<Response> {
+        let msg = self.reader.push(byte)?;
+        if msg.get(1) != Some(&SYSEX_MANUFACTURER) {
+            return None;
+        }
+        let response = match Request::parse(msg) {
+            Ok(request) => {
+                let command = request.command();
+                self.handle(request, device, storage, outputs)
+                    .await
+                    .unwrap_or_else(|reason| Response::Nak(command as u8, reason))
+            }
+            Err((command, reason)) => Response::Nak(command, reason),
+        };
+        Some(response)
+    }
+
+    /*
+     * Serializes the device into the buffer.
+     */
+    fn snapshot<const N: usize>(&mut self, device: &Device<N>) -> Result<(), NakReason> {
+        self.content = Buffer::Empty;
+        self.buf.clear();
+        self.buf.resize_default(SYSEX_CONFIG_MAX).ok();
+        let len = match device.to_binary(&mut self.buf) {
+            Ok(data) => data.len(),
+            Err(_) => {
+                self.buf.clear();
+                return Err(NakReason::TooLarge);
+            }
+        };
+        self.buf.truncate(len);
+        self.content = Buffer::Snapshot;
+        Ok(())
+    }
+
+    async fn handle<const N: usize, F: Flash>(
+        &mut self,
+        request: Request,
+        device: &mut Device<N>,
+        storage: &mut Storage<F>,
+        outputs: &[OutputType],
+    ) -> Result<Response, NakReason> {
+        let command = request.command();
+        match request {
+            Request::GetVersion => Ok(Response::Version {
+                protocol: SYSEX_PROTOCOL_VERSION,
+                config: BINARY_VERSION,
+                firmware: String::try_from(env!("CARGO_PKG_VERSION")).unwrap_or_default(),
+            }),
+            Request::GetConfig { offset } => {
+                if offset == 0 {
+                    self.snapshot(device)?;
+                }
+                if self.content != Buffer::Snapshot {
+                    return Err(NakReason::OutOfRange);
+                }
+                let chunk = self
+                    .buf
+                    .get(offset as usize..)
+                    .ok_or(NakReason::OutOfRange)?;
+                let chunk = &chunk[..chunk.len().min(SYSEX_CHUNK_MAX)];
+                Ok(Response::Config {
+                    offset,
+                    len: self.buf.len() as u16,
+                    data: Chunk::from_slice(chunk).unwrap_or_default(),
+                })
+            }
+            Request::SetConfig { offset, data } => {
+                if offset == 0 {
+                    self.buf.clear();
+                    self.content = Buffer::Upload;
+                }
+                if self.content != Buffer::Upload || offset as usize != self.buf.len() {
+                    return Err(NakReason::OutOfRange);
+                }
+                self.buf
+                    .extend_from_slice(&data)
+                    .map_err(|_| NakReason::TooLarge)?;
+                Ok(Response::Ack(command))
+            }
+            Request::CommitConfig { len } => {
+                if self.content != Buffer::Upload || len as usize != self.buf.len() {
+                    return Err(NakReason::OutOfRange);
+                }
+                let new = Device::from_binary_sized(&self.buf).map_err(|_| NakReason::Invalid)?;
+                // notes of the old device would keep hanging otherwise
+                device.release_notes(outputs).await;
+                *device = new;
+                self.buf.clear();
+                self.content = Buffer::Empty;
+                Ok(Response::Ack(command))
+            }
+            Request::GetInput { input } => {
+                let i = device
+                    .inputs()
+                    .get(input as usize)
+                    .ok_or(NakReason::OutOfRange)?;
+                let mut buf = [0; SYSEX_CHUNK_MAX];
+                let data = postcard::to_slice(i, &mut buf).map_err(|_| NakReason::TooLarge)?;
+                Ok(Response::Input {
+                    input,
+                    data: Chunk::from_slice(data).unwrap_or_default(),
+                })
+            }
+            Request::SetInput { input, data } => {
+                if input as usize >= device.inputs().len() {
+                    return Err(NakReason::OutOfRange);
+                }
+                let i: InputType = postcard::from_bytes(&data).map_err(|_| NakReason::Malformed)?;
+                device
+                    .replace_input(input as usize, i)
+                    .map_err(|_| NakReason::Invalid)?;
+                // a pull has to start over to see the change
+                if self.content == Buffer::Snapshot {
+                    self.content = Buffer::Empty;
+                }
+                Ok(Response::Ack(command))
+            }
+            Request::Save => {
+                self.snapshot(device)?;
+                storage.save(&self.buf).map_err(|_| NakReason::Storage)?;
+                Ok(Response::Ack(command))
+            }
+            Request::Reboot => {
+                self.reboot = true;
+                Ok(Response::Ack(command))
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+/*
+ * Monotonic time in microseconds. On linux the time is counted from the
+ * first call, on the bare-metal target from boot.
+ */
+#[cfg(target_os = "linux")]
+pub fn now() -> u64 {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_micros() as u64
+}
+
+#[cfg(target_os = "none")]
+pub fn now() -> u64 {
+    embassy_time::Instant::now().as_micros()
+}
+
+/*
+ * Source of time and async delays, so that time driven subsystems can run
+ * against the system time or a fake clock in tests.
+ */
+// timers are polled on the single threaded executor, the futures don't
+// need to be Send
+#[allow(async_fn_in_trait)]
+pub trait Timer {
+    fn now(&self) -> u64;
+    /*
+     * Waits until the given time in microseconds, returns right away if
+     * the time already passed.
+     */
+    async fn sleep_until(&mut self, deadline: u64);
+}
+
+#[cfg(target_os = "linux")]
+pub struct SystemTimer;
+
+#[cfg(target_os = "linux")]
+impl Timer for SystemTimer {
+    fn now(&self) -> u64 {
+        now()
+    }
+
+    async fn sleep_until(&mut self, deadline: u64) {
+        let delay = deadline.saturating_sub(now());
+        async_std::task::sleep(std::time::Duration::from_micros(delay)).await;
+    }
+}
+
+#[cfg(target_os = "none")]
+pub struct EmbassyTimer;
+
+#[cfg(target_os = "none")]
+impl Timer for EmbassyTimer {
+    fn now(&self) -> u64 {
+        now()
+    }
+
+    async fn sleep_until(&mut self, deadline: u64) {
+        embassy_time::Timer::at(embassy_time::Instant::from_micros(deadline)).await;
+    }
+}
+
+/*
+ * Timer which only moves forward when told to. Sleeping jumps straight to
+ * the deadline.
+ */
+#[derive(Debug, Default)]
+pub struct FakeTimer {
+    now: u64,
+}
+
+impl FakeTimer {
+    pub fn new(now: u64) -> Self {
+        Self { now }
+    }
+
+    pub fn advance(&mut self, us: u64) {
+        self.now += us;
+    }
+}
+
+impl Timer for FakeTimer {
+    fn now(&self) -> u64 {
+        self.now
+    }
+
+    async fn sleep_until(&mut self, deadline: u64) {
+        self.now = self.now.max(deadline);
+    }
+}
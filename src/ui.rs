@@ -1,24 +1,95 @@
 pub mod input {
+    mod button;
     mod encoder;
+    mod generator;
     mod potentiometer;
+    pub use self::button::Button;
     pub use self::{encoder::Encoder, encoder::EncoderDirection, potentiometer::Potentiometer};
+    pub use self::{generator::Generator, generator::GeneratorControl};
+    pub use self::{generator::GeneratorParam, generator::Waveform};
 }
 
 pub mod backend {
     mod memory;
+    #[cfg(target_os = "linux")]
+    mod simulated;
     #[cfg(target_os = "none")]
     mod stm32;
     pub use self::memory::InMemoryBackend;
 
+    #[cfg(target_os = "linux")]
+    pub use self::simulated::SimulatedBackend;
+
     #[cfg(target_os = "none")]
-    pub use self::stm32::Stm32Backend;
+    pub use self::stm32::{Stm32Backend, MIDI_IN, SYSEX_IN, SYSEX_OUT};
 }
 
+mod chain;
+pub use self::chain::{Board, Chain, BOARD_ENABLE, CHAIN_BOARDS_MAX};
+
+use crate::output::OutputData;
+use crate::ui::input::Button;
 use crate::ui::input::Encoder;
+use crate::ui::input::Generator;
 use crate::ui::input::Potentiometer;
 
 use serde::{Deserialize, Serialize};
 
+/*
+ * Maximum slots on a board and mux lines per slot.
+ */
+pub const BOARD_SLOTS: u8 = 8;
+pub const SLOT_PINS: u8 = 4;
+
+// highest address parts, for the config schema
+#[cfg(feature = "schema")]
+const ADDRESS_BOARD_MAX: u8 = CHAIN_BOARDS_MAX as u8 - 1;
+#[cfg(feature = "schema")]
+const ADDRESS_SLOT_MAX: u8 = BOARD_SLOTS - 1;
+#[cfg(feature = "schema")]
+const ADDRESS_PIN_MAX: u8 = SLOT_PINS - 1;
+
+/*
+ * Location of an input on the multiplexed PCB: the board in the chain,
+ * the slot on the board and the first mux line used within the slot.
+ * Inputs using several lines take the ones following.
+ */
+#[derive(Debug, Default, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Address {
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(range(max = "ADDRESS_BOARD_MAX")))]
+    pub board: u8,
+    #[cfg_attr(feature = "schema", schemars(range(max = "ADDRESS_SLOT_MAX")))]
+    pub slot: u8,
+    #[serde(default)]
+    #[cfg_attr(feature = "schema", schemars(range(max = "ADDRESS_PIN_MAX")))]
+    pub pin: u8,
+}
+
+impl Address {
+    pub const fn new(board: u8, slot: u8, pin: u8) -> Self {
+        Self { board, slot, pin }
+    }
+
+    /*
+     * Address of the line n pins further within the same slot.
+     */
+    pub const fn offset(self, n: u8) -> Self {
+        Self {
+            pin: self.pin + n,
+            ..self
+        }
+    }
+
+    /*
+     * Index of the mux line on its board.
+     */
+    pub const fn line(&self) -> u8 {
+        self.slot * SLOT_PINS + self.pin
+    }
+}
+
 pub trait Input {
     /*
      * Updates its state using the given HAL backend.
@@ -28,13 +99,65 @@ pub trait Input {
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum InputType {
     Encoder(Encoder),
     Potentiometer(Potentiometer),
+    Button(Button),
+    Generator(Generator),
+}
+
+impl InputType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputType::Encoder(_) => "Encoder",
+            InputType::Potentiometer(_) => "Potentiometer",
+            InputType::Button(_) => "Button",
+            InputType::Generator(_) => "Generator",
+        }
+    }
+
+    pub fn receive(&mut self, data: &OutputData) {
+        match self {
+            InputType::Encoder(i) => i.receive(data),
+            InputType::Potentiometer(i) => i.receive(data),
+            InputType::Button(i) => i.receive(data),
+            InputType::Generator(i) => i.receive(data),
+        }
+    }
+
+    /*
+     * First mux line used by the input and the number of lines, virtual
+     * inputs don't use any.
+     */
+    pub fn lines(&self) -> Option<(Address, u8)> {
+        match self {
+            InputType::Encoder(i) => Some((i.address, 2)),
+            InputType::Potentiometer(i) => Some((i.address, 1)),
+            InputType::Button(i) => Some((i.address, 1)),
+            InputType::Generator(_) => None,
+        }
+    }
+
+    pub fn handler_name(&self) -> &'static str {
+        match self {
+            InputType::Encoder(i) => i.handler.name(),
+            InputType::Potentiometer(i) => i.handler.name(),
+            InputType::Button(i) => i.handler.name(),
+            InputType::Generator(i) => i.handler.name(),
+        }
+    }
 }
 
 pub trait Backend {
-    async fn read_adc(&mut self) -> u16;
-    fn read_input(&mut self) -> bool;
-    fn rewind(&mut self);
+    async fn read_adc(&mut self, address: Address) -> u16;
+    fn read_input(&mut self, address: Address) -> bool;
+
+    /*
+     * Number of boards found in the chain, None if the hardware can't
+     * tell.
+     */
+    fn detect_boards(&mut self) -> Option<usize> {
+        None
+    }
 }
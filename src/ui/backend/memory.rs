@@ -1,76 +1,54 @@
-use crate::ui::Backend;
+use crate::ui::{Address, Backend};
 
-const IN_MEMORY_BACKEND_ADC_SIZE: usize = 4;
-const IN_MEMORY_BACKEND_INPUT_SIZE: usize = 16;
+use heapless::LinearMap;
 
+const IN_MEMORY_BACKEND_LINES_MAX: usize = 32;
+
+/*
+ * Backend holding the level of every addressed line in memory, lines
+ * never set read as low.
+ */
 pub struct InMemoryBackend {
-    adc_buffer: [u16; IN_MEMORY_BACKEND_ADC_SIZE],
-    adc_buffer_pos: usize,
-    input_buffer: [bool; IN_MEMORY_BACKEND_INPUT_SIZE],
-    input_buffer_pos: usize,
+    adc: LinearMap<Address, u16, IN_MEMORY_BACKEND_LINES_MAX>,
+    inputs: LinearMap<Address, bool, IN_MEMORY_BACKEND_LINES_MAX>,
 }
 
 impl Backend for InMemoryBackend {
-    async fn read_adc(&mut self) -> u16 {
-        let result = self.adc_buffer.get(self.adc_buffer_pos);
-        self.adc_buffer_pos += 1;
-
-        match result {
-            Some(data) => *data,
-            None => {
-                self.adc_buffer_pos = 1;
-                *self
-                    .adc_buffer
-                    .get(0)
-                    .expect("should have at least one value")
-            }
-        }
+    async fn read_adc(&mut self, address: Address) -> u16 {
+        self.adc.get(&address).copied().unwrap_or(0)
     }
 
-    fn read_input(&mut self) -> bool {
-        let result = self.input_buffer.get(self.input_buffer_pos);
-        self.input_buffer_pos += 1;
-
-        match result {
-            Some(data) => *data,
-            None => {
-                self.input_buffer_pos = 1;
-                *self
-                    .input_buffer
-                    .get(0)
-                    .expect("should have at least one value")
-            }
-        }
+    fn read_input(&mut self, address: Address) -> bool {
+        self.inputs.get(&address).copied().unwrap_or(false)
     }
-
-    fn rewind(&mut self) {}
 }
 
 impl InMemoryBackend {
     pub fn new() -> Self {
         Self {
-            adc_buffer: [0; IN_MEMORY_BACKEND_ADC_SIZE],
-            adc_buffer_pos: 0,
-            input_buffer: [false; IN_MEMORY_BACKEND_INPUT_SIZE],
-            input_buffer_pos: 0,
+            adc: LinearMap::new(),
+            inputs: LinearMap::new(),
         }
     }
 
-    pub fn set_adc_buffer(&mut self, data: &[u16]) {
-        if data.len() > self.adc_buffer.len() {
-            panic!("Maximum data size: {IN_MEMORY_BACKEND_ADC_SIZE}");
-        }
-        for (i, v) in data.iter().enumerate() {
-            self.adc_buffer[i] = *v;
+    pub fn set_adc(&mut self, address: Address, value: u16) {
+        if self.adc.insert(address, value).is_err() {
+            panic!("Maximum lines: {IN_MEMORY_BACKEND_LINES_MAX}");
         }
     }
 
-    pub fn set_input_buffer(&mut self, data: &[bool]) {
-        if data.len() > self.input_buffer.len() {
-            panic!("Maximum data size: {IN_MEMORY_BACKEND_INPUT_SIZE}");
+    pub fn set_input(&mut self, address: Address, level: bool) {
+        if self.inputs.insert(address, level).is_err() {
+            panic!("Maximum lines: {IN_MEMORY_BACKEND_LINES_MAX}");
         }
-        for (i, v) in data.iter().enumerate() {
-            self.input_buffer[i] = *v;
+    }
+
+    /*
+     * Sets consecutive lines starting at the given address.
+     */
+    pub fn set_inputs(&mut self, address: Address, levels: &[bool]) {
+        for (n, level) in levels.iter().enumerate() {
+            self.set_input(address.offset(n as u8), *level);
         }
     }
 }
@@ -80,37 +58,27 @@ mod tests {
     use super::*;
 
     #[async_std::test]
-    async fn read_adc_wraps_around() {
+    async fn read_adc_addressed() {
         let mut b = InMemoryBackend::new();
-        let data: [u16; 4] = [42, 23, 5, 1];
-
-        b.set_adc_buffer(&data);
-
-        let expected: [u16; 6] = [42, 23, 5, 1, 42, 23];
-        let mut actual: [u16; 6] = [0; 6];
-
-        for v in &mut actual {
-            *v = b.read_adc().await;
-        }
+        b.set_adc(Address::new(0, 1, 0), 42);
+        b.set_adc(Address::new(1, 1, 0), 23);
 
-        assert_eq!(expected, actual);
+        assert_eq!(b.read_adc(Address::new(0, 1, 0)).await, 42);
+        assert_eq!(b.read_adc(Address::new(1, 1, 0)).await, 23);
+        assert_eq!(b.read_adc(Address::new(0, 2, 0)).await, 0);
     }
 
     #[test]
-    #[ignore = "needs changes of buffersize"]
-    fn read_input_wraps_around() {
+    fn read_input_addressed() {
         let mut b = InMemoryBackend::new();
-        let data: [bool; 4] = [true, false, false, true];
-
-        b.set_input_buffer(&data);
+        b.set_inputs(Address::new(0, 3, 1), &[true, false, true]);
 
-        let expected: [bool; 6] = [true, false, false, true, true, false];
-        let mut actual: [bool; 6] = [false; 6];
-
-        for v in &mut actual {
-            *v = b.read_input();
-        }
+        let levels: std::vec::Vec<bool> = (0..4)
+            .map(|pin| b.read_input(Address::new(0, 3, pin)))
+            .collect();
+        assert_eq!(levels, [false, true, false, true]);
 
-        assert_eq!(expected, actual);
+        b.set_input(Address::new(0, 3, 1), false);
+        assert!(!b.read_input(Address::new(0, 3, 1)));
     }
 }
@@ -0,0 +1,154 @@
+use crate::ui::{Address, Backend, Chain, BOARD_ENABLE, SLOT_PINS};
+
+use std::collections::HashMap;
+
+/*
+ * Emulates a chain of boards down to their shift registers, lines are
+ * selected by shifting the frames of the configured chain through it.
+ * The number of boards present may differ from the configured ones.
+ */
+pub struct SimulatedBackend {
+    chain: Chain,
+    // register contents, nearest board first
+    registers: Vec<u8>,
+    adc: HashMap<Address, u16>,
+    inputs: HashMap<Address, bool>,
+}
+
+impl Backend for SimulatedBackend {
+    async fn read_adc(&mut self, address: Address) -> u16 {
+        match self.select(address) {
+            Some(line) => self.adc.get(&line).copied().unwrap_or(0),
+            None => 0,
+        }
+    }
+
+    fn read_input(&mut self, address: Address) -> bool {
+        match self.select(address) {
+            Some(line) => self.inputs.get(&line).copied().unwrap_or(false),
+            // nothing drives the line, the pull-up does
+            None => true,
+        }
+    }
+
+    fn detect_boards(&mut self) -> Option<usize> {
+        Chain::detect(|byte| self.transfer(byte))
+    }
+}
+
+impl SimulatedBackend {
+    /*
+     * Chain of the given number of boards, driven as configured by chain.
+     */
+    pub fn new(boards: usize, chain: Chain) -> Self {
+        Self {
+            chain,
+            registers: vec![0; boards],
+            adc: HashMap::new(),
+            inputs: HashMap::new(),
+        }
+    }
+
+    pub fn set_adc(&mut self, address: Address, value: u16) {
+        self.adc.insert(address, value);
+    }
+
+    pub fn set_input(&mut self, address: Address, level: bool) {
+        self.inputs.insert(address, level);
+    }
+
+    /*
+     * Shifts a byte into the first register and returns the one falling
+     * out of the last.
+     */
+    pub fn transfer(&mut self, byte: u8) -> u8 {
+        self.registers.insert(0, byte);
+        self.registers.pop().unwrap_or(byte)
+    }
+
+    /*
+     * Shifts the frame of the address into the chain and returns the line
+     * connected to the common pin. None if no board drives it, or if
+     * several do and the level is undefined.
+     */
+    fn select(&mut self, address: Address) -> Option<Address> {
+        for byte in self.chain.frame(address) {
+            self.transfer(byte);
+        }
+
+        // a single board keeps its mux enabled
+        let single = self.chain.len() == 1;
+        let mut enabled = self
+            .registers
+            .iter()
+            .enumerate()
+            .filter(|(board, r)| (single && *board == 0) || *r & BOARD_ENABLE != 0);
+        let (board, register) = enabled.next()?;
+        if enabled.next().is_some() {
+            return None;
+        }
+
+        let line = register & !BOARD_ENABLE;
+        Some(Address::new(
+            board as u8,
+            line / SLOT_PINS,
+            line % SLOT_PINS,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::Board;
+
+    fn chain(boards: usize) -> Chain {
+        let mut chain = Chain::new();
+        for _ in 0..boards {
+            chain.add_board(Board::default()).unwrap();
+        }
+        chain
+    }
+
+    #[async_std::test]
+    async fn read_through_chain() {
+        let mut b = SimulatedBackend::new(3, chain(3));
+        b.set_input(Address::new(2, 5, 1), true);
+        b.set_adc(Address::new(1, 7, 3), 1000);
+
+        assert!(b.read_input(Address::new(2, 5, 1)));
+        assert!(!b.read_input(Address::new(1, 5, 1)));
+        assert_eq!(b.read_adc(Address::new(1, 7, 3)).await, 1000);
+        assert_eq!(b.read_adc(Address::new(2, 7, 3)).await, 0);
+    }
+
+    #[test]
+    fn missing_board() {
+        let mut b = SimulatedBackend::new(1, chain(2));
+        b.set_input(Address::new(1, 0, 0), false);
+
+        assert_eq!(b.detect_boards(), Some(1));
+        // the frame for the second board falls out of the chain
+        assert!(b.read_input(Address::new(1, 0, 0)));
+    }
+
+    #[test]
+    fn single_board() {
+        let mut b = SimulatedBackend::new(1, chain(1));
+        b.set_input(Address::new(0, 3, 2), true);
+
+        assert!(b.read_input(Address::new(0, 3, 2)));
+        assert!(!b.read_input(Address::new(0, 3, 1)));
+    }
+
+    #[test]
+    fn several_boards_drive_the_line() {
+        let mut b = SimulatedBackend::new(3, chain(2));
+        b.set_input(Address::new(0, 0, 0), false);
+        b.set_input(Address::new(1, 0, 0), false);
+
+        assert!(!b.read_input(Address::new(0, 0, 0)));
+        // the stale frame left in the third board enables it as well
+        assert!(b.read_input(Address::new(1, 0, 0)));
+    }
+}
@@ -1,21 +1,31 @@
 use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_futures::select::{select, Either};
 use embassy_stm32::adc::{Adc, AdcPin, InterruptHandler};
 use embassy_stm32::dma::NoDma;
+use embassy_stm32::flash::Flash;
 use embassy_stm32::gpio::{Flex, Input, Level, Output, Pull, Speed};
 use embassy_stm32::spi::{Config as SpiConfig, Spi};
 use embassy_stm32::time::Hertz;
+use embassy_stm32::usart::{Config as UartConfig, UartRx};
 use embassy_stm32::usb::Driver;
-use embassy_stm32::{adc, bind_interrupts, peripherals, usb, Config, Peripheral};
+use embassy_stm32::{adc, bind_interrupts, peripherals, usart, usb, Config, Peripheral};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
 use embassy_time::{Delay, Timer};
-use embassy_usb::class::midi::{MidiClass, Sender};
+use embassy_usb::class::midi::{MidiClass, Receiver, Sender};
 use embassy_usb::driver::EndpointError;
 use embassy_usb::Builder;
 
 use defmt::info;
 use static_cell::StaticCell;
 
-use crate::output::CHANNEL;
-use crate::ui::Backend;
+use crate::output::{
+    from_usb_packet, usb_packet_bytes, MidiParser, OutputData, UsbPacketizer, CHANNEL,
+};
+use crate::storage::Stm32Flash;
+use crate::sysex::Message;
+use crate::ui::{Address, Backend, Chain};
 
 bind_interrupts!(struct ADCIrqs {
         ADC1_2 => adc::InterruptHandler<peripherals::ADC1>;
@@ -25,11 +35,36 @@ bind_interrupts!(struct USBIrqs {
     USB_LP_CAN1_RX0 => usb::InterruptHandler<peripherals::USB>;
 });
 
+bind_interrupts!(struct UartIrqs {
+    USART3 => usart::InterruptHandler<peripherals::USART3>;
+});
+
+const MIDI_IN_DEPTH: usize = 16;
+const SYSEX_IN_DEPTH: usize = 128;
+const SYSEX_OUT_DEPTH: usize = 2;
+const USB_MIDI_CIN_SYSEX: u8 = 0x4;
+const USB_MIDI_CIN_SYSEX_END_3: u8 = 0x7;
+const DIN_MIDI_BAUDRATE: u32 = 31_250;
+
+/*
+ * MIDI data received from the USB host and the DIN input, to be fed back
+ * into the device.
+ */
+pub static MIDI_IN: Channel<ThreadModeRawMutex, OutputData, MIDI_IN_DEPTH> = Channel::new();
+
+/*
+ * Bytes of SysEx messages received from the USB host and the ones to
+ * send back, for the configuration protocol and the feedback of presets.
+ */
+pub static SYSEX_IN: Channel<ThreadModeRawMutex, u8, SYSEX_IN_DEPTH> = Channel::new();
+pub static SYSEX_OUT: Channel<ThreadModeRawMutex, Message, SYSEX_OUT_DEPTH> = Channel::new();
+
 type USBDriver = Driver<'static, peripherals::USB>;
 type USBMidiClass = MidiClass<'static, USBDriver>;
+type DinRx = UartRx<'static, peripherals::USART3, peripherals::DMA1_CH3>;
 
 pub struct Stm32Backend {
-    addr: u8,
+    chain: Chain,
     out_a: Output<'static>,
     out_b: Output<'static>,
     out_c: Output<'static>,
@@ -38,29 +73,23 @@ pub struct Stm32Backend {
     adc_pin: peripherals::PA4,
     usb_builder: Option<Builder<'static, USBDriver>>,
     usb_midi_class: Option<USBMidiClass>,
+    din_rx: Option<DinRx>,
+    flash: Option<Stm32Flash>,
     spi: Spi<'static, peripherals::SPI1, NoDma, NoDma>,
     rclk: Output<'static>,
 }
 
 impl Backend for Stm32Backend {
-    async fn read_adc(&mut self) -> u16 {
-        self.set_addr();
+    async fn read_adc(&mut self, address: Address) -> u16 {
+        self.set_addr(address);
         self.flex_com.set_as_input(Pull::None);
-        let v = self.adc.read(&mut self.adc_pin).await;
-        self.next();
-        v
+        self.adc.read(&mut self.adc_pin).await
     }
 
-    fn read_input(&mut self) -> bool {
-        self.set_addr();
+    fn read_input(&mut self, address: Address) -> bool {
+        self.set_addr(address);
         self.flex_com.set_as_input(Pull::Up);
-        let level = self.flex_com.is_high();
-        self.next();
-        level
-    }
-
-    fn rewind(&mut self) {
-        self.addr = 0;
+        self.flex_com.is_high()
     }
 }
 
@@ -71,14 +100,86 @@ pub async fn usb_task(mut usb: embassy_usb::UsbDevice<'static, USBDriver>) -> !
 }
 
 #[embassy_executor::task(pool_size = 1)]
-pub async fn midi_task(mut class: USBMidiClass) -> ! {
-    class.wait_connection().await;
+pub async fn midi_task(class: USBMidiClass) -> ! {
+    let (mut sender, mut receiver) = class.split();
+    join(midi_tx(&mut sender), midi_rx(&mut receiver)).await;
+    unreachable!("midi task ended")
+}
+
+async fn midi_tx(sender: &mut Sender<'static, USBDriver>) -> ! {
+    let mut packetizer = UsbPacketizer::new();
+    loop {
+        sender.wait_connection().await;
+        info!("USB MIDI connected");
+        // don't send what was queued while the host was away
+        CHANNEL.clear();
+        loop {
+            let result = match select(CHANNEL.receive(), SYSEX_OUT.receive()).await {
+                Either::First(data) => write(sender, &mut packetizer, &data).await,
+                Either::Second(msg) => write(sender, &mut packetizer, &msg).await,
+            };
+            if let Err(EndpointError::Disabled) = result {
+                info!("USB MIDI disconnected");
+                CHANNEL.clear();
+                break;
+            }
+        }
+    }
+}
+
+/*
+ * Sends the bytes as USB MIDI event packets, SysEx spans several.
+ */
+async fn write(
+    sender: &mut Sender<'static, USBDriver>,
+    packetizer: &mut UsbPacketizer,
+    bytes: &[u8],
+) -> Result<(), EndpointError> {
+    for byte in bytes {
+        if let Some(packet) = packetizer.push(*byte) {
+            sender.write_packet(&packet).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn midi_rx(receiver: &mut Receiver<'static, USBDriver>) -> ! {
     let mut buf = [0; 64];
     loop {
-        if let data = CHANNEL.receive().await {
-            buf[0] = data[0] >> 4;
-            buf[1..4].copy_from_slice(&data);
-            class.write_packet(&buf[..4]).await;
+        receiver.wait_connection().await;
+        while let Ok(len) = receiver.read_packet(&mut buf).await {
+            for packet in buf[..len].chunks_exact(4) {
+                if let USB_MIDI_CIN_SYSEX..=USB_MIDI_CIN_SYSEX_END_3 = packet[0] & 0xf {
+                    let packet: &[u8; 4] = packet.try_into().unwrap();
+                    // a message missing bytes can't be parsed, hold the
+                    // host off until the device caught up instead
+                    for byte in usb_packet_bytes(packet) {
+                        SYSEX_IN.send(*byte).await;
+                    }
+                    continue;
+                }
+                if let Some(data) = from_usb_packet(packet) {
+                    // drop incoming data if the device doesn't keep up
+                    MIDI_IN.try_send(data).ok();
+                }
+            }
+        }
+    }
+}
+
+#[embassy_executor::task(pool_size = 1)]
+pub async fn din_task(mut rx: DinRx) -> ! {
+    let mut parser = MidiParser::new();
+    let mut byte = [0; 1];
+    loop {
+        // a framing or overrun error loses the byte, the parser resyncs
+        // on the next status byte
+        if rx.read(&mut byte).await.is_err() {
+            continue;
+        }
+        if let Some(data) = parser.parse(byte[0]) {
+            // drop incoming data if the device doesn't keep up
+            MIDI_IN.try_send(data).ok();
         }
     }
 }
@@ -155,10 +256,14 @@ impl Stm32Backend {
         let mut spi_config = SpiConfig::default();
         spi_config.frequency = Hertz(1_000_000);
 
+        let mut uart_config = UartConfig::default();
+        uart_config.baudrate = DIN_MIDI_BAUDRATE;
+        let din_rx = UartRx::new(p.USART3, UartIrqs, p.PB11, p.DMA1_CH3, uart_config).unwrap();
+
         let mut spi = Spi::new_txonly(p.SPI1, p.PA5, p.PA7, NoDma, NoDma, spi_config);
 
         Self {
-            addr: 0,
+            chain: Chain::default(),
             out_a: Output::new(p.PA0, Level::Low, Speed::Low),
             out_b: Output::new(p.PA1, Level::Low, Speed::Low),
             out_c: Output::new(p.PA2, Level::Low, Speed::Low),
@@ -167,6 +272,8 @@ impl Stm32Backend {
             adc_pin: p.PA4,
             usb_builder: Some(builder),
             usb_midi_class: Some(class),
+            din_rx: Some(din_rx),
+            flash: Some(Stm32Flash::new(Flash::new_blocking(p.FLASH))),
             spi: spi,
             rclk: Output::new(p.PB0, Level::Low, Speed::Low),
         }
@@ -178,20 +285,30 @@ impl Stm32Backend {
         }
     }
 
+    pub fn take_flash(&mut self) -> Option<Stm32Flash> {
+        self.flash.take()
+    }
+
     pub fn spawn_midi(&mut self, spawner: Spawner) {
         if let Some(m) = self.usb_midi_class.take() {
             spawner.spawn(midi_task(m)).unwrap();
         }
+        if let Some(rx) = self.din_rx.take() {
+            spawner.spawn(din_task(rx)).unwrap();
+        }
     }
 
-    fn next(&mut self) {
-        self.addr = self.addr + 1;
+    /*
+     * Boards to drive, has to match the config of the device. The serial
+     * output of the last register isn't wired back, so the chain length
+     * can't be detected.
+     */
+    pub fn set_chain(&mut self, chain: Chain) {
+        self.chain = chain;
     }
 
-    fn set_addr(&mut self) {
-        let mut buf = [0u8; 1];
-        buf[0] = self.addr;
-        self.spi.blocking_write(&buf);
+    fn set_addr(&mut self, address: Address) {
+        self.spi.blocking_write(&self.chain.frame(address));
         self.rclk.set_high();
         self.rclk.set_low();
     }
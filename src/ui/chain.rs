@@ -0,0 +1,161 @@
+use crate::ui::{Address, BOARD_SLOTS};
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+pub const CHAIN_BOARDS_MAX: usize = 8;
+
+/*
+ * Enables the mux output of a chained board, the lower bits select its
+ * line. A single board keeps its mux enabled and gets the bare line.
+ */
+pub const BOARD_ENABLE: u8 = 0x80;
+const DETECT_MARKER: u8 = 0xa5;
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Board {
+    // slots fitted on the board
+    #[serde(default = "Board::default_slots")]
+    #[cfg_attr(feature = "schema", schemars(range(min = 1, max = "BOARD_SLOTS")))]
+    pub slots: u8,
+}
+
+impl Board {
+    pub const fn new(slots: u8) -> Self {
+        Self { slots }
+    }
+
+    fn default_slots() -> u8 {
+        BOARD_SLOTS
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new(BOARD_SLOTS)
+    }
+}
+
+/*
+ * Daisy-chained boards, board 0 is the one wired to the microcontroller.
+ * Each board has a shift register selecting the line of its multiplexer,
+ * the registers are cascaded so selecting a line shifts one byte per
+ * board through the chain.
+ */
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(transparent)]
+pub struct Chain {
+    #[cfg_attr(feature = "schema", schemars(with = "std::vec::Vec<Board>"))]
+    boards: Vec<Board, CHAIN_BOARDS_MAX>,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        let mut boards = Vec::new();
+        boards.push(Board::default()).ok();
+        Self { boards }
+    }
+}
+
+impl Chain {
+    pub fn new() -> Self {
+        Self { boards: Vec::new() }
+    }
+
+    pub fn add_board(&mut self, board: Board) -> Result<(), Board> {
+        self.boards.push(board)
+    }
+
+    pub fn boards(&self) -> &[Board] {
+        &self.boards
+    }
+
+    pub fn len(&self) -> usize {
+        self.boards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.boards.is_empty()
+    }
+
+    /*
+     * Bytes to shift into the chain to select the line of the address.
+     * The byte for the last board comes first as it has to travel through
+     * all registers, all boards but the addressed one are disabled.
+     */
+    pub fn frame(&self, address: Address) -> Vec<u8, CHAIN_BOARDS_MAX> {
+        if self.boards.len() == 1 {
+            return Vec::from_slice(&[address.line()]).unwrap();
+        }
+        (0..self.boards.len())
+            .rev()
+            .map(|board| {
+                if board == address.board as usize {
+                    BOARD_ENABLE | address.line()
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
+    /*
+     * Counts the boards of a chain whose last register feeds back into
+     * the microcontroller. `transfer` shifts a byte in and returns the one
+     * shifted out at the end of the chain. Returns None if the marker
+     * never comes back, i.e. there is no feedback.
+     */
+    pub fn detect(mut transfer: impl FnMut(u8) -> u8) -> Option<usize> {
+        for _ in 0..CHAIN_BOARDS_MAX {
+            transfer(0);
+        }
+        transfer(DETECT_MARKER);
+        (1..=CHAIN_BOARDS_MAX).find(|_| transfer(0) == DETECT_MARKER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(slots: &[u8]) -> Chain {
+        let mut chain = Chain::new();
+        for s in slots {
+            chain.add_board(Board::new(*s)).unwrap();
+        }
+        chain
+    }
+
+    #[test]
+    fn frame_last_board_first() {
+        let chain = chain(&[8, 8, 4]);
+
+        assert_eq!(chain.frame(Address::new(0, 1, 2)), [0, 0, BOARD_ENABLE | 6]);
+        assert_eq!(chain.frame(Address::new(2, 0, 1)), [BOARD_ENABLE | 1, 0, 0]);
+    }
+
+    #[test]
+    fn frame_single_board() {
+        let chain = chain(&[8]);
+
+        assert_eq!(chain.frame(Address::new(0, 1, 2)), [6]);
+        assert_eq!(chain.frame(Address::new(0, 7, 3)), [31]);
+    }
+
+    #[test]
+    fn detect_chain_length() {
+        for len in 1..=CHAIN_BOARDS_MAX {
+            let mut registers = std::vec![0xffu8; len];
+            let transfer = |byte| {
+                registers.insert(0, byte);
+                registers.pop().unwrap()
+            };
+            assert_eq!(Chain::detect(transfer), Some(len));
+        }
+
+        // no feedback wired, the input reads its pull-up
+        assert_eq!(Chain::detect(|_| 0xff), None);
+    }
+}
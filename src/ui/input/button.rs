@@ -0,0 +1,60 @@
+use crate::handler::ButtonHandler;
+use crate::output::OutputData;
+use crate::ui::Input;
+use crate::ui::{Address, Backend};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Button {
+    pub address: Address,
+    #[serde(skip)]
+    pressed: bool,
+    pub handler: ButtonHandler,
+}
+
+impl Input for Button {
+    async fn update(&mut self, backend: &mut impl Backend) -> bool {
+        let pressed = backend.read_input(self.address);
+        if self.pressed == pressed {
+            return false;
+        }
+        self.pressed = pressed;
+        true
+    }
+}
+
+impl Button {
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            pressed: false,
+            handler: ButtonHandler::Dummy,
+        }
+    }
+
+    pub async fn init(&mut self, backend: &mut impl Backend) {
+        self.pressed = backend.read_input(self.address);
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    pub fn attach_handler(&mut self, handler: ButtonHandler) {
+        self.handler = handler;
+    }
+
+    pub fn receive(&mut self, _data: &OutputData) {}
+
+    pub fn run_handler(&mut self) -> OutputData {
+        let pressed = self.is_pressed();
+        match &mut self.handler {
+            ButtonHandler::Transport(h) => h.run(pressed),
+            ButtonHandler::MidiNote(h) => h.run(pressed),
+            ButtonHandler::Mcu(h) => h.run(pressed),
+            ButtonHandler::Dummy => OutputData::Dummy,
+        }
+    }
+}
@@ -1,12 +1,14 @@
 use crate::handler::EncoderHandler;
 use crate::output::OutputData;
-use crate::ui::Backend;
 use crate::ui::Input;
+use crate::ui::{Address, Backend};
 
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Encoder {
+    pub address: Address,
     #[serde(skip)]
     pattern: u8,
     pub handler: EncoderHandler,
@@ -34,8 +36,9 @@ pub enum EncoderDirection {
 }
 
 impl Encoder {
-    pub fn new() -> Self {
+    pub fn new(address: Address) -> Self {
         Self {
+            address,
             pattern: 0,
             handler: EncoderHandler::Dummy,
         }
@@ -49,18 +52,33 @@ impl Encoder {
         self.handler = handler;
     }
 
+    pub fn receive(&mut self, data: &OutputData) {
+        self.handler.receive(data);
+    }
+
     pub fn run_handler(&mut self) -> OutputData {
         let v = self.value();
         match &mut self.handler {
             EncoderHandler::MidiRel(h) => h.run(v),
             EncoderHandler::MidiAbs(h) => h.run(v),
+            EncoderHandler::Tempo(h) => h.run(v),
+            EncoderHandler::Modulation(h) => h.run(v),
+            EncoderHandler::VPot(h) => h.run(v),
             EncoderHandler::Dummy => OutputData::Dummy,
         }
     }
 
     fn read(&self, backend: &mut impl Backend) -> u8 {
-        let a: u8 = if backend.read_input() { 1 } else { 0 };
-        let b: u8 = if backend.read_input() { 1 } else { 0 };
+        let a: u8 = if backend.read_input(self.address) {
+            1
+        } else {
+            0
+        };
+        let b: u8 = if backend.read_input(self.address.offset(1)) {
+            1
+        } else {
+            0
+        };
 
         (a << 1) | b
     }
@@ -80,6 +98,8 @@ mod tests {
     //use crate::ui::backend::Backend;
     use crate::ui::backend::InMemoryBackend;
 
+    const ADDRESS: Address = Address::new(0, 2, 0);
+
     #[async_std::test]
     async fn encoder_turn_cw() -> std::io::Result<()> {
         let data_cw = [
@@ -111,12 +131,12 @@ mod tests {
         ];
 
         let mut b = InMemoryBackend::new();
-        b.set_input_buffer(&data_cw);
-
-        let mut encoder = Encoder::new();
+        let mut encoder = Encoder::new(ADDRESS);
+        b.set_inputs(ADDRESS, &data_cw[..2]);
         encoder.init(&mut b).await;
 
-        for _ in 0..5 {
+        for levels in data_cw[2..].chunks(2) {
+            b.set_inputs(ADDRESS, levels);
             assert!(encoder.update(&mut b).await);
             assert_eq!(encoder.value(), EncoderDirection::CW);
         }
@@ -154,12 +174,12 @@ mod tests {
         ];
 
         let mut b = InMemoryBackend::new();
-        b.set_input_buffer(&data_cw);
-
-        let mut encoder = Encoder::new();
+        let mut encoder = Encoder::new(ADDRESS);
+        b.set_inputs(ADDRESS, &data_cw[..2]);
         encoder.init(&mut b).await;
 
-        for _ in 0..4 {
+        for levels in data_cw[2..].chunks(2) {
+            b.set_inputs(ADDRESS, levels);
             assert!(encoder.update(&mut b).await);
             assert_eq!(encoder.value(), EncoderDirection::CCW);
         }
@@ -190,14 +210,15 @@ mod tests {
         ];
 
         let mut b = InMemoryBackend::new();
-        b.set_input_buffer(&data_cw);
-
-        let mut encoder = Encoder::new();
+        let mut encoder = Encoder::new(ADDRESS);
+        b.set_inputs(ADDRESS, &data_cw[..2]);
         encoder.init(&mut b).await;
 
+        b.set_inputs(ADDRESS, &data_cw[2..4]);
         assert!(encoder.update(&mut b).await);
         assert_eq!(encoder.value(), EncoderDirection::CCW);
 
+        b.set_inputs(ADDRESS, &data_cw[4..6]);
         assert!(encoder.update(&mut b).await);
         assert_eq!(encoder.value(), EncoderDirection::CW);
     }
@@ -214,13 +235,14 @@ mod tests {
         ];
 
         let mut b = InMemoryBackend::new();
-        b.set_input_buffer(&data_cw);
-
-        let mut encoder = Encoder::new();
+        let mut encoder = Encoder::new(ADDRESS);
+        b.set_inputs(ADDRESS, &data_cw[..2]);
         encoder.init(&mut b).await;
 
+        b.set_inputs(ADDRESS, &data_cw[2..4]);
         assert!(encoder.update(&mut b).await);
         assert_eq!(encoder.value(), EncoderDirection::CW);
+        b.set_inputs(ADDRESS, &data_cw[4..6]);
         assert!(!encoder.update(&mut b).await, "Returned update");
         assert_eq!(encoder.value(), EncoderDirection::CW);
     }
@@ -0,0 +1,326 @@
+use crate::clock::{ClockState, CLOCK_PPQN, MICROS_PER_MINUTE};
+#[cfg(feature = "schema")]
+use crate::config::MIDI_DATA_MAX;
+use crate::handler::GeneratorHandler;
+use crate::output::OutputData;
+use crate::ui::Backend;
+use crate::ui::Input;
+
+use serde::{Deserialize, Serialize};
+
+const MIDI_VALUE_MAX: i32 = 0x7f;
+const GENERATOR_INTERVAL_DEFAULT: u32 = 10_000;
+const GENERATOR_CENTER_DEFAULT: u8 = 64;
+const GENERATOR_WAVE_MAX: i32 = 127;
+const MICROS_PER_MILLI: u64 = 1000;
+
+// quarter wave of a sine with an amplitude of 127 in 64 steps
+const SINE_QUARTER: [u8; 65] = [
+    0, 3, 6, 9, 12, 16, 19, 22, 25, 28, 31, 34, 37, 40, 43, 46, 49, 51, 54, 57, 60, 63, 65, 68, 71,
+    73, 76, 78, 81, 83, 85, 88, 90, 92, 94, 96, 98, 100, 102, 104, 106, 107, 109, 111, 112, 113,
+    115, 116, 117, 118, 120, 121, 122, 122, 123, 124, 125, 125, 126, 126, 126, 127, 127, 127, 127,
+];
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    Square,
+    // a random value held for one period
+    SampleAndHold,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum GeneratorParam {
+    Period,
+    Depth,
+    // the CC the handler sends to
+    Target,
+}
+
+/*
+ * Changes a parameter of the generator input with the given index.
+ */
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct GeneratorControl {
+    pub input: usize,
+    pub param: GeneratorParam,
+    pub delta: i16,
+}
+
+/*
+ * Virtual input producing a periodic value from 0 to 127. The period is
+ * given in milliseconds or, if synced, in clocks at 24 ppqn of the clock
+ * the device follows. The value is swung by `depth` around `center`.
+ */
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Generator {
+    pub waveform: Waveform,
+    #[cfg_attr(feature = "schema", schemars(range(min = 1)))]
+    pub period: u16,
+    #[serde(default)]
+    pub sync: bool,
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
+    pub depth: u8,
+    #[serde(default = "Generator::default_center")]
+    #[cfg_attr(feature = "schema", schemars(range(max = "MIDI_DATA_MAX")))]
+    pub center: u8,
+    // minimum time between values in microseconds
+    #[serde(default = "Generator::default_interval")]
+    pub interval: u32,
+    #[serde(skip)]
+    phase: u32,
+    // rounding remainder of the phase in 1/period
+    #[serde(skip)]
+    carry: u64,
+    #[serde(skip)]
+    last: Option<u64>,
+    #[serde(skip)]
+    value: Option<u8>,
+    #[serde(skip)]
+    held: i32,
+    #[serde(skip, default = "Generator::default_seed")]
+    seed: u32,
+    pub handler: GeneratorHandler,
+}
+
+impl Input for Generator {
+    async fn update(&mut self, _backend: &mut impl Backend) -> bool {
+        // not backed by hardware, see `advance`
+        false
+    }
+}
+
+impl Generator {
+    pub fn new(waveform: Waveform, period: u16, depth: u8) -> Self {
+        Self {
+            waveform,
+            period,
+            sync: false,
+            depth,
+            center: Self::default_center(),
+            interval: Self::default_interval(),
+            phase: 0,
+            carry: 0,
+            last: None,
+            value: None,
+            held: 0,
+            seed: Self::default_seed(),
+            handler: GeneratorHandler::Dummy,
+        }
+    }
+
+    fn default_center() -> u8 {
+        GENERATOR_CENTER_DEFAULT
+    }
+
+    fn default_interval() -> u32 {
+        GENERATOR_INTERVAL_DEFAULT
+    }
+
+    fn default_seed() -> u32 {
+        0x2545_f491
+    }
+
+    pub fn attach_handler(&mut self, handler: GeneratorHandler) {
+        self.handler = handler;
+    }
+
+    pub fn receive(&mut self, _data: &OutputData) {}
+
+    pub fn value(&self) -> u8 {
+        self.value.unwrap_or(self.center)
+    }
+
+    pub fn control(&mut self, param: GeneratorParam, delta: i16) {
+        let apply = |v: i32, max: i32| (v + delta as i32).clamp(0, max);
+        match param {
+            GeneratorParam::Period => {
+                self.period = apply(self.period as i32, u16::MAX as i32) as u16
+            }
+            GeneratorParam::Depth => self.depth = apply(self.depth as i32, MIDI_VALUE_MAX) as u8,
+            GeneratorParam::Target => self.handler.retarget(delta),
+        }
+    }
+
+    /*
+     * Moves the generator forward to the given time in microseconds.
+     * Returns true if there is a new value to pass to the handler.
+     */
+    pub fn advance(&mut self, now: u64, clock: &ClockState) -> bool {
+        let Some(last) = self.last else {
+            self.last = Some(now);
+            self.held = self.random();
+            return self.set_value();
+        };
+        let elapsed = now.saturating_sub(last);
+        if elapsed < self.interval as u64 {
+            return false;
+        }
+        self.last = Some(now);
+
+        let period = self.period_us(clock).max(1);
+        let step = (((elapsed % period) as u128) << 32) + self.carry as u128;
+        self.carry = (step % period as u128) as u64;
+        let (phase, wrapped) = self.phase.overflowing_add((step / period as u128) as u32);
+        self.phase = phase;
+        if wrapped || elapsed >= period {
+            self.held = self.random();
+        }
+
+        self.set_value()
+    }
+
+    pub fn run_handler(&mut self) -> OutputData {
+        let v = self.value();
+        match &mut self.handler {
+            GeneratorHandler::MidiCc(h) => h.run(v),
+            GeneratorHandler::Dummy => OutputData::Dummy,
+        }
+    }
+
+    fn period_us(&self, clock: &ClockState) -> u64 {
+        let period = self.period as u64;
+        if self.sync {
+            period * MICROS_PER_MINUTE / (clock.bpm.max(1) as u64 * CLOCK_PPQN)
+        } else {
+            period * MICROS_PER_MILLI
+        }
+    }
+
+    fn set_value(&mut self) -> bool {
+        let wave = self.wave();
+        let value = self.center as i32 + wave * self.depth as i32 / (2 * GENERATOR_WAVE_MAX);
+        let value = Some(value.clamp(0, MIDI_VALUE_MAX) as u8);
+
+        if self.value == value {
+            return false;
+        }
+        self.value = value;
+        true
+    }
+
+    /*
+     * Current point of the waveform from -127 to 127.
+     */
+    fn wave(&self) -> i32 {
+        let x = (self.phase >> 24) as i32;
+        match self.waveform {
+            Waveform::Sine => {
+                let i = (x & 0x3f) as usize;
+                match x >> 6 {
+                    0 => SINE_QUARTER[i] as i32,
+                    1 => SINE_QUARTER[64 - i] as i32,
+                    2 => -(SINE_QUARTER[i] as i32),
+                    _ => -(SINE_QUARTER[64 - i] as i32),
+                }
+            }
+            Waveform::Triangle if x < 128 => -GENERATOR_WAVE_MAX + x * 2,
+            Waveform::Triangle => GENERATOR_WAVE_MAX - (x - 128) * 2,
+            Waveform::Square if x < 128 => GENERATOR_WAVE_MAX,
+            Waveform::Square => -GENERATOR_WAVE_MAX,
+            Waveform::SampleAndHold => self.held,
+        }
+    }
+
+    fn random(&mut self) -> i32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        (self.seed % (2 * GENERATOR_WAVE_MAX as u32 + 1)) as i32 - GENERATOR_WAVE_MAX
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(bpm: u16) -> ClockState {
+        ClockState {
+            bpm,
+            running: true,
+            song_position: 0,
+            external: false,
+        }
+    }
+
+    fn values(g: &mut Generator, step: u64, until: u64) -> std::vec::Vec<u8> {
+        let mut values = std::vec::Vec::new();
+        for i in 0..=until / step {
+            if g.advance(i * step, &clock(120)) {
+                values.push(g.value());
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn triangle() {
+        let mut g = Generator::new(Waveform::Triangle, 100, 127);
+        let v = values(&mut g, 10_000, 100_000);
+
+        assert_eq!(v.first(), Some(&1));
+        assert!(v[..6].windows(2).all(|w| w[0] < w[1]));
+        assert!(v[6..].windows(2).all(|w| w[0] > w[1]));
+        assert_eq!(v.iter().max(), Some(&127));
+    }
+
+    #[test]
+    fn sine() {
+        let mut g = Generator::new(Waveform::Sine, 1000, 127);
+        let v = values(&mut g, 10_000, 1_000_000);
+
+        assert_eq!(v[0], 64);
+        assert_eq!(v.iter().max(), Some(&127));
+        assert_eq!(v.iter().min(), Some(&1));
+    }
+
+    #[test]
+    fn square_depth() {
+        let mut g = Generator::new(Waveform::Square, 100, 127);
+        assert_eq!(values(&mut g, 10_000, 100_000), [127, 1, 127]);
+
+        let mut g = Generator::new(Waveform::Square, 100, 127);
+        g.control(GeneratorParam::Depth, -63);
+        assert_eq!(g.depth, 64);
+        assert_eq!(values(&mut g, 10_000, 100_000), [96, 32, 96]);
+    }
+
+    #[test]
+    fn sample_and_hold() {
+        let mut g = Generator::new(Waveform::SampleAndHold, 100, 127);
+        let v = values(&mut g, 10_000, 1_000_000);
+
+        // at most one new value per period
+        assert!(v.len() <= 11);
+        assert!(v.len() > 5);
+    }
+
+    #[test]
+    fn synced_period() {
+        let mut g = Generator::new(Waveform::Square, 24, 127);
+        g.sync = true;
+
+        // a beat at 120 BPM
+        assert!(g.advance(0, &clock(120)));
+        assert!(!g.advance(100_000, &clock(120)));
+        assert!(g.advance(250_000, &clock(120)));
+        assert!(g.advance(500_000, &clock(120)));
+        // half a beat at 240 BPM
+        assert!(g.advance(625_000, &clock(240)));
+        assert_eq!(g.value(), 1);
+    }
+
+    #[test]
+    fn interval() {
+        let mut g = Generator::new(Waveform::Triangle, 100, 127);
+        g.interval = 20_000;
+
+        assert!(g.advance(0, &clock(120)));
+        assert!(!g.advance(10_000, &clock(120)));
+        assert!(g.advance(20_000, &clock(120)));
+    }
+}
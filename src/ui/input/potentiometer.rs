@@ -1,12 +1,14 @@
 use crate::handler::PotentiometerHandler;
 use crate::output::OutputData;
-use crate::ui::Backend;
 use crate::ui::Input;
+use crate::ui::{Address, Backend};
 
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Potentiometer {
+    pub address: Address,
     #[serde(skip)]
     value: u8,
     pub handler: PotentiometerHandler,
@@ -14,7 +16,7 @@ pub struct Potentiometer {
 
 impl Input for Potentiometer {
     async fn update(&mut self, backend: &mut impl Backend) -> bool {
-        let data = backend.read_adc().await;
+        let data = backend.read_adc(self.address).await;
         let data = (data >> 5) as u8;
         if self.value != data {
             self.value = data;
@@ -25,8 +27,9 @@ impl Input for Potentiometer {
 }
 
 impl Potentiometer {
-    pub fn new() -> Self {
+    pub fn new(address: Address) -> Self {
         Self {
+            address,
             value: 0,
             handler: PotentiometerHandler::Dummy,
         }
@@ -34,6 +37,7 @@ impl Potentiometer {
 
     pub async fn init(&mut self, backend: &mut impl Backend) {
         self.update(backend).await;
+        self.handler.init(self.value);
     }
 
     fn value(&self) -> u8 {
@@ -44,10 +48,15 @@ impl Potentiometer {
         self.handler = handler;
     }
 
+    pub fn receive(&mut self, data: &OutputData) {
+        self.handler.receive(data);
+    }
+
     pub fn run_handler(&mut self) -> OutputData {
         let v = self.value();
         match &mut self.handler {
             PotentiometerHandler::MidiAbs(h) => h.run(v),
+            PotentiometerHandler::Fader(h) => h.run(v),
             PotentiometerHandler::Dummy => OutputData::Dummy,
         }
     }
@@ -60,14 +69,10 @@ mod tests {
 
     #[cfg(test)]
     async fn potentiometer_low_definition() {
-        let data: [u16; 1] = [1 << 12];
         let mut b = InMemoryBackend::new();
-        b.set_adc_buffer(&data);
+        b.set_adc(Address::new(0, 1, 0), 1 << 12);
 
-        let mut pot = Potentiometer {
-            value: 0,
-            handler: PotentiometerHandler::Dummy,
-        };
+        let mut pot = Potentiometer::new(Address::new(0, 1, 0));
         assert!(pot.update(&mut b).await);
         assert_eq!(pot.value(), 1 << 7);
     }
@@ -0,0 +1,72 @@
+use reset_ctrl::config::{ConfigErrorKind, BINARY_HEADER_LEN};
+use reset_ctrl::device::Device;
+
+const YAML: &str = "
+    boards:
+    - slots: 8
+    - slots: 4
+    inputs:
+    - !Encoder
+      address: {slot: 0}
+      handler: !MidiAbs
+        channel: 1
+        control: 4
+        value: 100
+    - !Potentiometer
+      address: {board: 1, slot: 2, pin: 3}
+      handler: !MidiAbs
+        channel: 0
+        control: 7
+        value: 0
+    - !Generator
+      waveform: Triangle
+      period: 24
+      sync: true
+      depth: 127
+      handler: !MidiCc
+        channel: 0
+        control: 1
+    routes:
+    - output: 0
+      filter:
+        messages: [Cc]
+      channel: 9
+    clock:
+      bpm: 132
+    notes:
+      processor: !Chord
+        intervals: [0, 4, 7]
+";
+
+#[test]
+fn yaml_to_binary() {
+    let device = Device::from_config(YAML).unwrap();
+    let data = device.to_binary_vec().unwrap();
+
+    assert!(data.len() < YAML.len() / 4);
+    assert_eq!(Device::from_binary(&data).unwrap(), device);
+
+    // the same bytes are written without allocating
+    let mut buf = [0; 256];
+    assert_eq!(device.to_binary(&mut buf).unwrap(), &data[..]);
+
+    let err = device.to_binary(&mut buf[..data.len() - 1]).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Capacity);
+}
+
+#[test]
+fn binary_is_validated() {
+    let device = Device::from_config(YAML).unwrap();
+    let mut data = device.to_binary_vec().unwrap();
+
+    // the channel of the first handler, out of range
+    let channel = data[BINARY_HEADER_LEN..]
+        .windows(3)
+        .position(|w| w == [1, 4, 100])
+        .unwrap();
+    data[BINARY_HEADER_LEN + channel] = 16;
+
+    let err = Device::from_binary(&data).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::OutOfRange);
+    assert_eq!(err.path, "inputs[0].handler.channel");
+}
@@ -0,0 +1,58 @@
+use reset_ctrl::device::{Device, DEVICE_INPUTS_MAX};
+use reset_ctrl::output::{MergeOut, MidiPort, OutputType};
+use reset_ctrl::ui::backend::InMemoryBackend;
+use reset_ctrl::ui::input::Encoder;
+use reset_ctrl::ui::{Address, InputType};
+
+use heapless::Vec;
+
+#[test]
+fn add_input_over_capacity() {
+    let encoder = |slot| InputType::Encoder(Encoder::new(Address::new(0, slot, 0)));
+
+    let mut device = Device::new();
+    for slot in 0..DEVICE_INPUTS_MAX as u8 {
+        assert!(device.add_input(encoder(slot)).is_ok());
+    }
+    assert_eq!(device.add_input(encoder(0)), Err(encoder(0)));
+
+    let mut device = Device::<16>::sized();
+    for slot in 0..16 {
+        assert!(device.add_input(encoder(slot)).is_ok());
+    }
+    assert!(device.add_input(encoder(0)).is_err());
+}
+
+#[async_std::test]
+async fn three_encoders() {
+    let mut yaml = String::from("inputs:\n");
+    for control in 1..=3 {
+        yaml += &format!(
+            "- !Encoder\n  address: {{slot: {}}}\n  handler: !MidiRel\n    \
+             channel: 0\n    control: {}\n",
+            control, control
+        );
+    }
+    let mut device = Device::from_config(&yaml).unwrap();
+
+    let mut b = InMemoryBackend::new();
+
+    let mut outputs: Vec<OutputType, 1> = Vec::new();
+    outputs
+        .push(OutputType::MergeOut(MergeOut::new(MidiPort::Usb, false)))
+        .ok();
+
+    device.init_inputs(&mut b).await;
+    // all three encoders turned clockwise
+    for slot in 1..=3 {
+        b.set_input(Address::new(0, slot, 0), true);
+    }
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+
+    let sent: std::vec::Vec<u8> = match &outputs[0] {
+        OutputType::MergeOut(o) => std::iter::from_fn(|| o.pop()).collect(),
+        _ => unreachable!(),
+    };
+    assert_eq!(sent, [0xb0, 3, 63, 0xb0, 2, 63, 0xb0, 1, 63]);
+}
@@ -0,0 +1,105 @@
+use reset_ctrl::config::ConfigErrorKind;
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{MergeOut, MidiPort, OutputType};
+use reset_ctrl::ui::backend::SimulatedBackend;
+use reset_ctrl::ui::Address;
+
+use heapless::Vec;
+
+#[async_std::test]
+async fn inputs_on_chained_boards() {
+    let yaml = "
+        boards:
+        - slots: 8
+        - slots: 4
+        inputs:
+        - !Button
+          address: {slot: 3}
+          handler: !MidiNote
+            channel: 0
+            key: 60
+            velocity: 100
+        - !Encoder
+          address: {board: 1, slot: 3}
+          handler: !MidiRel
+            channel: 0
+            control: 4
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = SimulatedBackend::new(2, device.boards().clone());
+    assert!(device.detect_boards(&mut b).is_ok());
+
+    let mut outputs: Vec<OutputType, 1> = Vec::new();
+    outputs
+        .push(OutputType::MergeOut(MergeOut::new(MidiPort::Usb, false)))
+        .ok();
+
+    device.init_inputs(&mut b).await;
+    // only the encoder on the second board turned clockwise
+    assert!(!device.update(&mut b).await);
+    b.set_input(Address::new(1, 3, 0), true);
+    assert!(device.update(&mut b).await);
+    device.run_handler(&outputs).await;
+
+    let sent: std::vec::Vec<u8> = match &outputs[0] {
+        OutputType::MergeOut(o) => std::iter::from_fn(|| o.pop()).collect(),
+        _ => unreachable!(),
+    };
+    assert_eq!(sent, [0xb0, 4, 63]);
+}
+
+#[test]
+fn missing_boards() {
+    let yaml = "
+        boards:
+        - slots: 8
+        - slots: 8
+        - slots: 8
+        inputs: []
+    ";
+
+    let device = Device::from_config(yaml).unwrap();
+    let mut b = SimulatedBackend::new(2, device.boards().clone());
+
+    let err = device.detect_boards(&mut b).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Reference);
+    assert_eq!(err.message, "3 boards configured but only 2 found");
+}
+
+#[test]
+fn address_outside_chain() {
+    let yaml = "
+        inputs:
+        - !Button
+          address: {board: 1, slot: 0}
+          handler: !Dummy
+    ";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Reference);
+    assert_eq!(err.path, "inputs[0].address.board");
+
+    let yaml = "
+        boards:
+        - slots: 4
+        inputs:
+        - !Button
+          address: {slot: 4}
+          handler: !Dummy
+    ";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::OutOfRange);
+    assert_eq!(err.path, "inputs[0].address.slot");
+    assert_eq!(err.message, "slot 4 is not within 0 to 3");
+
+    let yaml = "
+        boards:
+        - slots: 9
+        inputs: []
+    ";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.path, "boards[0].slots");
+}
@@ -0,0 +1,234 @@
+use reset_ctrl::device::Device;
+use reset_ctrl::output::LogEntry;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const CONFIG: &str = "
+boards:
+- slots: 4
+inputs:
+- !Encoder
+  address: {slot: 0}
+  handler: !MidiRel
+    channel: 2
+    control: 4
+- !Button
+  address: {slot: 1}
+  handler: !MidiNote
+    channel: 2
+    key: 60
+    velocity: 100
+- !Potentiometer
+  address: {slot: 2}
+  handler: !Dummy
+";
+
+/*
+ * Path in a directory of its own for the test.
+ */
+fn path(test: &str, name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("reset_ctrl-cli-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_reset_ctrl"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn validate() {
+    let config = path("validate", "config.yaml");
+    fs::write(&config, CONFIG).unwrap();
+    let output = run(&["validate", config.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    fs::write(
+        &config,
+        CONFIG.replace("channel: 2\n    key", "channel: 20\n    key"),
+    )
+    .unwrap();
+    let output = run(&["validate", config.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let err = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        err.trim(),
+        "error: inputs[1].handler.channel: channel 20 is not within 0 to 15"
+    );
+}
+
+#[test]
+fn convert_round_trip() {
+    let yaml = path("convert", "config.yaml");
+    let json = path("convert", "config.json");
+    let binary = path("convert", "config.bin");
+    let back = path("convert", "back.yaml");
+    fs::write(&yaml, CONFIG).unwrap();
+
+    for (from, to) in [(&yaml, &json), (&json, &binary), (&binary, &back)] {
+        let output = run(&["convert", from.to_str().unwrap(), to.to_str().unwrap()]);
+        assert!(output.status.success(), "{:?}", output);
+    }
+
+    let json = fs::read_to_string(&json).unwrap();
+    assert!(json.contains("\"MidiNote\""));
+    assert_eq!(
+        Device::from_config(&fs::read_to_string(&back).unwrap()).unwrap(),
+        Device::from_config(CONFIG).unwrap()
+    );
+
+    // a format given explicitly wins over the extension
+    let output = run(&["convert", binary.to_str().unwrap(), "-", "--from", "json"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn dump() {
+    let config = path("dump", "config.yaml");
+    fs::write(&config, CONFIG).unwrap();
+    let output = run(&["dump", config.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let lines: Vec<String> = stdout(&output)
+        .lines()
+        .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "input type address handler message",
+            "0 Encoder 0.0.0 MidiRel CC 4 on channel 2",
+            "1 Button 0.1.0 MidiNote Note 60 on channel 2",
+            "2 Potentiometer 0.2.0 Dummy -",
+        ]
+    );
+
+    // the extension doesn't tell the format
+    let binary = path("dump", "config.dat");
+    fs::write(
+        &binary,
+        Device::from_config(CONFIG)
+            .unwrap()
+            .to_binary_vec()
+            .unwrap(),
+    )
+    .unwrap();
+    let output = run(&["dump", binary.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let output = run(&["dump", binary.to_str().unwrap(), "--format", "binary"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(stdout(&output).lines().count(), 4);
+}
+
+#[test]
+fn simulate() {
+    let config = path("simulate", "config.yaml");
+    let script = path("simulate", "script.txt");
+    fs::write(&config, CONFIG).unwrap();
+    fs::write(
+        &script,
+        "
+# one step clockwise
+set 0.0.0 1
+update
+set 0.0.1 1
+update
+# press the button
+set 0.1.0 1
+update
+",
+    )
+    .unwrap();
+
+    let output = run(&[
+        "simulate",
+        config.to_str().unwrap(),
+        script.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let log: Vec<LogEntry> = stdout(&output)
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    let messages: Vec<(&str, &[u8])> = log
+        .iter()
+        .map(|e| (e.handler.as_str(), &e.bytes[..]))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            ("MidiRel", &[0xb2, 4, 63][..]),
+            ("MidiRel", &[0xb2, 4, 63][..]),
+            ("MidiNote", &[0x92, 60, 100][..]),
+        ]
+    );
+
+    fs::write(&script, "set 0.0 1\n").unwrap();
+    let output = run(&[
+        "simulate",
+        config.to_str().unwrap(),
+        script.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    let err = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(err.trim(), "error: line 1: invalid step `set 0.0 1`");
+}
+
+#[test]
+fn simulate_ticks() {
+    let config = path("simulate_ticks", "config.yaml");
+    let script = path("simulate_ticks", "script.txt");
+    fs::write(
+        &config,
+        "
+inputs:
+- !Generator
+  waveform: Square
+  period: 100
+  depth: 60
+  handler: !MidiCc
+    channel: 1
+    control: 7
+",
+    )
+    .unwrap();
+    fs::write(&script, "tick 0\ntick 50000\ntick 50000\n").unwrap();
+
+    let output = run(&[
+        "simulate",
+        config.to_str().unwrap(),
+        script.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let log: Vec<LogEntry> = stdout(&output)
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    let values: Vec<u8> = log.iter().map(|e| e.bytes[2]).collect();
+    // square wave flipping every half period
+    assert!(log.iter().all(|e| e.bytes[..2] == [0xb1, 7]));
+    assert_eq!(values.len(), 3, "{:?}", values);
+    assert_ne!(values[0], values[1]);
+    assert_ne!(values[1], values[2]);
+}
+
+#[cfg(feature = "schema")]
+#[test]
+fn schema() {
+    let output = run(&["schema"]);
+    assert!(output.status.success());
+    let schema: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(
+        schema,
+        serde_json::to_value(reset_ctrl::config::schema()).unwrap()
+    );
+}
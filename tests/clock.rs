@@ -0,0 +1,63 @@
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{MergeOut, MidiPort, OutputType};
+use reset_ctrl::time::{FakeTimer, Timer};
+use reset_ctrl::ui::backend::InMemoryBackend;
+use reset_ctrl::ui::Address;
+
+use heapless::Vec;
+
+fn sent(outputs: &[OutputType]) -> std::vec::Vec<u8> {
+    match &outputs[0] {
+        OutputType::MergeOut(o) => std::iter::from_fn(|| o.pop()).collect(),
+        _ => unreachable!(),
+    }
+}
+
+#[async_std::test]
+async fn transport_and_tempo() {
+    let yaml = "
+        inputs:
+        - !Button
+          address: {slot: 0}
+          handler: !Transport
+            control: Toggle
+        - !Encoder
+          address: {slot: 1}
+          handler: !Tempo
+            step: 10
+        clock:
+          bpm: 110
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+
+    let mut outputs: Vec<OutputType, 1> = Vec::new();
+    outputs
+        .push(OutputType::MergeOut(MergeOut::new(MidiPort::Usb, false)))
+        .ok();
+
+    let mut timer = FakeTimer::new(1000);
+
+    device.init_inputs(&mut b).await;
+    device.run_clock(&outputs, &mut timer).await;
+    assert_eq!(timer.now(), 1000);
+    assert!(sent(&outputs).is_empty());
+
+    // button pressed and encoder turned clockwise
+    b.set_input(Address::new(0, 0, 0), true);
+    b.set_input(Address::new(0, 1, 0), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+    assert!(device.clock().is_running());
+    assert_eq!(device.clock().bpm(), 120);
+    assert_eq!(sent(&outputs), [0xfa]);
+
+    for _ in 0..25 {
+        device.run_clock(&outputs, &mut timer).await;
+    }
+    assert_eq!(sent(&outputs), [0xf8; 25]);
+    // one beat at 120 BPM after the first clock
+    assert_eq!(timer.now(), 1000 + 500_000);
+    assert_eq!(device.clock().song_position(), 4);
+}
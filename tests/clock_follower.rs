@@ -0,0 +1,112 @@
+use reset_ctrl::clock::ClockControl;
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{MergeOut, MidiParser, MidiPort, OutputType};
+use reset_ctrl::time::{FakeTimer, Timer};
+
+use heapless::Vec;
+
+#[async_std::test]
+async fn follow_and_fall_back() {
+    let yaml = "
+        inputs: []
+        clock:
+          bpm: 90
+        clock_follower:
+          timeout: 100000
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut parser = MidiParser::new();
+
+    let mut outputs: Vec<OutputType, 1> = Vec::new();
+    outputs
+        .push(OutputType::MergeOut(MergeOut::new(MidiPort::Usb, false)))
+        .ok();
+    let sent = |outputs: &[OutputType]| match &outputs[0] {
+        OutputType::MergeOut(o) => std::iter::from_fn(|| o.pop()).count(),
+        _ => unreachable!(),
+    };
+
+    let mut timer = FakeTimer::new(0);
+
+    // external clock at 150 BPM, starting from the second bar
+    let mut stream = std::vec![0xf2, 16, 0, 0xfb];
+    stream.extend([0xf8; 48]);
+    for byte in stream {
+        if let Some(data) = parser.parse(byte) {
+            device.receive_at(&data, timer.now());
+        }
+        if byte == 0xf8 {
+            timer.advance(16_667);
+        }
+        device.tick(&outputs, timer.now()).await;
+    }
+
+    let state = device.clock_state(timer.now());
+    assert!(state.external);
+    assert!(state.running);
+    assert_eq!(state.bpm, 150);
+    assert_eq!(state.song_position, 24);
+    // the internal clock kept quiet
+    assert_eq!(sent(&outputs), 0);
+
+    timer.advance(100_000);
+    device.run_clock(&outputs, &mut timer).await;
+    let state = device.clock_state(timer.now());
+    assert!(!state.external);
+    assert!(state.running);
+    assert_eq!(state.bpm, 150);
+    assert_eq!(sent(&outputs), 1);
+
+    // the internal clock goes on at the followed tempo
+    for _ in 0..24 {
+        device.run_clock(&outputs, &mut timer).await;
+    }
+    assert_eq!(sent(&outputs), 24);
+    assert_eq!(device.clock_state(timer.now()).song_position, 28);
+}
+
+#[async_std::test]
+async fn deadline_while_following() {
+    let yaml = "
+        inputs: []
+        clock:
+          bpm: 120
+        clock_follower:
+          timeout: 100000
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let outputs: Vec<OutputType, 1> = Vec::new();
+    let mut timer = FakeTimer::new(0);
+
+    // the deadline always lies ahead once the clock due was sent
+    let ahead = |device: &Device, now: u64| device.deadline(now).is_some_and(|d| d > now);
+
+    device.clock_mut().control(ClockControl::Start);
+    for _ in 0..4 {
+        device.run_clock(&outputs, &mut timer).await;
+        assert!(ahead(&device, timer.now()));
+    }
+
+    // the internal clock is idle, only the timeout of the external one is due
+    let mut parser = MidiParser::new();
+    for byte in std::iter::once(0xfa).chain([0xf8; 8]) {
+        timer.advance(10_000);
+        let data = parser.parse(byte).unwrap();
+        device.receive_at(&data, timer.now());
+        device.tick(&outputs, timer.now()).await;
+        assert!(ahead(&device, timer.now()));
+    }
+    let last = timer.now();
+    assert_eq!(device.deadline(last), Some(last + 100_000));
+
+    // waits for the timeout and falls back to the internal clock
+    device.run_clock(&outputs, &mut timer).await;
+    assert_eq!(timer.now(), last + 100_000);
+    assert!(!device.clock_state(timer.now()).external);
+    for _ in 0..4 {
+        device.run_clock(&outputs, &mut timer).await;
+        assert!(ahead(&device, timer.now()));
+    }
+}
@@ -0,0 +1,48 @@
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{read_log, JsonOut, OutputType};
+use reset_ctrl::ui::backend::InMemoryBackend;
+use reset_ctrl::ui::Address;
+
+use heapless::Vec;
+use std::fs::File;
+use std::io::BufReader;
+
+#[async_std::test]
+async fn coalesce_fader_moves() {
+    let yaml = "
+        inputs:
+        - !Potentiometer
+          address: {slot: 0}
+          handler: !MidiAbs
+            channel: 0
+            control: 7
+            value: 0
+        coalesce_window: 1000000
+    ";
+    let path =
+        std::env::temp_dir().join(format!("reset_ctrl-coalesce-{}.jsonl", std::process::id()));
+    let read = || read_log(BufReader::new(File::open(&path).unwrap())).unwrap();
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+
+    let mut outputs: Vec<OutputType, 1> = Vec::new();
+    outputs
+        .push(OutputType::JsonOut(JsonOut::create(&path).unwrap()))
+        .ok();
+
+    device.init_inputs(&mut b).await;
+    for value in [10 << 5, 20 << 5, 30 << 5] {
+        b.set_adc(Address::new(0, 0, 0), value);
+        device.update(&mut b).await;
+        device.run_handler(&outputs).await;
+    }
+    assert!(read().is_empty());
+
+    device.flush(&outputs).await;
+    let log = read();
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].bytes, [0xb0, 7, 30]);
+}
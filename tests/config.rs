@@ -0,0 +1,255 @@
+use reset_ctrl::config::ConfigErrorKind;
+use reset_ctrl::device::Device;
+
+#[test]
+fn syntax_error_location() {
+    let yaml = "
+inputs:
+- !Encoder
+  address: {slot: 0}
+  handler: !MidiAbs
+    channel: 300
+    control: 4
+    value: 0
+";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Syntax);
+    assert_eq!((err.line, err.column), (Some(6), Some(14)));
+    assert_eq!(err.input, Some(0));
+    assert_eq!(err.path, "inputs[0].handler.channel");
+    assert_eq!(
+        err.to_string(),
+        "line 6 column 14: inputs[0].handler.channel: \
+         invalid value: integer `300`, expected u8"
+    );
+}
+
+#[test]
+fn json_error_location() {
+    let json = r#"{
+  "inputs": [
+    {"Encoder": {"address": {"slot": 0}, "handler": {"MidiRel": {"channel": 300, "control": 4}}}}
+  ]
+}"#;
+
+    let err = Device::from_json(json).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Syntax);
+    assert_eq!(err.line, Some(3));
+    assert_eq!(err.input, Some(0));
+    assert_eq!(err.path, "inputs[0].handler.channel");
+    assert!(err.message.starts_with("invalid value: integer `300`"));
+}
+
+#[test]
+fn unknown_field() {
+    let yaml = "
+inputs:
+- !Button
+  address: {slot: 0}
+  handler: !Dummy
+- !Encoder
+  address: {slot: 1}
+  handler: !MidiRel
+    channel: 0
+    contrl: 4
+";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Syntax);
+    assert_eq!(err.input, Some(1));
+    assert_eq!(err.path, "inputs[1].handler");
+    assert!(err.message.starts_with("missing field `control`"));
+}
+
+#[test]
+fn out_of_range() {
+    let yaml = "
+inputs:
+- !Encoder
+  address: {slot: 0}
+  handler: !MidiRel
+    channel: 20
+    control: 4
+";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::OutOfRange);
+    assert_eq!((err.line, err.column), (None, None));
+    assert_eq!(err.input, Some(0));
+    assert_eq!(err.path, "inputs[0].handler.channel");
+
+    let yaml = "
+inputs:
+- !Potentiometer
+  address: {slot: 0}
+  handler: !MidiAbs
+    channel: 0
+    control: 200
+    value: 0
+";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::OutOfRange);
+    assert_eq!(err.path, "inputs[0].handler.control");
+    assert_eq!(
+        err.to_string(),
+        "inputs[0].handler.control: value 200 is not within 0 to 127"
+    );
+
+    let err = Device::from_config("inputs: []\nclock: {bpm: 1000}\n").unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::OutOfRange);
+    assert_eq!(err.path, "clock.bpm");
+    assert_eq!(
+        err.to_string(),
+        "clock.bpm: 1000 BPM are not within 20 to 300"
+    );
+}
+
+#[test]
+fn duplicate_mapping() {
+    let yaml = "
+inputs:
+- !Encoder
+  address: {slot: 0}
+  handler: !MidiRel
+    channel: 1
+    control: 4
+- !Potentiometer
+  address: {slot: 1}
+  handler: !MidiAbs
+    channel: 1
+    control: 4
+    value: 0
+";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Duplicate);
+    assert_eq!(err.input, Some(1));
+    assert_eq!(err.message, "sends the same message as input 0");
+}
+
+#[test]
+fn preset_after_user_values() {
+    let yaml = "
+inputs:
+- !Encoder
+  address: {slot: 0}
+  handler: !MidiRel
+    channel: 16
+    control: 4
+preset: !Mcu
+  buttons: []
+";
+
+    // the values written are checked, even if the preset replaces them
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::OutOfRange);
+    assert_eq!(err.path, "inputs[0].handler.channel");
+}
+
+#[test]
+fn capacities_and_references() {
+    let yaml = "
+inputs:
+- !Encoder
+  address: {slot: 0}
+  handler: !Dummy
+- !Encoder
+  address: {slot: 1}
+  handler: !Dummy
+- !Encoder
+  address: {slot: 2}
+  handler: !Dummy
+";
+
+    let err = Device::<2>::from_config_sized(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Capacity);
+    assert_eq!(err.path, "inputs");
+    assert!(Device::from_config(yaml).is_ok());
+
+    let yaml = "
+inputs: []
+routes:
+- output: 8
+";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Capacity);
+    assert_eq!(err.path, "routes[0].output");
+
+    let yaml = "
+inputs:
+- !Encoder
+  address: {slot: 0}
+  handler: !Modulation
+    input: 1
+    param: Depth
+    step: 1
+";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Reference);
+    assert_eq!(err.path, "inputs[0].handler.input");
+
+    let yaml = "
+inputs: []
+sequencer:
+  tracks:
+  - channel: 0
+    steps:
+    - note: 60
+    length: 2
+";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Capacity);
+    assert_eq!(err.path, "sequencer.tracks[0].length");
+}
+
+#[test]
+fn mux_lines() {
+    let yaml = "
+boards:
+- slots: 8
+- slots: 8
+inputs:
+- !Encoder
+  address: {slot: 2, pin: 1}
+  handler: !Dummy
+- !Button
+  address: {board: 1, slot: 2, pin: 1}
+  handler: !Dummy
+- !Potentiometer
+  address: {slot: 2, pin: 2}
+  handler: !Dummy
+";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Duplicate);
+    assert_eq!(err.input, Some(2));
+    assert_eq!(err.path, "inputs[2].address");
+    assert_eq!(err.message, "uses the same mux lines as input 0");
+
+    let yaml = "
+inputs:
+- !Encoder
+  address: {slot: 0, pin: 3}
+  handler: !Dummy
+";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::OutOfRange);
+    assert_eq!(err.path, "inputs[0].address.pin");
+
+    let yaml = "
+inputs:
+- !Button
+  address: {slot: 8}
+  handler: !Dummy
+";
+
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::OutOfRange);
+    assert_eq!(err.path, "inputs[0].address.slot");
+}
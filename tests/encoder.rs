@@ -1,26 +1,25 @@
 use reset_ctrl::device::Device;
 use reset_ctrl::handler::{EncoderHandler, MidiRel};
-use reset_ctrl::output::{MidiMsgCc, OutputData, OutputType, StdOut};
+use reset_ctrl::output::{MidiMsgCc, MidiParser, OutputData, OutputType, StdOut};
 use reset_ctrl::ui::backend::InMemoryBackend;
 use reset_ctrl::ui::input::{Encoder, EncoderDirection};
-use reset_ctrl::ui::{Input, InputType};
+use reset_ctrl::ui::{Address, Input, InputType};
 
 use heapless::Vec;
 use serde::{Deserialize, Serialize};
 
 #[async_std::test]
 async fn cw_to_midi() {
-    let data_cw = [false, false, true, false, true, true];
     let mut b = InMemoryBackend::new();
-    b.set_input_buffer(&data_cw);
 
-    let mut encoder = Encoder::new();
+    let mut encoder = Encoder::new(Address::new(0, 0, 0));
     encoder.init(&mut b).await;
     let mut handler = EncoderHandler::MidiRel(MidiRel {
         channel: 0,
         control: 4,
     });
     encoder.attach_handler(handler);
+    b.set_input(Address::new(0, 0, 0), true);
     assert!(encoder.update(&mut b).await);
     assert_eq!(encoder.value(), EncoderDirection::CW);
 
@@ -38,12 +37,10 @@ async fn cw_to_midi() {
 
 #[test]
 fn device() {
-    let data_cw = [false, false, true, false, true, true];
-
     let mut b = InMemoryBackend::new();
-    b.set_input_buffer(&data_cw);
+    b.set_input(Address::new(0, 0, 0), true);
 
-    let mut encoder = Encoder::new();
+    let mut encoder = Encoder::new(Address::new(0, 0, 0));
     let mut handler = EncoderHandler::MidiRel(MidiRel {
         channel: 0,
         control: 4,
@@ -57,7 +54,7 @@ fn device() {
     outputs.push(OutputType::StdOut(StdOut {}));
 
     // setup
-    device.add_input(input);
+    device.add_input(input).expect("no input slot left");
     device.init_inputs(&mut b);
 
     // operation
@@ -72,16 +69,15 @@ fn load_from_config() {
     let yaml = "
         inputs:
         - !Encoder
+          address: {slot: 0}
           handler: !MidiRel
             channel: 0
             control: 4
     ";
 
-    let mut device = Device::from_config(&yaml);
-    let data_cw = [false, false, true, false, true, true];
-
+    let mut device = Device::from_config(&yaml).unwrap();
     let mut b = InMemoryBackend::new();
-    b.set_input_buffer(&data_cw);
+    b.set_input(Address::new(0, 0, 0), true);
 
     let mut outputs: Vec<OutputType, 1> = Vec::new();
     outputs.push(OutputType::StdOut(StdOut {}));
@@ -93,3 +89,37 @@ fn load_from_config() {
     device.update(&mut b);
     device.run_handler(&outputs);
 }
+
+#[test]
+fn feedback_from_host() {
+    let yaml = "
+        inputs:
+        - !Encoder
+          address: {slot: 0}
+          handler: !MidiAbs
+            channel: 1
+            control: 4
+            value: 0
+    ";
+    let expected = "
+        inputs:
+        - !Encoder
+          address: {slot: 0}
+          handler: !MidiAbs
+            channel: 1
+            control: 4
+            value: 100
+    ";
+
+    let mut device = Device::from_config(&yaml).unwrap();
+    let mut parser = MidiParser::new();
+
+    // running status, only the matching controller is taken over
+    for byte in [0xb1, 5, 23, 4, 100] {
+        if let Some(data) = parser.parse(byte) {
+            device.receive(&data);
+        }
+    }
+
+    assert_eq!(device, Device::from_config(&expected).unwrap());
+}
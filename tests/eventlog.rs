@@ -0,0 +1,51 @@
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{read_log, JsonOut, OutputType};
+use reset_ctrl::ui::backend::InMemoryBackend;
+use reset_ctrl::ui::Address;
+
+use heapless::Vec;
+use std::fs::File;
+use std::io::BufReader;
+
+#[async_std::test]
+async fn device_to_event_log() {
+    let yaml = "
+        inputs:
+        - !Encoder
+          address: {slot: 0}
+          handler: !MidiRel
+            channel: 2
+            control: 4
+    ";
+    let path = std::env::temp_dir().join(format!("reset_ctrl-{}.jsonl", std::process::id()));
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+
+    let mut outputs: Vec<OutputType, 1> = Vec::new();
+    outputs
+        .push(OutputType::JsonOut(JsonOut::create(&path).unwrap()))
+        .ok();
+
+    device.init_inputs(&mut b).await;
+    // two steps clockwise
+    b.set_input(Address::new(0, 0, 0), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+    b.set_input(Address::new(0, 0, 1), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+
+    let log = read_log(BufReader::new(File::open(&path).unwrap())).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(log.len(), 2);
+    for entry in &log {
+        assert_eq!(entry.input, 0);
+        assert_eq!(entry.input_type, "Encoder");
+        assert_eq!(entry.handler, "MidiRel");
+        assert_eq!(entry.message, "MidiMsgCc");
+        assert_eq!(entry.bytes, [0xb2, 4, 63]);
+    }
+    assert!(log[0].timestamp <= log[1].timestamp);
+}
@@ -0,0 +1,84 @@
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{MergeOut, MidiParser, MidiPort, OutputData, OutputType};
+use reset_ctrl::time::{FakeTimer, Timer};
+use reset_ctrl::ui::backend::InMemoryBackend;
+use reset_ctrl::ui::Address;
+
+use heapless::Vec;
+
+fn sent(outputs: &[OutputType]) -> std::vec::Vec<OutputData> {
+    let mut parser = MidiParser::new();
+    match &outputs[0] {
+        OutputType::MergeOut(o) => std::iter::from_fn(|| o.pop())
+            .filter_map(|b| parser.parse(b))
+            .collect(),
+        _ => unreachable!(),
+    }
+}
+
+fn cc_values(data: &[OutputData], control: u8) -> std::vec::Vec<u8> {
+    data.iter()
+        .filter_map(|d| match d {
+            OutputData::MidiMsgCc(m) if m.control == control => Some(m.value),
+            _ => None,
+        })
+        .collect()
+}
+
+#[async_std::test]
+async fn lfo_controlled_by_encoders() {
+    let yaml = "
+        inputs:
+        - !Generator
+          waveform: Triangle
+          period: 24
+          sync: true
+          depth: 127
+          handler: !MidiCc
+            channel: 0
+            control: 1
+        - !Encoder
+          address: {slot: 0}
+          handler: !Modulation
+            input: 0
+            param: Target
+            step: 1
+        clock:
+          bpm: 120
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+
+    let mut outputs: Vec<OutputType, 1> = Vec::new();
+    outputs
+        .push(OutputType::MergeOut(MergeOut::new(MidiPort::Usb, false)))
+        .ok();
+
+    let mut timer = FakeTimer::new(0);
+    device.init_inputs(&mut b).await;
+
+    // one beat at 120 BPM
+    let mut values = std::vec::Vec::new();
+    while timer.now() <= 500_000 {
+        device.tick(&outputs, timer.now()).await;
+        device.run_handler(&outputs).await;
+        values.extend(cc_values(&sent(&outputs), 1));
+        timer.advance(10_000);
+    }
+    assert_eq!(values.len(), 51);
+    assert_eq!(values.iter().min(), Some(&1));
+    assert_eq!(values.iter().max(), Some(&127));
+    assert_eq!(values.last(), Some(&1));
+
+    // the encoder turns clockwise
+    b.set_input(Address::new(0, 0, 0), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+    device.tick(&outputs, timer.now()).await;
+    device.run_handler(&outputs).await;
+
+    let sent = sent(&outputs);
+    assert!(cc_values(&sent, 1).is_empty());
+    assert_eq!(cc_values(&sent, 2).len(), 1);
+}
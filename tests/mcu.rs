@@ -0,0 +1,109 @@
+use reset_ctrl::config::ConfigErrorKind;
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{MergeOut, MidiMsgCc, MidiPort, OutputData, OutputType};
+use reset_ctrl::preset::{Preset, Ring, RingMode};
+use reset_ctrl::ui::backend::InMemoryBackend;
+use reset_ctrl::ui::Address;
+
+use heapless::Vec;
+
+#[async_std::test]
+async fn mcu_preset() {
+    let yaml = "
+        inputs:
+        - !Encoder
+          address: {slot: 0}
+          handler: !Dummy
+        - !Button
+          address: {slot: 1}
+          handler: !Dummy
+        preset: !Mcu
+          buttons: [Play]
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+
+    let mut outputs: Vec<OutputType, 1> = Vec::new();
+    outputs
+        .push(OutputType::MergeOut(MergeOut::new(MidiPort::Usb, false)))
+        .ok();
+
+    device.init_inputs(&mut b).await;
+    // encoder turned clockwise and button pressed
+    b.set_input(Address::new(0, 0, 0), true);
+    b.set_input(Address::new(0, 1, 0), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+
+    let sent: std::vec::Vec<u8> = match &outputs[0] {
+        OutputType::MergeOut(o) => std::iter::from_fn(|| o.pop()).collect(),
+        _ => unreachable!(),
+    };
+    // the handlers run in reverse order of the inputs
+    assert_eq!(sent, [0x90, 0x5e, 0x7f, 0xb0, 0x10, 0x01]);
+
+    // LED ring and LCD feedback of the DAW
+    device.receive(&OutputData::MidiMsgCc(MidiMsgCc {
+        channel: 0,
+        control: 0x30,
+        value: 0x15,
+    }));
+    for byte in [
+        0xf0, 0x00, 0x00, 0x66, 0x14, 0x12, 0x00, b'V', b'o', b'x', 0xf7,
+    ] {
+        device.receive_byte(byte);
+    }
+
+    let Some(Preset::Mcu(mcu)) = device.preset() else {
+        panic!("MCU preset not selected");
+    };
+    assert_eq!(
+        mcu.ring(0),
+        Some(&Ring {
+            mode: RingMode::BoostCut,
+            position: 5,
+            center: false,
+        })
+    );
+    assert_eq!(&mcu.lcd_line(0).unwrap()[..4], b"Vox ");
+}
+
+#[test]
+fn mcu_preset_leftover_inputs() {
+    let mut yaml = String::from("inputs:\n");
+    for pin in 0..10 {
+        yaml += &format!(
+            "- !Potentiometer\n  address: {{slot: {}, pin: {}}}\n  handler: !Dummy\n",
+            pin / 4,
+            pin % 4
+        );
+    }
+    yaml += "preset: !Mcu {}\n";
+
+    let device = Device::<16>::from_config_sized(&yaml).unwrap();
+    let handlers: std::vec::Vec<&str> = device.inputs().iter().map(|i| i.handler_name()).collect();
+    // eight strips and the master fader, the last one has no fader left
+    assert_eq!(handlers[..9], ["Fader"; 9]);
+    assert_eq!(handlers[9], "Dummy");
+}
+
+#[test]
+fn mcu_preset_conflicts() {
+    let mut yaml = String::from("boards:\n- slots: 8\ninputs:\n");
+    for strip in 0..8 {
+        yaml += &format!(
+            "- !Encoder\n  address: {{slot: {}, pin: {}}}\n  handler: !Dummy\n",
+            strip / 2,
+            strip % 2 * 2
+        );
+    }
+    // left over and sending the CC of the first V-Pot
+    yaml += "- !Encoder\n  address: {slot: 4}\n  handler: !MidiRel {channel: 0, control: 16}\n";
+    yaml += "preset: !Mcu {}\n";
+
+    let err = Device::<16>::from_config_sized(&yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Duplicate);
+    assert_eq!(err.input, Some(8));
+    assert_eq!(err.message, "sends the same message as input 0");
+}
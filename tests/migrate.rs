@@ -0,0 +1,129 @@
+use reset_ctrl::config::{migrate, ConfigErrorKind, CONFIG_VERSION};
+use reset_ctrl::device::Device;
+use reset_ctrl::ui::Address;
+
+/*
+ * A config of every layout version, all describing the same device.
+ */
+const FIXTURES: [(u32, &str); 2] = [
+    (1, include_str!("fixtures/v1.yaml")),
+    (2, include_str!("fixtures/v2.yaml")),
+];
+
+fn current() -> Device {
+    let (version, config) = FIXTURES[FIXTURES.len() - 1];
+    assert_eq!(
+        version, CONFIG_VERSION,
+        "fixture of the current version missing"
+    );
+    Device::from_config(config).unwrap()
+}
+
+#[test]
+fn fixtures_load_as_current() {
+    let current = current();
+    for (version, config) in FIXTURES {
+        let device =
+            Device::from_config(config).unwrap_or_else(|e| panic!("version {}: {}", version, e));
+        assert_eq!(device, current, "version {}", version);
+    }
+}
+
+#[test]
+fn fixtures_migrate_to_current() {
+    for (version, config) in FIXTURES {
+        let mut doc: serde_yaml::Value = serde_yaml::from_str(config).unwrap();
+        let changed = migrate(&mut doc).unwrap();
+        assert_eq!(changed, version < CONFIG_VERSION, "version {}", version);
+        assert_eq!(doc["version"].as_u64(), Some(CONFIG_VERSION as u64));
+    }
+}
+
+#[test]
+fn v1_mux_lines_in_order() {
+    let device = Device::from_config(FIXTURES[0].1).unwrap();
+    let addresses: Vec<Option<Address>> = device
+        .inputs()
+        .iter()
+        .map(|i| i.lines().map(|(a, _)| a))
+        .collect();
+    assert_eq!(
+        addresses,
+        [
+            Some(Address::new(0, 0, 0)),
+            Some(Address::new(0, 0, 2)),
+            Some(Address::new(0, 0, 3)),
+            None,
+            Some(Address::new(0, 1, 0)),
+        ]
+    );
+
+    // an encoder on the last pin of a slot used to span two slots
+    let yaml = "
+inputs:
+- !Button
+  handler: !Dummy
+- !Potentiometer
+  handler: !Dummy
+- !Button
+  handler: !Dummy
+- !Encoder
+  handler: !Dummy
+";
+    let err = Device::from_config(yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::OutOfRange);
+    assert_eq!(err.path, "inputs[3].address.pin");
+}
+
+#[test]
+fn v1_error_without_location() {
+    let (_, v1) = FIXTURES[0];
+    let config = v1.replace("key: 60", "key: sixty");
+    let err = Device::from_config(&config).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Syntax);
+    assert_eq!(err.path, "inputs[2].handler.key");
+    // the location would point into the migrated document
+    assert_eq!((err.line, err.column), (None, None));
+
+    // current configs keep it
+    let (_, current) = FIXTURES[FIXTURES.len() - 1];
+    let config = current.replace("key: 60", "key: sixty");
+    let err = Device::from_config(&config).unwrap_err();
+    assert_eq!(err.path, "inputs[2].handler.key");
+    assert!(err.line.is_some());
+}
+
+#[test]
+fn v1_json() {
+    let json = r#"{
+  "inputs": [
+    {"Encoder": {"handler": {"MidiRel": {"channel": 0, "control": 4}}}},
+    {"Button": {"handler": "Dummy"}}
+  ]
+}"#;
+    let device = Device::from_json(json).unwrap();
+    assert_eq!(device.inputs()[1].lines(), Some((Address::new(0, 0, 2), 1)));
+}
+
+#[test]
+fn written_with_version() {
+    let current = current();
+    let yaml = current.to_config().unwrap();
+    assert!(yaml.starts_with(&format!("version: {}\n", CONFIG_VERSION)));
+    assert_eq!(Device::from_config(&yaml).unwrap(), current);
+
+    let json = current.to_json().unwrap();
+    assert_eq!(Device::from_json(&json).unwrap(), current);
+}
+
+#[test]
+fn newer_version() {
+    let yaml = format!("version: {}\ninputs: []\n", CONFIG_VERSION + 1);
+    let err = Device::from_config(&yaml).unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Version);
+    assert_eq!(err.path, "version");
+
+    let err = Device::from_config("version: zero\ninputs: []\n").unwrap_err();
+    assert_eq!(err.kind, ConfigErrorKind::Syntax);
+    assert_eq!(err.path, "version");
+}
@@ -0,0 +1,179 @@
+use reset_ctrl::clock::ClockControl;
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{MergeOut, MidiParser, MidiPort, OutputData, OutputType};
+use reset_ctrl::time::{FakeTimer, Timer};
+use reset_ctrl::ui::backend::InMemoryBackend;
+use reset_ctrl::ui::Address;
+
+use heapless::Vec;
+
+fn notes(outputs: &[OutputType], parser: &mut MidiParser) -> std::vec::Vec<(u8, bool)> {
+    match &outputs[0] {
+        OutputType::MergeOut(o) => std::iter::from_fn(|| o.pop())
+            .filter_map(|b| match parser.parse(b) {
+                Some(OutputData::MidiMsgNote(m)) => Some((m.key, m.on)),
+                _ => None,
+            })
+            .collect(),
+        _ => unreachable!(),
+    }
+}
+
+fn outputs() -> Vec<OutputType, 1> {
+    let mut outputs = Vec::new();
+    outputs
+        .push(OutputType::MergeOut(MergeOut::new(MidiPort::Usb, false)))
+        .ok();
+    outputs
+}
+
+#[async_std::test]
+async fn chord_buttons() {
+    let yaml = "
+        inputs:
+        - !Button
+          address: {slot: 0}
+          handler: !MidiNote
+            channel: 0
+            key: 60
+            velocity: 100
+        - !Button
+          address: {slot: 1}
+          handler: !MidiNote
+            channel: 0
+            key: 62
+            velocity: 100
+        notes:
+          filter:
+            inputs: [0]
+          processor: !Chord
+            intervals: [0, 4, 7]
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+
+    let outputs = outputs();
+    let mut parser = MidiParser::new();
+
+    device.init_inputs(&mut b).await;
+    // both buttons pressed and released again
+    b.set_input(Address::new(0, 0, 0), true);
+    b.set_input(Address::new(0, 1, 0), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+    let mut on = notes(&outputs, &mut parser);
+    on.sort();
+    // only the first button plays a chord
+    assert_eq!(on, [(60, true), (62, true), (64, true), (67, true)]);
+
+    b.set_input(Address::new(0, 0, 0), false);
+    b.set_input(Address::new(0, 1, 0), false);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+    let mut off = notes(&outputs, &mut parser);
+    off.sort();
+    assert_eq!(off, [(60, false), (62, false), (64, false), (67, false)]);
+}
+
+#[async_std::test]
+async fn arpeggiator_follows_tempo() {
+    let yaml = "
+        inputs:
+        - !Button
+          address: {slot: 0}
+          handler: !MidiNote
+            channel: 0
+            key: 60
+            velocity: 100
+        clock:
+          bpm: 125
+        notes:
+          processor: !Arpeggiator
+            mode: Up
+            octaves: 2
+            division: 12
+            gate: 25
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+
+    let outputs = outputs();
+    let mut parser = MidiParser::new();
+    let mut timer = FakeTimer::new(0);
+    let mut played = std::vec::Vec::new();
+
+    device.init_inputs(&mut b).await;
+    b.set_input(Address::new(0, 0, 0), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+    assert!(notes(&outputs, &mut parser).is_empty());
+
+    // nothing plays before the clock runs
+    device.tick(&outputs, timer.now()).await;
+    assert!(notes(&outputs, &mut parser).is_empty());
+    device.clock_mut().control(ClockControl::Start);
+
+    // an 8th at 125 BPM lasts 240ms
+    while timer.now() < 500_000 {
+        device.tick(&outputs, timer.now()).await;
+        for (key, on) in notes(&outputs, &mut parser) {
+            played.push((timer.now(), key, on));
+        }
+        timer.advance(1000);
+    }
+
+    // releasing the button ends the sounding note
+    b.set_input(Address::new(0, 0, 0), false);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+    for (key, on) in notes(&outputs, &mut parser) {
+        played.push((timer.now(), key, on));
+    }
+
+    assert_eq!(
+        played,
+        [
+            (0, 60, true),
+            (60_000, 60, false),
+            (240_000, 72, true),
+            (300_000, 72, false),
+            (480_000, 60, true),
+            (500_000, 60, false),
+        ]
+    );
+}
+
+#[async_std::test]
+async fn replace_stage_ends_notes() {
+    let yaml = "
+        inputs:
+        - !Button
+          address: {slot: 0}
+          handler: !MidiNote
+            channel: 0
+            key: 60
+            velocity: 100
+        notes:
+          processor: !Chord
+            intervals: [0, 4]
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+
+    let outputs = outputs();
+    let mut parser = MidiParser::new();
+
+    device.init_inputs(&mut b).await;
+    b.set_input(Address::new(0, 0, 0), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+    assert_eq!(notes(&outputs, &mut parser), [(60, true), (64, true)]);
+
+    device.set_note_stage(None, &outputs).await;
+    let mut off = notes(&outputs, &mut parser);
+    off.sort();
+    assert_eq!(off, [(60, false), (64, false)]);
+}
@@ -0,0 +1,67 @@
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{JsonOut, OutputStats, OutputType, StdOut};
+use reset_ctrl::ui::backend::InMemoryBackend;
+use reset_ctrl::ui::Address;
+
+use heapless::Vec;
+use std::io::{self, Write};
+
+struct BrokenPipe;
+
+impl Write for BrokenPipe {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[async_std::test]
+async fn count_output_results() {
+    let yaml = "
+        inputs:
+        - !Encoder
+          address: {slot: 0}
+          handler: !MidiRel
+            channel: 0
+            control: 4
+        - !Encoder
+          address: {slot: 1}
+          handler: Dummy
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+
+    let mut outputs: Vec<OutputType, 2> = Vec::new();
+    outputs.push(OutputType::StdOut(StdOut {})).ok();
+    outputs
+        .push(OutputType::JsonOut(JsonOut::new(BrokenPipe)))
+        .ok();
+
+    device.init_inputs(&mut b).await;
+    // both encoders turned clockwise
+    b.set_input(Address::new(0, 0, 0), true);
+    b.set_input(Address::new(0, 1, 0), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+
+    // the dummy handler doesn't produce any data to send
+    assert_eq!(
+        device.output_stats(),
+        &[
+            OutputStats {
+                sent: 1,
+                dropped: 0,
+                failed: 0,
+            },
+            OutputStats {
+                sent: 0,
+                dropped: 0,
+                failed: 1,
+            },
+        ]
+    );
+}
@@ -0,0 +1,64 @@
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{read_log, JsonOut, OutputType};
+use reset_ctrl::ui::backend::InMemoryBackend;
+use reset_ctrl::ui::Address;
+
+use heapless::Vec;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+fn log_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("reset_ctrl-{}-{}.jsonl", name, std::process::id()))
+}
+
+#[async_std::test]
+async fn route_from_config() {
+    let yaml = "
+        inputs:
+        - !Encoder
+          address: {slot: 0}
+          handler: !MidiRel
+            channel: 2
+            control: 4
+        routes:
+        - output: 0
+          filter:
+            messages: [Cc]
+          channel: 9
+        - output: 1
+          filter:
+            channels: [5]
+    ";
+    let paths = [log_path("remapped"), log_path("filtered"), log_path("all")];
+
+    let mut device = Device::from_config(&yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+
+    let mut outputs: Vec<OutputType, 3> = Vec::new();
+    for path in &paths {
+        outputs
+            .push(OutputType::JsonOut(JsonOut::create(path).unwrap()))
+            .ok();
+    }
+
+    device.init_inputs(&mut b).await;
+    b.set_input(Address::new(0, 0, 0), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+
+    let logs: std::vec::Vec<_> = paths
+        .iter()
+        .map(|p| {
+            let log = read_log(BufReader::new(File::open(p).unwrap())).unwrap();
+            std::fs::remove_file(p).ok();
+            log
+        })
+        .collect();
+
+    assert_eq!(logs[0].len(), 1);
+    assert_eq!(logs[0][0].bytes, [0xb9, 4, 63]);
+    assert!(logs[1].is_empty());
+    assert_eq!(logs[2].len(), 1);
+    assert_eq!(logs[2][0].bytes, [0xb2, 4, 63]);
+}
@@ -0,0 +1,99 @@
+use reset_ctrl::output::{MidiMsgCc, OutputData, OutputError, RtpMidiOut};
+
+use async_std::net::UdpSocket;
+
+const SESSION_ADDR: &str = "127.0.0.1:15004";
+const SESSION_DATA_ADDR: &str = "127.0.0.1:15005";
+
+fn invitation(token: u32, ssrc: u32) -> Vec<u8> {
+    let mut buf = vec![0xff, 0xff, b'I', b'N'];
+    buf.extend_from_slice(&2u32.to_be_bytes());
+    buf.extend_from_slice(&token.to_be_bytes());
+    buf.extend_from_slice(&ssrc.to_be_bytes());
+    buf.extend_from_slice(b"loopback\0");
+    buf
+}
+
+fn sync(ssrc: u32, ts1: u64) -> Vec<u8> {
+    let mut buf = vec![0xff, 0xff, b'C', b'K'];
+    buf.extend_from_slice(&ssrc.to_be_bytes());
+    buf.extend_from_slice(&[0, 0, 0, 0]);
+    buf.extend_from_slice(&ts1.to_be_bytes());
+    buf.extend_from_slice(&[0; 16]);
+    buf
+}
+
+#[async_std::test]
+async fn session_to_midi() {
+    let out = RtpMidiOut::bind("reset_ctrl", SESSION_ADDR.parse().unwrap())
+        .await
+        .expect("Unable to bind session ports");
+    let peer_control = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let peer_data = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut buf = [0u8; 128];
+
+    let cc = OutputData::MidiMsgCc(MidiMsgCc {
+        channel: 2,
+        control: 4,
+        value: 0,
+    });
+
+    // data is discarded as long as nobody joined
+    assert!(!out.is_connected());
+    assert_eq!(out.run(&cc).await, Err(OutputError::Disconnected));
+
+    // invitation on control port
+    peer_control
+        .send_to(&invitation(42, 23), SESSION_ADDR)
+        .await
+        .unwrap();
+    out.handle_control().await.unwrap();
+    let (len, _) = peer_control.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[0..4], &[0xff, 0xff, b'O', b'K']);
+    assert_eq!(&buf[8..12], &42u32.to_be_bytes());
+    assert_eq!(&buf[12..16], &out.ssrc().to_be_bytes());
+    assert_eq!(&buf[16..len], b"reset_ctrl\0");
+
+    // invitation on data port
+    peer_data
+        .send_to(&invitation(42, 23), SESSION_DATA_ADDR)
+        .await
+        .unwrap();
+    out.handle_data().await.unwrap();
+    peer_data.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[0..4], &[0xff, 0xff, b'O', b'K']);
+    assert!(out.is_connected());
+
+    // clock synchronization
+    peer_data
+        .send_to(&sync(23, 1234), SESSION_DATA_ADDR)
+        .await
+        .unwrap();
+    out.handle_data().await.unwrap();
+    let (len, _) = peer_data.recv_from(&mut buf).await.unwrap();
+    assert_eq!(len, 36);
+    assert_eq!(&buf[0..4], &[0xff, 0xff, b'C', b'K']);
+    assert_eq!(buf[8], 1);
+    assert_eq!(&buf[12..20], &1234u64.to_be_bytes());
+
+    // midi
+    for value in [63, 65] {
+        out.run(&OutputData::MidiMsgCc(MidiMsgCc {
+            channel: 2,
+            control: 4,
+            value,
+        }))
+        .await
+        .unwrap();
+    }
+
+    for (sequence, value) in [(0u16, 63u8), (1, 65)] {
+        let (len, _) = peer_data.recv_from(&mut buf).await.unwrap();
+        assert_eq!(len, 16);
+        assert_eq!(buf[0], 0x80);
+        assert_eq!(buf[1], 0xe1);
+        assert_eq!(&buf[2..4], &sequence.to_be_bytes());
+        assert_eq!(&buf[8..12], &out.ssrc().to_be_bytes());
+        assert_eq!(&buf[12..16], &[3, 0xb2, 4, value]);
+    }
+}
@@ -0,0 +1,157 @@
+#![cfg(feature = "schema")]
+
+use reset_ctrl::config::schema;
+use reset_ctrl::device::Device;
+
+use jsonschema::JSONSchema;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::PathBuf;
+
+fn compiled() -> JSONSchema {
+    let schema = serde_json::to_value(schema()).unwrap();
+    JSONSchema::compile(&schema).unwrap()
+}
+
+/*
+ * JSON form of a YAML config, tags become maps with a single key and unit
+ * variants strings.
+ */
+fn json(yaml: serde_yaml::Value) -> Value {
+    match yaml {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(b) => b.into(),
+        serde_yaml::Value::Number(n) => serde_json::to_value(n).unwrap(),
+        serde_yaml::Value::String(s) => s.into(),
+        serde_yaml::Value::Sequence(s) => s.into_iter().map(json).collect(),
+        serde_yaml::Value::Mapping(m) => m
+            .into_iter()
+            .map(|(k, v)| (k.as_str().unwrap().to_string(), json(v)))
+            .collect::<Map<_, _>>()
+            .into(),
+        serde_yaml::Value::Tagged(t) => {
+            let name = t.tag.to_string().trim_start_matches('!').to_string();
+            match t.value {
+                serde_yaml::Value::Null => name.into(),
+                value => [(name, json(value))]
+                    .into_iter()
+                    .collect::<Map<_, _>>()
+                    .into(),
+            }
+        }
+    }
+}
+
+fn errors(schema: &JSONSchema, instance: &Value) -> Vec<String> {
+    match schema.validate(instance) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors
+            .map(|e| format!("{}: {}", e.instance_path, e))
+            .collect(),
+    }
+}
+
+fn examples() -> Vec<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("configs");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn example_configs_match() {
+    let schema = compiled();
+    let paths = examples();
+    assert!(!paths.is_empty());
+
+    for path in paths {
+        let config = fs::read_to_string(&path).unwrap();
+        let doc = json(serde_yaml::from_str(&config).unwrap());
+        assert_eq!(errors(&schema, &doc), Vec::<String>::new(), "{:?}", path);
+
+        // and so does the config written back
+        let device = Device::from_config(&config).unwrap();
+        let written: Value = serde_json::from_str(&device.to_json().unwrap()).unwrap();
+        assert_eq!(
+            errors(&schema, &written),
+            Vec::<String>::new(),
+            "{:?}",
+            path
+        );
+    }
+}
+
+#[test]
+fn invalid_configs_rejected() {
+    let schema = compiled();
+    // errors within an input are reported for the whole input, as it
+    // matches none of the variants
+    let check = |yaml: &str, path: &str| {
+        let doc = json(serde_yaml::from_str(yaml).unwrap());
+        let errors = errors(&schema, &doc);
+        assert!(
+            errors.iter().any(|e| e.starts_with(path)),
+            "{} not in {:?}",
+            path,
+            errors
+        );
+    };
+
+    // version 1 configs need migrating first
+    check(include_str!("fixtures/v1.yaml"), "/inputs/0");
+    check(
+        "
+inputs:
+- !Button
+  address: {slot: 0}
+  handler: !MidiNote
+    channel: 16
+    key: 60
+    velocity: 100
+",
+        "/inputs/0",
+    );
+    check(
+        "
+inputs:
+- !Potentiometer
+  address: {slot: 0, pin: 4}
+  handler: !Dummy
+",
+        "/inputs/0",
+    );
+    check(
+        "
+inputs: []
+routes: [{output: 0}, {output: 0}, {output: 0}, {output: 0}, {output: 0},
+         {output: 0}, {output: 0}, {output: 0}, {output: 0}]
+",
+        "/routes",
+    );
+    check("version: 1\ninputs: []\n", "/version");
+    check("version: 3\ninputs: []\n", "/version");
+    check("inputs: []\nclock: {bpm: 301}\n", "/clock/bpm");
+    check(
+        "
+inputs: []
+sequencer:
+  tracks: []
+  swing: 80
+",
+        "/sequencer",
+    );
+    check(
+        "
+inputs:
+- !Generator
+  waveform: Sine
+  period: 0
+  depth: 64
+  handler: !Dummy
+",
+        "/inputs/0",
+    );
+}
@@ -0,0 +1,76 @@
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{MergeOut, MidiMsgNote, MidiParser, MidiPort, OutputData, OutputType};
+use reset_ctrl::time::{FakeTimer, Timer};
+
+use heapless::Vec;
+
+fn notes(outputs: &[OutputType], parser: &mut MidiParser) -> std::vec::Vec<MidiMsgNote> {
+    match &outputs[0] {
+        OutputType::MergeOut(o) => std::iter::from_fn(|| o.pop())
+            .filter_map(|b| match parser.parse(b) {
+                Some(OutputData::MidiMsgNote(m)) => Some(m),
+                _ => None,
+            })
+            .collect(),
+        _ => unreachable!(),
+    }
+}
+
+#[async_std::test]
+async fn play_on_external_clock() {
+    let yaml = "
+        inputs: []
+        sequencer:
+          tracks:
+          - channel: 9
+            steps:
+            - note: 36
+              velocity: 100
+              gate: 50
+            - note: 42
+              velocity: 80
+              gate: 50
+          swing: 60
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut parser = MidiParser::new();
+
+    let mut outputs: Vec<OutputType, 1> = Vec::new();
+    outputs
+        .push(OutputType::MergeOut(MergeOut::new(MidiPort::Din, false)))
+        .ok();
+
+    let mut timer = FakeTimer::new(0);
+    let mut played = std::vec::Vec::new();
+
+    // external clock at 125 BPM, one clock every 20ms
+    device.receive_at(&OutputData::Start, timer.now());
+    for clock in 0..12 {
+        device.receive_at(&OutputData::Clock, timer.now());
+        for _ in 0..20 {
+            device.tick(&outputs, timer.now()).await;
+            for m in notes(&outputs, &mut parser) {
+                played.push((timer.now(), m.key, m.on));
+            }
+            timer.advance(1000);
+        }
+        if clock == 9 {
+            device.receive_at(&OutputData::Stop, timer.now());
+        }
+    }
+
+    assert_eq!(
+        played,
+        [
+            (0, 36, true),
+            // the tempo isn't known before the second clock, the first
+            // step uses the 120 BPM of the internal clock
+            (63_000, 36, false),
+            // the second step swings by a fifth of a step
+            (144_000, 42, true),
+            // stopping ends the note early
+            (200_000, 42, false),
+        ]
+    );
+}
@@ -0,0 +1,41 @@
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{MidiMsgCc, OutputData};
+use reset_ctrl::storage::{FileFlash, Storage};
+
+#[test]
+fn device_survives_restart() {
+    let yaml = "
+        inputs:
+        - !Encoder
+          address: {slot: 0}
+          handler: !MidiAbs
+            channel: 1
+            control: 4
+            value: 0
+    ";
+    let path = std::env::temp_dir().join(format!("reset_ctrl-flash-{}.bin", std::process::id()));
+    let mut buf = [0; 1024];
+
+    let mut device = Device::from_config(yaml).unwrap();
+    device.receive(&OutputData::MidiMsgCc(MidiMsgCc {
+        channel: 1,
+        control: 4,
+        value: 100,
+    }));
+
+    let mut storage = Storage::new(FileFlash::open(&path, 4096, 1024).unwrap()).unwrap();
+    storage.save(device.to_binary(&mut buf).unwrap()).unwrap();
+    drop(storage);
+
+    // power cycle
+    let mut storage = Storage::new(FileFlash::open(&path, 4096, 1024).unwrap()).unwrap();
+    let data = storage.load(&mut buf).unwrap().unwrap();
+    let restored = Device::from_binary(data).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(restored, device);
+    assert_eq!(
+        restored,
+        Device::from_config(&yaml.replace("value: 0", "value: 100")).unwrap()
+    );
+}
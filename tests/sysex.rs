@@ -0,0 +1,278 @@
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{MergeOut, MidiParser, MidiPort, OutputData, OutputType};
+use reset_ctrl::storage::{RamFlash, Storage};
+use reset_ctrl::sysex::{
+    Client, ClientError, Command, ConfigServer, NakReason, Request, Response, Transport,
+    SYSEX_PROTOCOL_VERSION,
+};
+use reset_ctrl::ui::backend::InMemoryBackend;
+use reset_ctrl::ui::{Address, InputType};
+
+use heapless::Vec;
+use std::collections::VecDeque;
+use std::io;
+
+const YAML: &str = "
+    inputs:
+    - !Encoder
+      address: {slot: 0}
+      handler: !MidiAbs
+        channel: 1
+        control: 4
+        value: 100
+    - !Button
+      address: {slot: 1}
+      handler: !MidiNote
+        channel: 0
+        key: 60
+        velocity: 100
+    - !Encoder
+      address: {slot: 3}
+      handler: !MidiRel
+        channel: 2
+        control: 3
+    - !Encoder
+      address: {slot: 4}
+      handler: !MidiRel
+        channel: 2
+        control: 4
+    - !Encoder
+      address: {slot: 5}
+      handler: !MidiRel
+        channel: 2
+        control: 5
+    - !Encoder
+      address: {slot: 6}
+      handler: !MidiRel
+        channel: 2
+        control: 6
+    - !Encoder
+      address: {slot: 7}
+      handler: !MidiRel
+        channel: 2
+        control: 7
+    routes:
+    - output: 0
+      filter:
+        messages: [Cc]
+      channel: 9
+";
+
+/*
+ * Device answering right away, responses are mixed with other MIDI data.
+ */
+struct Loopback {
+    server: ConfigServer,
+    device: Device,
+    storage: Storage<RamFlash<4096>>,
+    outputs: Vec<OutputType, 1>,
+    rx: VecDeque<u8>,
+}
+
+impl Transport for Loopback {
+    fn send(&mut self, msg: &[u8]) -> io::Result<()> {
+        for byte in msg {
+            if let Some(response) = async_std::task::block_on(self.server.receive(
+                *byte,
+                &mut self.device,
+                &mut self.storage,
+                &self.outputs,
+            )) {
+                self.rx.extend([0xf8, 0xb0, 7, 100]);
+                self.rx.extend(response.to_sysex());
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<u8> {
+        self.rx.pop_front().ok_or(io::ErrorKind::TimedOut.into())
+    }
+}
+
+fn outputs() -> Vec<OutputType, 1> {
+    let mut outputs = Vec::new();
+    outputs
+        .push(OutputType::MergeOut(MergeOut::new(MidiPort::Usb, false)))
+        .ok();
+    outputs
+}
+
+fn client(device: Device) -> Client<Loopback> {
+    Client::new(Loopback {
+        server: ConfigServer::new(),
+        device,
+        storage: Storage::new(RamFlash::new(1024)).unwrap(),
+        outputs: outputs(),
+        rx: VecDeque::new(),
+    })
+}
+
+#[test]
+fn version_and_pull() {
+    let device = Device::from_config(YAML).unwrap();
+    let expected = device.to_binary_vec().unwrap();
+    let mut client = client(device);
+
+    let version = client.version().unwrap();
+    assert_eq!(version.protocol, SYSEX_PROTOCOL_VERSION);
+    assert_eq!(version.firmware, env!("CARGO_PKG_VERSION"));
+
+    // spans several chunks
+    assert!(expected.len() > 64);
+    assert_eq!(client.pull().unwrap(), expected);
+}
+
+#[test]
+fn pull_snapshot() {
+    let expected = Device::from_config(YAML).unwrap().to_binary_vec().unwrap();
+    let mut server = ConfigServer::new();
+    let mut storage: Storage<RamFlash<4096>> = Storage::new(RamFlash::new(1024)).unwrap();
+    let mut request = |request: Request, device: &mut Device| {
+        request
+            .to_sysex()
+            .iter()
+            .find_map(|byte| {
+                async_std::task::block_on(server.receive(*byte, device, &mut storage, &[]))
+            })
+            .unwrap()
+    };
+    let mut device = Device::from_config(YAML).unwrap();
+
+    // chunks come from the snapshot taken at offset 0
+    assert_eq!(
+        request(Request::GetConfig { offset: 64 }, &mut device),
+        Response::Nak(Command::GetConfig as u8, NakReason::OutOfRange)
+    );
+    let Response::Config { len, .. } = request(Request::GetConfig { offset: 0 }, &mut device)
+    else {
+        panic!("no config");
+    };
+    assert_eq!(len as usize, expected.len());
+
+    // changed in the middle of the pull
+    let mut device = Device::new();
+    let Response::Config { data, .. } = request(Request::GetConfig { offset: 64 }, &mut device)
+    else {
+        panic!("no config");
+    };
+    assert_eq!(data, expected[64..expected.len().min(128)]);
+}
+
+#[test]
+fn push_and_save() {
+    let mut client = client(Device::new());
+    let config = Device::from_config(YAML).unwrap().to_binary_vec().unwrap();
+
+    client.push(&config).unwrap();
+    client.save().unwrap();
+    client.reboot().unwrap();
+
+    let mut loopback = client.into_inner();
+    assert_eq!(loopback.device, Device::from_config(YAML).unwrap());
+    assert!(loopback.server.reboot_requested());
+
+    let mut buf = [0; 1024];
+    let saved = loopback.storage.load(&mut buf).unwrap().unwrap();
+    assert_eq!(saved, config);
+
+    // an invalid config is refused and the device kept
+    let mut client = Client::new(loopback);
+    let invalid = Device::from_config(&YAML.replace("channel: 9", "channel: 20"));
+    assert!(invalid.is_err());
+    let mut config = config.clone();
+    let route = config.iter().rposition(|b| *b == 9).unwrap();
+    config[route] = 20;
+    assert!(matches!(
+        client.push(&config),
+        Err(ClientError::Nak(0x04, NakReason::Invalid))
+    ));
+    assert_eq!(
+        client.into_inner().device,
+        Device::from_config(YAML).unwrap()
+    );
+}
+
+#[async_std::test]
+async fn commit_ends_notes() {
+    let yaml = "
+        inputs:
+        - !Button
+          address: {slot: 0}
+          handler: !MidiNote
+            channel: 0
+            key: 60
+            velocity: 100
+        notes:
+          processor: !Chord
+            intervals: [0, 7]
+    ";
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+    let outputs = outputs();
+    let mut parser = MidiParser::new();
+    let mut notes = |outputs: &[OutputType]| -> std::vec::Vec<(u8, bool)> {
+        match &outputs[0] {
+            OutputType::MergeOut(o) => std::iter::from_fn(|| o.pop())
+                .filter_map(|b| match parser.parse(b) {
+                    Some(OutputData::MidiMsgNote(m)) => Some((m.key, m.on)),
+                    _ => None,
+                })
+                .collect(),
+            _ => unreachable!(),
+        }
+    };
+
+    device.init_inputs(&mut b).await;
+    b.set_input(Address::new(0, 0, 0), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+    assert_eq!(notes(&outputs), [(60, true), (67, true)]);
+
+    // replaced while the chord is held
+    let mut client = Client::new(Loopback {
+        server: ConfigServer::new(),
+        device,
+        storage: Storage::new(RamFlash::new(1024)).unwrap(),
+        outputs,
+        rx: VecDeque::new(),
+    });
+    let config = Device::from_config(YAML).unwrap().to_binary_vec().unwrap();
+    client.push(&config).unwrap();
+
+    let loopback = client.into_inner();
+    assert_eq!(loopback.device, Device::from_config(YAML).unwrap());
+    let mut off = notes(&loopback.outputs);
+    off.sort();
+    assert_eq!(off, [(60, false), (67, false)]);
+}
+
+#[test]
+fn single_input() {
+    let mut client = client(Device::from_config(YAML).unwrap());
+
+    let InputType::Button(mut button) = client.get_input(1).unwrap() else {
+        panic!("not a button");
+    };
+    button.address.slot = 2;
+    client.set_input(1, &InputType::Button(button)).unwrap();
+
+    let InputType::Encoder(encoder) = client.get_input(0).unwrap() else {
+        panic!("not an encoder");
+    };
+    // the same mux lines as the encoder
+    let mut button = match client.get_input(1).unwrap() {
+        InputType::Button(b) => b,
+        _ => unreachable!(),
+    };
+    assert_eq!(button.address.slot, 2);
+    button.address = encoder.address;
+    assert!(matches!(
+        client.set_input(1, &InputType::Button(button)),
+        Err(ClientError::Nak(0x06, NakReason::Invalid))
+    ));
+    assert!(matches!(
+        client.get_input(7),
+        Err(ClientError::Nak(0x05, NakReason::OutOfRange))
+    ));
+}
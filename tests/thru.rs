@@ -0,0 +1,52 @@
+use reset_ctrl::device::Device;
+use reset_ctrl::output::{MergeOut, MidiPort, OutputType};
+use reset_ctrl::ui::backend::InMemoryBackend;
+use reset_ctrl::ui::Address;
+
+use heapless::Vec;
+
+#[async_std::test]
+async fn merge_din_in_with_device_output() {
+    let yaml = "
+        inputs:
+        - !Encoder
+          address: {slot: 0}
+          handler: !MidiRel
+            channel: 0
+            control: 4
+        thru:
+        - from: Din
+          to: Din
+    ";
+
+    let mut device = Device::from_config(yaml).unwrap();
+    let mut b = InMemoryBackend::new();
+
+    let mut outputs: Vec<OutputType, 2> = Vec::new();
+    outputs
+        .push(OutputType::MergeOut(MergeOut::new(MidiPort::Din, true)))
+        .ok();
+    outputs
+        .push(OutputType::MergeOut(MergeOut::new(MidiPort::Usb, false)))
+        .ok();
+
+    device.init_inputs(&mut b).await;
+
+    // an incoming SysEx holds the encoder message back until it ended
+    for byte in [0xf0, 0x7d, 0x01] {
+        device.thru(&outputs, MidiPort::Din, byte).unwrap();
+    }
+    b.set_input(Address::new(0, 0, 0), true);
+    device.update(&mut b).await;
+    device.run_handler(&outputs).await;
+    device.thru(&outputs, MidiPort::Din, 0xf7).unwrap();
+
+    let merged = |ot: &OutputType| match ot {
+        OutputType::MergeOut(o) => std::iter::from_fn(|| o.pop()).collect::<std::vec::Vec<u8>>(),
+        _ => unreachable!(),
+    };
+
+    assert_eq!(merged(&outputs[0]), [0xf0, 0x7d, 0x01, 0xf7, 0xb0, 4, 63]);
+    // no thru from DIN to USB configured
+    assert_eq!(merged(&outputs[1]), [0xb0, 4, 63]);
+}